@@ -0,0 +1,49 @@
+#![no_std]
+use soroban_sdk::{contract, contractimpl, contracttype, token, Address, Bytes, Env};
+
+#[contracttype]
+#[derive(Clone)]
+pub enum DataKey {
+    // Pool the loan is paid back to.
+    Pool,
+    // Only flash loans initiated by the owner are accepted.
+    Owner,
+    // Whether the fee is paid back together with the principal.
+    RepayFee,
+}
+
+#[contract]
+pub struct MockFlashLoanReceiver;
+
+#[contractimpl]
+impl MockFlashLoanReceiver {
+    pub fn initialize(e: Env, pool: Address, owner: Address, repay_fee: bool) {
+        e.storage().instance().set(&DataKey::Pool, &pool);
+        e.storage().instance().set(&DataKey::Owner, &owner);
+        e.storage().instance().set(&DataKey::RepayFee, &repay_fee);
+    }
+
+    pub fn exec_op(
+        e: Env,
+        initiator: Address,
+        token: Address,
+        amount: i128,
+        fee: i128,
+        _params: Bytes,
+    ) {
+        let owner: Address = e.storage().instance().get(&DataKey::Owner).unwrap();
+        assert!(initiator == owner, "Unknown initiator!");
+
+        let pool: Address = e.storage().instance().get(&DataKey::Pool).unwrap();
+        let repay_fee: bool = e.storage().instance().get(&DataKey::RepayFee).unwrap();
+
+        let client = token::Client::new(&e, &token);
+        assert!(
+            client.balance(&e.current_contract_address()) >= amount,
+            "Loan was not received!"
+        );
+
+        let repay_amount = if repay_fee { amount + fee } else { amount };
+        client.transfer(&e.current_contract_address(), &pool, &repay_amount);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::contract::Error;
+use crate::events;
+use crate::storage_types::{
+    Auction, AuctionConfig, LoansDataKey, POSITIONS_BUMP_AMOUNT, POSITIONS_LIFETIME_THRESHOLD,
+};
+use soroban_sdk::Env;
+
+// Largest allowed discount, 5_000_000 = 50%. Collateral can't be given away for free.
+pub(crate) const MAX_AUCTION_DISCOUNT: i128 = 5_000_000;
+
+pub fn validate_config(config: &AuctionConfig) -> Result<(), Error> {
+    if config.start_discount < 0
+        || config.start_discount > config.max_discount
+        || config.max_discount > MAX_AUCTION_DISCOUNT
+        || config.duration == 0
+    {
+        return Err(Error::InvalidAuctionConfig);
+    }
+    Ok(())
+}
+
+/// Turn auctions on with the given config, or off with `None`. Turning them off ends every running
+/// auction, so they don't carry their old discounts over if auctions are turned on again.
+pub fn write_config(e: &Env, config: Option<&AuctionConfig>) -> Result<(), Error> {
+    let key = LoansDataKey::AuctionConfig;
+
+    match config {
+        Some(config) => {
+            validate_config(config)?;
+            e.storage().persistent().set(&key, config);
+            e.storage().persistent().extend_ttl(
+                &key,
+                POSITIONS_LIFETIME_THRESHOLD,
+                POSITIONS_BUMP_AMOUNT,
+            );
+        }
+        None => {
+            e.storage().persistent().remove(&key);
+
+            let disabled_key = LoansDataKey::AuctionsDisabledLedger;
+            e.storage()
+                .persistent()
+                .set(&disabled_key, &e.ledger().sequence());
+            e.storage().persistent().extend_ttl(
+                &disabled_key,
+                POSITIONS_LIFETIME_THRESHOLD,
+                POSITIONS_BUMP_AMOUNT,
+            );
+        }
+    }
+    Ok(())
+}
+
+fn read_disabled_ledger(e: &Env) -> Option<u32> {
+    let key = LoansDataKey::AuctionsDisabledLedger;
+
+    let ledger: Option<u32> = e.storage().persistent().get(&key);
+    if ledger.is_some() {
+        e.storage().persistent().extend_ttl(
+            &key,
+            POSITIONS_LIFETIME_THRESHOLD,
+            POSITIONS_BUMP_AMOUNT,
+        );
+    }
+    ledger
+}
+
+pub fn read_config(e: &Env) -> Option<AuctionConfig> {
+    let key = LoansDataKey::AuctionConfig;
+
+    let config: Option<AuctionConfig> = e.storage().persistent().get(&key);
+    if config.is_some() {
+        e.storage().persistent().extend_ttl(
+            &key,
+            POSITIONS_LIFETIME_THRESHOLD,
+            POSITIONS_BUMP_AMOUNT,
+        );
+    }
+    config
+}
+
+pub fn start(e: &Env, loan_id: u64) -> Result<Auction, Error> {
+    if read(e, loan_id).is_some() {
+        return Err(Error::AuctionAlreadyStarted);
+    }
+
+    let key = LoansDataKey::Auction(loan_id);
+
+    let auction = Auction {
+        loan_id,
+        start_ledger: e.ledger().sequence(),
+    };
+    e.storage().persistent().set(&key, &auction);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
+
+    events::auction_started(e, auction.clone());
+    Ok(auction)
+}
+
+/// Running auction of a loan. Auctions started before auctions were last turned off are ended.
+pub fn read(e: &Env, loan_id: u64) -> Option<Auction> {
+    let key = LoansDataKey::Auction(loan_id);
+
+    let auction: Auction = e.storage().persistent().get(&key)?;
+    if read_disabled_ledger(e).is_some_and(|ledger| auction.start_ledger <= ledger) {
+        return None;
+    }
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
+    Some(auction)
+}
+
+/// End the auction of a loan if it has one.
+pub fn remove(e: &Env, loan_id: u64) {
+    if let Some(auction) = read(e, loan_id) {
+        e.storage()
+            .persistent()
+            .remove(&LoansDataKey::Auction(loan_id));
+        events::auction_ended(e, auction);
+    }
+}
+
+/// Discount on the collateral at the current ledger.
+pub fn discount(e: &Env, config: &AuctionConfig, auction: &Auction) -> Result<i128, Error> {
+    let elapsed = e.ledger().sequence().saturating_sub(auction.start_ledger);
+    if elapsed >= config.duration {
+        return Ok(config.max_discount);
+    }
+
+    config
+        .max_discount
+        .checked_sub(config.start_discount)
+        .and_then(|range| range.checked_mul(elapsed as i128))
+        .and_then(|growth| growth.checked_div(config.duration as i128))
+        .and_then(|growth| growth.checked_add(config.start_discount))
+        .ok_or(Error::OverOrUnderFlow)
+}
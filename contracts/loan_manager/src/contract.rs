@@ -1,23 +1,29 @@
-use crate::oracle::{self, Asset};
+use crate::auction;
+use crate::dto::{InterestRateModel, LiquidationPreview, PoolStatus};
+use crate::events::{self, ManagerParam};
+use crate::oracle::{self, PriceSide};
 use crate::positions;
 use crate::storage_types::{
-    Loan, LoansDataKey, POSITIONS_BUMP_AMOUNT, POSITIONS_LIFETIME_THRESHOLD,
+    Auction, AuctionConfig, Collateral, GovernanceAction, Loan, LoansDataKey, OracleAsset,
+    PendingAction, PricingMode,
 };
+use crate::timelock;
 
 use soroban_sdk::{
-    contract, contracterror, contractimpl, symbol_short, vec, Address, BytesN, Env, String, Symbol,
-    Vec,
+    contract, contracterror, contractimpl, token, vec, Address, BytesN, Env, Map, Symbol, Vec,
 };
 
-mod loan_pool {
+pub(crate) mod loan_pool {
     soroban_sdk::contractimport!(
         file = "../../target/wasm32-unknown-unknown/release/loan_pool.wasm"
     );
 }
 
-// This is the real address of the Reflector Oracle in Testnet.
-// We use the same address to mock it for testing.
-const REFLECTOR_ADDRESS: &str = "CCYOZJCOPG34LLQQ7N24YXBM7LL62R7ONMZ3G6WZAAYPB5OYKOMJRN63";
+const DECIMAL: i128 = 10_000_000;
+// Health factor is defined as so: 1.0 = 10000000_i128
+pub(crate) const HEALTH_FACTOR_THRESHOLD: i128 = 10000000;
+// Every loan of a page is priced, which is expensive.
+const MAX_LOANS_PER_PAGE: u32 = 10;
 
 #[contracterror]
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
@@ -29,6 +35,116 @@ pub enum Error {
     OverOrUnderFlow = 4,
     NoLastPrice = 5,
     AddressNotFound = 6,
+    LoanNotInsolvent = 7,
+    TimelockDelayNotFound = 8,
+    InvalidTimelockDelay = 9,
+    ActionNotFound = 10,
+    TimelockNotReady = 11,
+    ActionExpired = 12,
+    NoCollateral = 13,
+    InvalidCollateral = 14,
+    CollateralNotFound = 15,
+    InsufficientCollateral = 16,
+    LoanNotFound = 17,
+    HealthFactorTooLow = 18,
+    InvalidBorrowAmount = 19,
+    OracleNotFound = 20,
+    StalePrice = 21,
+    InvalidPrice = 22,
+    InvalidMaxPriceAge = 23,
+    InvalidOracles = 24,
+    InvalidMaxPriceDeviation = 25,
+    PriceDeviation = 26,
+    InvalidPricingMode = 27,
+    PoolNotFound = 28,
+    LoanNotLiquidatable = 29,
+    LiquidationTooLarge = 30,
+    AuctionsDisabled = 31,
+    AuctionNotFound = 32,
+    AuctionAlreadyStarted = 33,
+    InvalidAuctionConfig = 34,
+    LoanStillLiquidatable = 35,
+    AlreadyMigrated = 36,
+    InvalidInterestRateModel = 37,
+    InvalidReserveFactor = 38,
+    InvalidCap = 39,
+    InvalidFlashLoanFee = 40,
+    InvalidLiquidationParams = 41,
+    UpgradeAlreadyQueued = 42,
+    InvalidLiquidationAmount = 43,
+}
+
+// Result of quote_liquidation with what liquidate needs for updating the loan.
+struct LiquidationQuote {
+    preview: LiquidationPreview,
+    collateral_index: u32,
+    remaining_collateral: i128,
+}
+
+// Pool data and prices for pricing a page of loans. Loans often share pools, and every pool and
+// oracle call is expensive.
+struct PoolCache {
+    currencies: Map<Address, loan_pool::Currency>,
+    collateral_factors: Map<Address, i128>,
+    accruals: Map<Address, i128>,
+    collateral_prices: Map<Address, i128>,
+    debt_prices: Map<Address, i128>,
+}
+
+impl PoolCache {
+    fn new(e: &Env) -> Self {
+        PoolCache {
+            currencies: Map::new(e),
+            collateral_factors: Map::new(e),
+            accruals: Map::new(e),
+            collateral_prices: Map::new(e),
+            debt_prices: Map::new(e),
+        }
+    }
+
+    fn currency(&mut self, e: &Env, pool: &Address) -> loan_pool::Currency {
+        if let Some(currency) = self.currencies.get(pool.clone()) {
+            return currency;
+        }
+        let currency = loan_pool::Client::new(e, pool).get_currency();
+        self.currencies.set(pool.clone(), currency.clone());
+        currency
+    }
+
+    fn collateral_factor(&mut self, e: &Env, pool: &Address) -> i128 {
+        if let Some(factor) = self.collateral_factors.get(pool.clone()) {
+            return factor;
+        }
+        let factor = loan_pool::Client::new(e, pool).get_collateral_factor();
+        self.collateral_factors.set(pool.clone(), factor);
+        factor
+    }
+
+    fn accrual(&mut self, e: &Env, pool: &Address) -> i128 {
+        if let Some(accrual) = self.accruals.get(pool.clone()) {
+            return accrual;
+        }
+        let accrual = loan_pool::Client::new(e, pool).get_projected_accrual();
+        self.accruals.set(pool.clone(), accrual);
+        accrual
+    }
+
+    fn price(&mut self, e: &Env, pool: &Address, side: PriceSide) -> Result<i128, Error> {
+        let cached = match side {
+            PriceSide::Collateral => self.collateral_prices.get(pool.clone()),
+            PriceSide::Debt => self.debt_prices.get(pool.clone()),
+        };
+        if let Some(price) = cached {
+            return Ok(price);
+        }
+        let ticker = self.currency(e, pool).ticker;
+        let price = oracle::read_price_for(e, pool, ticker, side)?;
+        match side {
+            PriceSide::Collateral => self.collateral_prices.set(pool.clone(), price),
+            PriceSide::Debt => self.debt_prices.set(pool.clone(), price),
+        }
+        Ok(price)
+    }
 }
 
 #[contract]
@@ -37,18 +153,64 @@ struct LoanManager;
 #[allow(dead_code)]
 #[contractimpl]
 impl LoanManager {
-    /// Set the admin that's allowed to upgrade the wasm.
-    pub fn initialize(e: Env, admin: Address) -> Result<(), Error> {
+    /// Set the admin that's allowed to upgrade the wasm, the primary price oracle, and the delay in
+    /// seconds before the admin's queued actions can be executed.
+    pub fn initialize(
+        e: Env,
+        admin: Address,
+        oracle: Address,
+        timelock_delay: u64,
+    ) -> Result<(), Error> {
         if e.storage().persistent().has(&LoansDataKey::Admin) {
             return Err(Error::AlreadyInitialized);
         }
+        timelock::validate_delay(timelock_delay)?;
 
         e.storage().persistent().set(&LoansDataKey::Admin, &admin);
-        e.events()
-            .publish((symbol_short!("admin"), symbol_short!("added")), admin);
+        oracle::write_oracles(&e, &vec![&e, oracle.clone()])?;
+        timelock::write_delay(&e, timelock_delay);
+        events::param_changed(&e, ManagerParam::Admin(admin));
+        events::param_changed(&e, ManagerParam::Oracles(vec![&e, oracle]));
+        events::param_changed(&e, ManagerParam::TimelockDelay(timelock_delay));
+        Ok(())
+    }
+
+    /// Write the settings added since the first deployment to a loan manager upgraded from it. Such
+    /// a manager already has an admin, so `initialize` can't be used, and nothing can be queued
+    /// before it has a timelock delay. Can only be called once.
+    pub fn migrate(e: Env, oracle: Address, timelock_delay: u64) -> Result<(), Error> {
+        let admin: Address = e
+            .storage()
+            .persistent()
+            .get(&LoansDataKey::Admin)
+            .ok_or(Error::AdminNotFound)?;
+        admin.require_auth();
+
+        if e.storage().persistent().has(&LoansDataKey::TimelockDelay) {
+            return Err(Error::AlreadyMigrated);
+        }
+        timelock::validate_delay(timelock_delay)?;
+
+        oracle::write_oracles(&e, &vec![&e, oracle.clone()])?;
+        timelock::write_delay(&e, timelock_delay);
+        events::param_changed(&e, ManagerParam::Oracles(vec![&e, oracle]));
+        events::param_changed(&e, ManagerParam::TimelockDelay(timelock_delay));
         Ok(())
     }
 
+    /// Move the loan a borrower had in the first deployment, which was stored by their address, under
+    /// a loan ID like the loans created since. Returns the new ID.
+    pub fn migrate_loan(e: Env, borrower: Address) -> Result<u64, Error> {
+        let admin: Address = e
+            .storage()
+            .persistent()
+            .get(&LoansDataKey::Admin)
+            .ok_or(Error::AdminNotFound)?;
+        admin.require_auth();
+
+        positions::migrate_legacy_loan(&e, &borrower).ok_or(Error::LoanNotFound)
+    }
+
     /// Deploy a loan_pool contract, and initialize it.
     pub fn deploy_pool(
         e: Env,
@@ -57,6 +219,8 @@ impl LoanManager {
         token_address: Address,
         ticker: Symbol,
         liquidation_threshold: i128,
+        interest_rate_model: InterestRateModel,
+        reserve_factor: i128,
     ) -> Result<Address, Error> {
         // Deploy the contract using the uploaded Wasm with given hash.
         let deployed_address: Address = e
@@ -79,14 +243,18 @@ impl LoanManager {
             e.storage()
                 .persistent()
                 .set(&LoansDataKey::PoolAddresses, &pool_addresses);
-            e.events().publish(
-                (LoansDataKey::PoolAddresses, symbol_short!("added")),
-                &deployed_address,
-            );
+            events::param_changed(&e, ManagerParam::PoolAdded(deployed_address.clone()));
 
             let pool_client = loan_pool::Client::new(&e, &deployed_address);
 
+            oracle::write_oracle_asset(
+                &e,
+                &deployed_address,
+                &oracle::ticker_asset(ticker.clone()),
+            );
+
             let currency = loan_pool::Currency {
+                decimals: token::Client::new(&e, &token_address).decimals(),
                 token_address,
                 ticker,
             };
@@ -94,6 +262,8 @@ impl LoanManager {
                 &e.current_contract_address(),
                 &currency,
                 &liquidation_threshold,
+                &interest_rate_model.into(),
+                &reserve_factor,
             );
 
             // Return the contract ID of the deployed contract
@@ -103,76 +273,205 @@ impl LoanManager {
         }
     }
 
-    /// Upgrade deployed loan pools and the loan manager WASM.
-    pub fn upgrade(
-        e: Env,
-        new_manager_wasm_hash: BytesN<32>,
-        new_pool_wasm_hash: BytesN<32>,
-    ) -> Result<(), Error> {
+    /// Queue an admin action. It can be executed once the timelock delay has passed, which gives
+    /// users time to exit before e.g. an upgrade or a change of risk parameters. Returns the id of the action.
+    pub fn queue_action(e: Env, action: GovernanceAction) -> Result<u64, Error> {
         let admin: Address = e
             .storage()
             .persistent()
             .get(&LoansDataKey::Admin)
             .ok_or(Error::AdminNotFound)?;
         admin.require_auth();
-        e.storage()
+
+        match action {
+            // A newer upgrade has to replace the queued one, so that they can't land out of order.
+            GovernanceAction::Upgrade(..) => {
+                if timelock::pending_actions(&e)
+                    .iter()
+                    .any(|pending| matches!(pending.action, GovernanceAction::Upgrade(..)))
+                {
+                    return Err(Error::UpgradeAlreadyQueued);
+                }
+            }
+            GovernanceAction::SetInterestModel(ref pool, ref model) => {
+                Self::validate_pool_param(
+                    &e,
+                    pool,
+                    loan_pool::PoolParam::InterestRateModel(model.clone().into()),
+                    Error::InvalidInterestRateModel,
+                )?;
+            }
+            GovernanceAction::SetReserveFactor(ref pool, reserve_factor) => {
+                Self::validate_pool_param(
+                    &e,
+                    pool,
+                    loan_pool::PoolParam::ReserveFactor(reserve_factor),
+                    Error::InvalidReserveFactor,
+                )?;
+            }
+            GovernanceAction::SetPoolCaps(ref pool, supply_cap, borrow_cap) => {
+                for cap in [
+                    loan_pool::PoolParam::SupplyCap(supply_cap),
+                    loan_pool::PoolParam::BorrowCap(borrow_cap),
+                ] {
+                    Self::validate_pool_param(&e, pool, cap, Error::InvalidCap)?;
+                }
+            }
+            GovernanceAction::SetFlashLoanFee(ref pool, fee) => {
+                Self::validate_pool_param(
+                    &e,
+                    pool,
+                    loan_pool::PoolParam::FlashLoanFee(fee),
+                    Error::InvalidFlashLoanFee,
+                )?;
+            }
+            GovernanceAction::SetLiquidationParams(ref pool, ref params) => {
+                Self::validate_pool_param(
+                    &e,
+                    pool,
+                    loan_pool::PoolParam::LiquidationParams(params.clone().into()),
+                    Error::InvalidLiquidationParams,
+                )?;
+            }
+            GovernanceAction::SetTimelockDelay(delay) => timelock::validate_delay(delay)?,
+            GovernanceAction::SetMaxPriceAge(ref pool, max_age) => {
+                Self::require_pool(&e, pool)?;
+                oracle::validate_max_price_age(max_age)?;
+            }
+            GovernanceAction::SetOracles(ref oracles) => oracle::validate_oracles(&e, oracles)?,
+            GovernanceAction::SetOracle(ref primary) => {
+                oracle::with_primary_oracle(&e, primary.clone())?;
+            }
+            GovernanceAction::SetMaxPriceDeviation(max_deviation) => {
+                oracle::validate_max_price_deviation(max_deviation)?
+            }
+            GovernanceAction::SetPricingMode(ref pool, ref mode) => {
+                Self::require_pool(&e, pool)?;
+                oracle::validate_pricing_mode(mode)?;
+            }
+            GovernanceAction::SetAuctionConfig(ref config) => auction::validate_config(config)?,
+            GovernanceAction::SetOracleAsset(ref pool, _) => Self::require_pool(&e, pool)?,
+            GovernanceAction::DisableAuctions => {
+                auction::read_config(&e).ok_or(Error::AuctionsDisabled)?;
+            }
+        }
+        let pending = timelock::queue(&e, action)?;
+        events::action_queued(&e, pending.clone());
+        Ok(pending.id)
+    }
+
+    /// Execute a queued action whose delay has passed.
+    pub fn execute_action(e: Env, id: u64) -> Result<(), Error> {
+        let admin: Address = e
+            .storage()
             .persistent()
-            .get(&LoansDataKey::PoolAddresses)
-            .unwrap_or(vec![&e])
-            .iter()
-            .for_each(|pool| {
-                let pool_client = loan_pool::Client::new(&e, &pool);
-                pool_client.upgrade(&new_pool_wasm_hash);
-            });
+            .get(&LoansDataKey::Admin)
+            .ok_or(Error::AdminNotFound)?;
+        admin.require_auth();
 
-        e.deployer()
-            .update_current_contract_wasm(new_manager_wasm_hash);
+        let pending = timelock::dequeue_ready(&e, id)?;
+        events::action_executed(&e, pending.clone());
+        Self::execute(&e, pending.action)
+    }
+
+    /// Remove a queued action without executing it.
+    pub fn cancel_action(e: Env, id: u64) -> Result<(), Error> {
+        let admin: Address = e
+            .storage()
+            .persistent()
+            .get(&LoansDataKey::Admin)
+            .ok_or(Error::AdminNotFound)?;
+        admin.require_auth();
+
+        let pending = timelock::remove(&e, id)?;
+        events::action_cancelled(&e, pending);
+        Ok(())
+    }
+
+    pub fn get_pending_actions(e: Env) -> Vec<PendingAction> {
+        timelock::pending_actions(&e)
+    }
+
+    pub fn get_timelock_delay(e: Env) -> Result<u64, Error> {
+        timelock::read_delay(&e)
+    }
+
+    /// Price oracles in priority order.
+    pub fn get_oracles(e: Env) -> Result<Vec<Address>, Error> {
+        oracle::read_oracles(&e)
+    }
+
+    /// The primary price oracle. Kept for integrations from before the fallback oracles, see
+    /// `get_oracles`.
+    pub fn get_oracle(e: Env) -> Result<Address, Error> {
+        oracle::read_primary_oracle(&e)
+    }
+
+    pub fn get_max_price_deviation(e: Env) -> i128 {
+        oracle::read_max_price_deviation(&e)
+    }
+
+    /// Freeze, pause or deprecate a loan pool. Takes effect immediately so it can be used in an incident.
+    pub fn set_pool_status(e: Env, pool_address: Address, status: PoolStatus) -> Result<(), Error> {
+        let admin: Address = e
+            .storage()
+            .persistent()
+            .get(&LoansDataKey::Admin)
+            .ok_or(Error::AdminNotFound)?;
+        admin.require_auth();
 
+        let pool_client = loan_pool::Client::new(&e, &pool_address);
+        pool_client.set_status(&status.into());
         Ok(())
     }
 
-    /// Initialize a new loan
+    /// Withdraw protocol reserves from a loan pool. Returns the reserves left in the pool.
+    pub fn claim_reserves(
+        e: Env,
+        pool_address: Address,
+        to: Address,
+        amount: i128,
+    ) -> Result<i128, Error> {
+        let admin: Address = e
+            .storage()
+            .persistent()
+            .get(&LoansDataKey::Admin)
+            .ok_or(Error::AdminNotFound)?;
+        admin.require_auth();
+
+        let pool_client = loan_pool::Client::new(&e, &pool_address);
+        Ok(pool_client.claim_reserves(&to, &amount))
+    }
+
+    /// Initialize a new loan backed by one or more collateral positions, each in a different pool.
+    /// A user can have several loans open at once. Returns the ID of the new loan.
     pub fn create_loan(
         e: Env,
         user: Address,
         borrowed: i128,
         borrowed_from: Address,
-        collateral: i128,
-        collateral_from: Address,
-    ) -> Result<(), Error> {
+        collateral: Vec<Collateral>,
+    ) -> Result<u64, Error> {
         user.require_auth();
 
-        if e.storage()
-            .persistent()
-            .has(&LoansDataKey::Loan(user.clone()))
-        {
-            return Err(Error::LoanAlreadyExists);
-        }
+        Self::require_pool(&e, &borrowed_from)?;
+        Self::validate_collateral(&e, &collateral)?;
 
-        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_from);
         let borrow_pool_client = loan_pool::Client::new(&e, &borrowed_from);
 
-        let token_currency = borrow_pool_client.get_currency();
-        let collateral_currency = collateral_pool_client.get_currency();
-        let health_factor: i128 = Self::calculate_health_factor(
-            &e,
-            token_currency.ticker,
-            borrowed,
-            collateral_currency.ticker,
-            collateral,
-            collateral_from.clone(),
-        )?;
+        let health_factor: i128 =
+            Self::calculate_health_factor(&e, borrowed_from.clone(), borrowed, collateral.clone())?;
 
         // Health factor has to be over 1.2 for the loan to be initialized.
-        // Health factor is defined as so: 1.0 = 10000000_i128
-        const HEALTH_FACTOR_THRESHOLD: i128 = 10000000;
         assert!(
             health_factor > HEALTH_FACTOR_THRESHOLD,
             "Health factor must be over {HEALTH_FACTOR_THRESHOLD} to create a new loan!"
         );
 
         // Deposit collateral
-        let collateral_amount = collateral_pool_client.deposit_collateral(&user, &collateral);
+        for Collateral { pool, amount } in collateral.iter() {
+            loan_pool::Client::new(&e, &pool).deposit_collateral(&user, &amount);
+        }
 
         // Borrow the funds
         let borrowed_amount = borrow_pool_client.borrow(&user, &borrowed);
@@ -180,194 +479,180 @@ impl LoanManager {
         let unpaid_interest = 0;
 
         let loan = Loan {
+            // Assigned when the loan is stored.
+            id: 0,
             borrower: user.clone(),
             borrowed_amount,
             borrowed_from,
-            collateral_amount,
-            collateral_from,
+            collateral,
             health_factor,
             unpaid_interest,
             last_accrual: borrow_pool_client.get_accrual(),
         };
 
-        positions::init_loan(&e, user.clone(), loan);
-
-        Ok(())
+        Ok(positions::init_loan(&e, loan))
     }
 
-    pub fn add_interest(e: &Env, user: Address) -> Result<(), Error> {
-        const DECIMAL: i128 = 10000000;
+    pub fn add_interest(e: &Env, loan_id: u64) -> Result<(), Error> {
         let Loan {
+            id,
             borrower,
             borrowed_from,
-            collateral_amount,
+            collateral,
             borrowed_amount,
-            collateral_from,
             health_factor: _,
             unpaid_interest,
             last_accrual,
-        } = Self::get_loan(e, user.clone());
+        } = Self::get_loan(e, loan_id)?;
 
         let borrow_pool_client = loan_pool::Client::new(e, &borrowed_from);
-        let collateral_pool_client = loan_pool::Client::new(e, &collateral_from);
-
-        let loan_pool::Currency {
-            ticker: token_ticker,
-            ..
-        } = borrow_pool_client.get_currency();
-
-        let loan_pool::Currency {
-            ticker: token_collateral_ticker,
-            ..
-        } = collateral_pool_client.get_currency();
-
-        borrow_pool_client.add_interest_to_accrual();
-        let current_accrual = borrow_pool_client.get_accrual();
-        let interest_since_update_multiplier = current_accrual
-            .checked_mul(DECIMAL)
-            .ok_or(Error::OverOrUnderFlow)?
-            .checked_div(last_accrual)
-            .ok_or(Error::OverOrUnderFlow)?;
 
-        let new_borrowed_amount = borrowed_amount
-            .checked_mul(interest_since_update_multiplier)
-            .ok_or(Error::OverOrUnderFlow)?
-            .checked_div(DECIMAL)
-            .ok_or(Error::OverOrUnderFlow)?;
+        let (new_borrowed_amount, new_unpaid_interest, current_accrual) = Self::accrue_interest(
+            &borrow_pool_client,
+            borrowed_amount,
+            unpaid_interest,
+            last_accrual,
+        )?;
 
         let new_health_factor = Self::calculate_health_factor(
             e,
-            token_ticker,
+            borrowed_from.clone(),
             new_borrowed_amount,
-            token_collateral_ticker,
-            collateral_amount,
-            collateral_from.clone(),
+            collateral.clone(),
         )?;
 
-        let borrow_change = new_borrowed_amount
-            .checked_sub(borrowed_amount)
-            .ok_or(Error::OverOrUnderFlow)?;
-        let new_unpaid_interest = unpaid_interest
-            .checked_add(borrow_change)
-            .ok_or(Error::OverOrUnderFlow)?;
-
         let updated_loan = Loan {
+            id,
             borrower,
             borrowed_from,
-            collateral_amount,
+            collateral,
             borrowed_amount: new_borrowed_amount,
-            collateral_from,
             health_factor: new_health_factor,
             unpaid_interest: new_unpaid_interest,
             last_accrual: current_accrual,
         };
 
-        let key = (Symbol::new(e, "Loan"), user.clone());
-
-        e.storage().persistent().set(&key, &updated_loan);
-        e.storage().persistent().extend_ttl(
-            &key,
-            POSITIONS_LIFETIME_THRESHOLD,
-            POSITIONS_BUMP_AMOUNT,
-        );
-        e.events()
-            .publish((key, symbol_short!("updated")), updated_loan);
+        positions::write_positions(e, &updated_loan);
+        events::loan_updated(e, updated_loan);
 
         Ok(())
     }
 
+    /// Health factor of a loan, summing up the value of all of its collateral.
     pub fn calculate_health_factor(
         e: &Env,
-        token_ticker: Symbol,
-        token_amount: i128,
-        token_collateral_ticker: Symbol,
-        token_collateral_amount: i128,
-        token_collateral_address: Address,
+        borrowed_from: Address,
+        borrowed_amount: i128,
+        collateral: Vec<Collateral>,
     ) -> Result<i128, Error> {
-        const DECIMAL_TO_INT_MULTIPLIER: i128 = 10000000;
-        let reflector_address = Address::from_string(&String::from_str(e, REFLECTOR_ADDRESS));
-        let reflector_contract = oracle::Client::new(e, &reflector_address);
+        let mut collateral_value: i128 = 0;
+        for Collateral { pool, amount } in collateral.iter() {
+            let (price, decimals, collateral_factor) = Self::collateral_price_and_factor(e, &pool)?;
+            collateral_value = collateral_value
+                .checked_add(Self::collateral_value(
+                    price,
+                    amount,
+                    decimals,
+                    collateral_factor,
+                )?)
+                .ok_or(Error::OverOrUnderFlow)?;
+        }
 
-        // get the price and calculate the value of the collateral
-        let collateral_asset = Asset::Other(token_collateral_ticker);
+        let loan_pool::Currency {
+            ticker, decimals, ..
+        } = loan_pool::Client::new(e, &borrowed_from).get_currency();
+        let borrowed_price = oracle::read_price_for(e, &borrowed_from, ticker, PriceSide::Debt)?;
+        Self::health_factor(
+            collateral_value,
+            Self::value(borrowed_price, borrowed_amount, decimals)?,
+        )
+    }
 
-        let collateral_pool_client = loan_pool::Client::new(e, &token_collateral_address);
-        let collateral_factor = collateral_pool_client.get_collateral_factor();
+    pub fn get_loan(e: &Env, loan_id: u64) -> Result<Loan, Error> {
+        positions::read_positions(e, loan_id).ok_or(Error::LoanNotFound)
+    }
 
-        let collateral_asset_price = reflector_contract
-            .lastprice(&collateral_asset)
-            .ok_or(Error::NoLastPrice)?;
-        let collateral_value = collateral_asset_price
-            .price
-            .checked_mul(token_collateral_amount)
-            .ok_or(Error::OverOrUnderFlow)?
-            .checked_mul(collateral_factor)
-            .ok_or(Error::OverOrUnderFlow)?
-            .checked_div(DECIMAL_TO_INT_MULTIPLIER)
-            .ok_or(Error::OverOrUnderFlow)?;
+    /// IDs of the open loans of a user.
+    pub fn get_user_loans(e: &Env, user: Address) -> Vec<u64> {
+        positions::read_user_loans(e, &user)
+    }
 
-        // get the price and calculate the value of the borrowed asset
-        let borrowed_asset = Asset::Other(token_ticker);
-        let asset_price = reflector_contract
-            .lastprice(&borrowed_asset)
-            .ok_or(Error::NoLastPrice)?;
-        let borrowed_value = asset_price
-            .price
-            .checked_mul(token_amount)
-            .ok_or(Error::OverOrUnderFlow)?;
+    pub fn get_active_loan_count(e: &Env) -> u32 {
+        positions::read_active_loan_count(e)
+    }
 
-        let health_factor = collateral_value
-            .checked_mul(DECIMAL_TO_INT_MULTIPLIER)
-            .ok_or(Error::OverOrUnderFlow)?
-            .checked_div(borrowed_value)
-            .ok_or(Error::OverOrUnderFlow)?;
-        Ok(health_factor)
+    /// Loans with debt from position `cursor` onwards, with interest and health factors as of now.
+    /// At most `MAX_LOANS_PER_PAGE` loans are returned. A repaid or closed loan is replaced by the
+    /// last one in the list, so a loan can move to an earlier position between calls.
+    pub fn get_loans(e: &Env, cursor: u32, limit: u32) -> Result<Vec<Loan>, Error> {
+        let end = cursor
+            .saturating_add(limit.min(MAX_LOANS_PER_PAGE))
+            .min(positions::read_active_loan_count(e));
+
+        let mut cache = PoolCache::new(e);
+        let mut loans = Vec::new(e);
+        for position in cursor..end {
+            let loan_id = positions::read_active_loan(e, position).ok_or(Error::LoanNotFound)?;
+            loans.push_back(Self::project_loan(e, &mut cache, loan_id)?);
+        }
+        Ok(loans)
     }
 
-    pub fn get_loan(e: &Env, addr: Address) -> Loan {
-        if let Some(loan) = positions::read_positions(e, addr) {
-            loan
-        } else {
-            panic!() // TODO: It should be panic_with_error or something and give out detailed error.
+    /// Open loans of a borrower with their debt, unpaid interest and health factor as of now.
+    /// Unlike `add_interest`, the interest is only projected and nothing is written.
+    pub fn get_loan_health(e: &Env, borrower: Address) -> Result<Vec<Loan>, Error> {
+        let mut cache = PoolCache::new(e);
+        let mut loans = Vec::new(e);
+        for loan_id in positions::read_user_loans(e, &borrower).iter() {
+            loans.push_back(Self::project_loan(e, &mut cache, loan_id)?);
         }
+        Ok(loans)
     }
 
-    pub fn get_price(e: &Env, token: Symbol) -> Result<i128, Error> {
-        let reflector_address = Address::from_string(&String::from_str(e, REFLECTOR_ADDRESS));
-        let reflector_contract = oracle::Client::new(e, &reflector_address);
+    /// Latest spot price of the token of a pool, whatever its pricing mode is. The token is priced
+    /// by the pool's oracle asset.
+    pub fn get_price(e: &Env, pool: Address) -> Result<i128, Error> {
+        let ticker = loan_pool::Client::new(e, &pool).get_currency().ticker;
+        oracle::read_price(e, &pool, ticker)
+    }
 
-        let asset = Asset::Other(token);
+    /// How the token of a pool is priced.
+    pub fn get_oracle_asset(e: &Env, pool: Address) -> OracleAsset {
+        let ticker = loan_pool::Client::new(e, &pool).get_currency().ticker;
+        oracle::read_oracle_asset(e, &pool, ticker)
+    }
 
-        let asset_pricedata = reflector_contract
-            .lastprice(&asset)
-            .ok_or(Error::NoLastPrice)?;
-        Ok(asset_pricedata.price)
+    pub fn get_max_price_age(e: &Env, pool: Address) -> u64 {
+        oracle::read_max_price_age(e, &pool)
     }
 
-    pub fn repay(e: &Env, user: Address, amount: i128) -> Result<(i128, i128), Error> {
-        user.require_auth();
+    pub fn get_pricing_mode(e: &Env, pool: Address) -> PricingMode {
+        oracle::read_pricing_mode(e, &pool)
+    }
 
-        Self::add_interest(e, user.clone())?;
+    pub fn repay(e: &Env, loan_id: u64, amount: i128) -> Result<(i128, i128), Error> {
+        Self::get_loan(e, loan_id)?.borrower.require_auth();
+
+        Self::add_interest(e, loan_id)?;
 
         let Loan {
+            id,
             borrower,
             borrowed_amount,
             borrowed_from,
-            collateral_amount,
-            collateral_from,
+            collateral,
             unpaid_interest,
             last_accrual,
             ..
-        } = Self::get_loan(e, user.clone());
+        } = Self::get_loan(e, loan_id)?;
 
         assert!(
             amount <= borrowed_amount,
             "Amount can not be greater than borrowed amount!"
         );
 
-        let collateral_pool_client = loan_pool::Client::new(e, &collateral_from);
         let borrow_pool_client = loan_pool::Client::new(e, &borrowed_from);
-        borrow_pool_client.repay(&user, &amount, &unpaid_interest);
+        borrow_pool_client.repay(&borrower, &amount, &unpaid_interest);
 
         let new_unpaid_interest = if amount < unpaid_interest {
             unpaid_interest
@@ -377,61 +662,52 @@ impl LoanManager {
             0
         };
 
-        let key = (Symbol::new(e, "Loan"), user.clone());
         let new_borrowed_amount = borrowed_amount
             .checked_sub(amount)
             .ok_or(Error::OverOrUnderFlow)?;
 
         let new_health_factor = Self::calculate_health_factor(
             e,
-            borrow_pool_client.get_currency().ticker,
+            borrowed_from.clone(),
             new_borrowed_amount,
-            collateral_pool_client.get_currency().ticker,
-            collateral_amount,
-            collateral_from.clone(),
+            collateral.clone(),
         )?;
 
         let loan = Loan {
+            id,
             borrower,
             borrowed_amount: new_borrowed_amount,
             borrowed_from,
-            collateral_amount,
-            collateral_from,
+            collateral,
             health_factor: new_health_factor,
             unpaid_interest: new_unpaid_interest,
             last_accrual,
         };
 
-        e.storage().persistent().set(&key, &loan);
-        e.storage().persistent().extend_ttl(
-            &key,
-            POSITIONS_LIFETIME_THRESHOLD,
-            POSITIONS_BUMP_AMOUNT,
-        );
-        e.events().publish((key, symbol_short!("updated")), loan);
+        positions::write_positions(e, &loan);
+        events::repay(e, amount, loan);
 
         Ok((borrowed_amount, new_borrowed_amount))
     }
 
     pub fn repay_and_close_manager(
         e: &Env,
-        user: Address,
+        loan_id: u64,
         max_allowed_amount: i128,
     ) -> Result<i128, Error> {
-        user.require_auth();
+        Self::get_loan(e, loan_id)?.borrower.require_auth();
 
-        Self::add_interest(e, user.clone())?;
+        Self::add_interest(e, loan_id)?;
 
+        let loan = Self::get_loan(e, loan_id)?;
         let Loan {
-            borrower: _,
+            borrower: user,
             borrowed_amount,
             borrowed_from,
-            collateral_amount,
-            collateral_from,
-            health_factor: _,
+            collateral,
             unpaid_interest,
-            last_accrual: _,
-        } = Self::get_loan(e, user.clone());
+            ..
+        } = loan.clone();
 
         let borrow_pool_client = loan_pool::Client::new(e, &borrowed_from);
         borrow_pool_client.repay_and_close(
@@ -441,125 +717,832 @@ impl LoanManager {
             &unpaid_interest,
         );
 
-        let collateral_pool_client = loan_pool::Client::new(e, &collateral_from);
-        collateral_pool_client.withdraw_collateral(&user, &collateral_amount);
+        for Collateral { pool, amount } in collateral.iter() {
+            loan_pool::Client::new(e, &pool).withdraw_collateral(&user, &amount);
+        }
 
-        let key = (Symbol::new(e, "Loan"), user.clone());
-        e.storage().persistent().remove(&key);
+        positions::remove_positions(e, &loan);
+        auction::remove(e, loan_id);
+        events::loan_closed(e, loan_id, user, borrowed_amount, 0, collateral);
         Ok(borrowed_amount)
     }
 
-    pub fn liquidate(
-        e: Env,
-        user: Address,
-        borrower: Address,
-        amount: i128,
-    ) -> Result<(i128, i128), Error> {
-        user.require_auth();
-
-        Self::add_interest(&e, borrower.clone())?;
+    /// Borrow more from the pool of an existing loan. Interest is accrued first and the loan has
+    /// to stay above the health factor required for borrowing. Returns the new borrowed amount.
+    pub fn borrow_more(e: &Env, loan_id: u64, amount: i128) -> Result<i128, Error> {
+        Self::get_loan(e, loan_id)?.borrower.require_auth();
+        if amount <= 0 {
+            return Err(Error::InvalidBorrowAmount);
+        }
 
-        let Loan {
-            borrower,
-            borrowed_amount,
-            borrowed_from,
-            collateral_from,
-            collateral_amount,
-            health_factor: _,
-            unpaid_interest,
-            last_accrual,
-        } = Self::get_loan(&e, borrower);
+        Self::add_interest(e, loan_id)?;
+        let mut loan = Self::get_loan(e, loan_id)?;
 
-        let key = (Symbol::new(&e, "Loan"), borrower.clone());
+        loan.borrowed_amount = loan
+            .borrowed_amount
+            .checked_add(amount)
+            .ok_or(Error::OverOrUnderFlow)?;
+        let health_factor = Self::refresh_health_factor(e, &mut loan)?;
+        if health_factor <= HEALTH_FACTOR_THRESHOLD {
+            return Err(Error::HealthFactorTooLow);
+        }
 
-        let borrow_pool_client = loan_pool::Client::new(&e, &borrowed_from);
-        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_from);
+        loan_pool::Client::new(e, &loan.borrowed_from).borrow(&loan.borrower, &amount);
 
-        let borrowed_ticker = borrow_pool_client.get_currency().ticker;
-        let collateral_ticker = collateral_pool_client.get_currency().ticker;
+        let borrowed_amount = loan.borrowed_amount;
+        positions::write_positions(e, &loan);
+        events::loan_updated(e, loan);
 
-        // Check that loan is for sure liquidatable at this moment.
-        assert!(
-            Self::calculate_health_factor(
-                &e,
-                borrowed_ticker.clone(),
-                borrowed_amount,
-                collateral_ticker.clone(),
-                collateral_amount,
-                collateral_from.clone(),
-            )? < 10000000
-        ); // Temp high value for testing
-        assert!(
-            amount
-                < (borrowed_amount
-                    .checked_div(2)
-                    .ok_or(Error::OverOrUnderFlow)?)
-        );
+        Ok(borrowed_amount)
+    }
 
-        let borrowed_price = Self::get_price(&e, borrowed_ticker.clone())?;
-        let collateral_price = Self::get_price(&e, collateral_ticker.clone())?;
+    /// Deposit more collateral to a loan, either to a pool the loan already uses or to a new one.
+    /// Returns the new health factor.
+    pub fn add_collateral(
+        e: &Env,
+        loan_id: u64,
+        pool: Address,
+        amount: i128,
+    ) -> Result<i128, Error> {
+        Self::get_loan(e, loan_id)?.borrower.require_auth();
+        if amount <= 0 {
+            return Err(Error::InvalidCollateral);
+        }
+        Self::require_pool(e, &pool)?;
+
+        Self::add_interest(e, loan_id)?;
+        let mut loan = Self::get_loan(e, loan_id)?;
+
+        loan_pool::Client::new(e, &pool).deposit_collateral(&loan.borrower, &amount);
+
+        match loan.collateral.iter().position(|c| c.pool == pool) {
+            Some(index) => {
+                let index = index as u32;
+                let current = loan
+                    .collateral
+                    .get(index)
+                    .ok_or(Error::CollateralNotFound)?;
+                let new_amount = current
+                    .amount
+                    .checked_add(amount)
+                    .ok_or(Error::OverOrUnderFlow)?;
+                loan.collateral.set(
+                    index,
+                    Collateral {
+                        pool,
+                        amount: new_amount,
+                    },
+                );
+            }
+            None => loan.collateral.push_back(Collateral { pool, amount }),
+        }
 
-        const TEMP_BONUS: i128 = 10_500_000; // multiplier 1.05 -> 5%
+        let health_factor = Self::refresh_health_factor(e, &mut loan)?;
+        positions::write_positions(e, &loan);
+        events::loan_updated(e, loan);
 
-        let liquidation_value = amount
-            .checked_mul(borrowed_price)
-            .ok_or(Error::OverOrUnderFlow)?;
-        let collateral_amount_bonus = liquidation_value
-            .checked_mul(TEMP_BONUS)
-            .ok_or(Error::OverOrUnderFlow)?
-            .checked_div(collateral_price)
-            .ok_or(Error::OverOrUnderFlow)?
-            .checked_div(10_000_000)
-            .ok_or(Error::OverOrUnderFlow)?;
+        Ok(health_factor)
+    }
 
-        borrow_pool_client.liquidate(&user, &amount, &unpaid_interest, &borrower);
+    /// Withdraw part of the collateral of a loan. The loan has to stay above the health factor
+    /// required for borrowing. A loan without debt is closed once the last of its collateral is
+    /// withdrawn. Returns the new health factor.
+    pub fn withdraw_collateral(
+        e: &Env,
+        loan_id: u64,
+        pool: Address,
+        amount: i128,
+    ) -> Result<i128, Error> {
+        Self::get_loan(e, loan_id)?.borrower.require_auth();
+        if amount <= 0 {
+            return Err(Error::InvalidCollateral);
+        }
 
-        collateral_pool_client.liquidate_transfer_collateral(
-            &user,
-            &collateral_amount_bonus,
-            &borrower,
-        );
+        Self::add_interest(e, loan_id)?;
+        let mut loan = Self::get_loan(e, loan_id)?;
 
-        let new_borrowed_amount = borrowed_amount
+        let index = loan
+            .collateral
+            .iter()
+            .position(|c| c.pool == pool)
+            .ok_or(Error::CollateralNotFound)? as u32;
+        let current = loan
+            .collateral
+            .get(index)
+            .ok_or(Error::CollateralNotFound)?;
+        let new_amount = current
+            .amount
             .checked_sub(amount)
             .ok_or(Error::OverOrUnderFlow)?;
-        let new_collateral_amount = collateral_amount
-            .checked_sub(collateral_amount_bonus)
-            .ok_or(Error::OverOrUnderFlow)?;
+        if new_amount < 0 {
+            return Err(Error::InsufficientCollateral);
+        }
+        if new_amount > 0 {
+            loan.collateral.set(
+                index,
+                Collateral {
+                    pool: pool.clone(),
+                    amount: new_amount,
+                },
+            );
+        } else {
+            loan.collateral.remove(index);
+        }
 
-        let new_health_factor = Self::calculate_health_factor(
-            &e,
-            borrowed_ticker,
-            new_borrowed_amount,
-            collateral_ticker,
-            new_collateral_amount,
-            collateral_from.clone(),
-        )?;
+        let health_factor = Self::refresh_health_factor(e, &mut loan)?;
+        if health_factor <= HEALTH_FACTOR_THRESHOLD {
+            return Err(Error::HealthFactorTooLow);
+        }
 
-        let new_loan = Loan {
-            borrower,
-            borrowed_amount: new_borrowed_amount,
-            borrowed_from,
-            collateral_from,
-            collateral_amount: new_collateral_amount,
-            health_factor: new_health_factor,
-            unpaid_interest, // Temp
-            last_accrual,
-        };
+        loan_pool::Client::new(e, &pool).withdraw_collateral(&loan.borrower, &amount);
+        if loan.collateral.is_empty() && loan.borrowed_amount == 0 {
+            positions::remove_positions(e, &loan);
+            auction::remove(e, loan_id);
+            events::loan_closed(
+                e,
+                loan_id,
+                loan.borrower,
+                0,
+                0,
+                vec![e, Collateral { pool, amount }],
+            );
+        } else {
+            positions::write_positions(e, &loan);
+            events::loan_updated(e, loan);
+        }
 
-        e.storage().persistent().set(&key, &new_loan);
+        Ok(health_factor)
+    }
 
-        Ok((new_borrowed_amount, new_collateral_amount))
+    /// Start a liquidation auction for an unhealthy loan. Anyone can start one if auctions are
+    /// enabled.
+    pub fn start_auction(e: Env, loan_id: u64) -> Result<Auction, Error> {
+        if auction::read_config(&e).is_none() {
+            return Err(Error::AuctionsDisabled);
+        }
+        Self::add_interest(&e, loan_id)?;
+        if Self::get_loan(&e, loan_id)?.health_factor >= HEALTH_FACTOR_THRESHOLD {
+            return Err(Error::LoanNotLiquidatable);
+        }
+        auction::start(&e, loan_id)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use soroban_sdk::{
-        testutils::{Address as _, Ledger},
+    /// End the auction of a loan that is healthy again, e.g. after the prices recovered.
+    pub fn cancel_auction(e: Env, loan_id: u64) -> Result<(), Error> {
+        if auction::read(&e, loan_id).is_none() {
+            return Err(Error::AuctionNotFound);
+        }
+        Self::add_interest(&e, loan_id)?;
+        if Self::get_loan(&e, loan_id)?.health_factor < HEALTH_FACTOR_THRESHOLD {
+            return Err(Error::LoanStillLiquidatable);
+        }
+        auction::remove(&e, loan_id);
+        Ok(())
+    }
+
+    pub fn get_auction(e: Env, loan_id: u64) -> Option<Auction> {
+        auction::read(&e, loan_id)
+    }
+
+    pub fn get_auction_config(e: Env) -> Option<AuctionConfig> {
+        auction::read_config(&e)
+    }
+
+    /// Current discount on the collateral of a loan's auction, 10_000_000 = 100%. Liquidations
+    /// cap it at 100% minus the liquidation threshold of the seized collateral's pool.
+    pub fn get_auction_discount(e: Env, loan_id: u64) -> Result<i128, Error> {
+        let config = auction::read_config(&e).ok_or(Error::AuctionsDisabled)?;
+        let auction = auction::read(&e, loan_id).ok_or(Error::AuctionNotFound)?;
+        auction::discount(&e, &config, &auction)
+    }
+
+    /// Repay part of an unhealthy loan and receive the chosen collateral with a bonus, or at the
+    /// current discount of the loan's auction if auctions are enabled. Returns the remaining debt
+    /// and the remaining amount of the chosen collateral.
+    pub fn liquidate(
+        e: Env,
+        user: Address,
+        loan_id: u64,
+        amount: i128,
+        collateral_pool: Address,
+    ) -> Result<(i128, i128), Error> {
+        user.require_auth();
+        if amount <= 0 {
+            return Err(Error::InvalidLiquidationAmount);
+        }
+
+        let mut loan = Self::get_loan(&e, loan_id)?;
+        let borrow_pool_client = loan_pool::Client::new(&e, &loan.borrowed_from);
+        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool);
+
+        (
+            loan.borrowed_amount,
+            loan.unpaid_interest,
+            loan.last_accrual,
+        ) = Self::accrue_interest(
+            &borrow_pool_client,
+            loan.borrowed_amount,
+            loan.unpaid_interest,
+            loan.last_accrual,
+        )?;
+        let quote = Self::quote_liquidation(
+            &e,
+            &loan,
+            amount,
+            &borrow_pool_client,
+            &collateral_pool_client,
+        )?;
+
+        borrow_pool_client.liquidate(&user, &amount, &loan.unpaid_interest, &loan.borrower);
+
+        collateral_pool_client.liquidate_transfer_collateral(
+            &user,
+            &quote.preview.collateral_seized,
+            &loan.borrower,
+        );
+
+        if quote.remaining_collateral > 0 {
+            loan.collateral.set(
+                quote.collateral_index,
+                Collateral {
+                    pool: collateral_pool,
+                    amount: quote.remaining_collateral,
+                },
+            );
+        } else {
+            loan.collateral.remove(quote.collateral_index);
+        }
+        loan.borrowed_amount = loan
+            .borrowed_amount
+            .checked_sub(amount)
+            .ok_or(Error::OverOrUnderFlow)?;
+        // The liquidator pays the unpaid interest first.
+        loan.unpaid_interest = loan
+            .unpaid_interest
+            .checked_sub(amount.min(loan.unpaid_interest))
+            .ok_or(Error::OverOrUnderFlow)?;
+        loan.health_factor = quote.preview.health_factor;
+
+        positions::write_positions(&e, &loan);
+        let new_borrowed_amount = loan.borrowed_amount;
+        events::liquidate(&e, user, amount, quote.preview.collateral_seized, loan);
+
+        Ok((new_borrowed_amount, quote.remaining_collateral))
+    }
+
+    /// Outcome of `liquidate` with the same arguments at the current ledger, without changing
+    /// anything. Interest is accrued only for the calculation, and the call fails in the same
+    /// cases as the liquidation would.
+    pub fn preview_liquidation(
+        e: Env,
+        loan_id: u64,
+        amount: i128,
+        collateral_pool: Address,
+    ) -> Result<LiquidationPreview, Error> {
+        let mut loan = Self::get_loan(&e, loan_id)?;
+        let borrow_pool_client = loan_pool::Client::new(&e, &loan.borrowed_from);
+        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool);
+
+        (
+            loan.borrowed_amount,
+            loan.unpaid_interest,
+            loan.last_accrual,
+        ) = Self::apply_accrual(
+            borrow_pool_client.get_projected_accrual(),
+            loan.borrowed_amount,
+            loan.unpaid_interest,
+            loan.last_accrual,
+        )?;
+        let quote = Self::quote_liquidation(
+            &e,
+            &loan,
+            amount,
+            &borrow_pool_client,
+            &collateral_pool_client,
+        )?;
+        Ok(quote.preview)
+    }
+
+    /// Close a loan whose collateral is worth less than its debt. The admin buys the remaining
+    /// collateral at its spot oracle value, paid in the borrowed token to the borrow pool, and only
+    /// the shortfall is written off. Spot prices are used regardless of the pricing modes, so a
+    /// loan that is solvent at market prices can't be written off. The pool covers the loss from
+    /// reserves first and then by the suppliers. Returns the written off debt and the loss to the
+    /// suppliers.
+    pub fn write_off_bad_debt(e: Env, loan_id: u64) -> Result<(i128, i128), Error> {
+        let admin: Address = e
+            .storage()
+            .persistent()
+            .get(&LoansDataKey::Admin)
+            .ok_or(Error::AdminNotFound)?;
+        admin.require_auth();
+
+        Self::add_interest(&e, loan_id)?;
+
+        let loan = Self::get_loan(&e, loan_id)?;
+        let Loan {
+            borrower,
+            borrowed_amount,
+            borrowed_from,
+            collateral,
+            unpaid_interest,
+            ..
+        } = loan.clone();
+
+        let borrow_pool_client = loan_pool::Client::new(&e, &borrowed_from);
+
+        let borrowed_currency = borrow_pool_client.get_currency();
+        let borrowed_price = oracle::read_price(&e, &borrowed_from, borrowed_currency.ticker)?;
+        let borrowed_value =
+            Self::value(borrowed_price, borrowed_amount, borrowed_currency.decimals)?;
+        let mut collateral_value: i128 = 0;
+        for Collateral { pool, amount } in collateral.iter() {
+            let collateral_currency = loan_pool::Client::new(&e, &pool).get_currency();
+            collateral_value = Self::value(
+                oracle::read_price(&e, &pool, collateral_currency.ticker)?,
+                amount,
+                collateral_currency.decimals,
+            )?
+            .checked_add(collateral_value)
+            .ok_or(Error::OverOrUnderFlow)?;
+        }
+        if collateral_value >= borrowed_value {
+            return Err(Error::LoanNotInsolvent);
+        }
+
+        // Debt that the collateral is worth, rounded down in favour of the admin.
+        let recovered_amount = 10_i128
+            .checked_pow(borrowed_currency.decimals)
+            .and_then(|unit| collateral_value.checked_mul(unit))
+            .ok_or(Error::OverOrUnderFlow)?
+            .checked_div(borrowed_price)
+            .ok_or(Error::OverOrUnderFlow)?;
+        if recovered_amount > 0 {
+            borrow_pool_client.liquidate(&admin, &recovered_amount, &unpaid_interest, &borrower);
+        }
+        for Collateral { pool, amount } in collateral.iter() {
+            if amount > 0 {
+                loan_pool::Client::new(&e, &pool)
+                    .liquidate_transfer_collateral(&admin, &amount, &borrower);
+            }
+        }
+
+        // The admin's payment covers the unpaid interest first, like any liquidation.
+        let written_off_amount = borrowed_amount
+            .checked_sub(recovered_amount)
+            .ok_or(Error::OverOrUnderFlow)?;
+        let written_off_interest = unpaid_interest
+            .checked_sub(recovered_amount.min(unpaid_interest))
+            .ok_or(Error::OverOrUnderFlow)?;
+        let socialized_loss = borrow_pool_client.write_off_debt(
+            &borrower,
+            &written_off_amount,
+            &written_off_interest,
+        );
+
+        positions::remove_positions(&e, &loan);
+        auction::remove(&e, loan_id);
+        events::loan_closed(
+            &e,
+            loan_id,
+            borrower,
+            recovered_amount,
+            written_off_amount,
+            collateral,
+        );
+
+        Ok((written_off_amount, socialized_loss))
+    }
+}
+
+impl LoanManager {
+    /// Every collateral position has to be positive and in a different registered pool.
+    fn validate_collateral(e: &Env, collateral: &Vec<Collateral>) -> Result<(), Error> {
+        if collateral.is_empty() {
+            return Err(Error::NoCollateral);
+        }
+        let mut pools: Map<Address, bool> = Map::new(e);
+        for Collateral { pool, amount } in collateral.iter() {
+            if amount <= 0 || pools.contains_key(pool.clone()) {
+                return Err(Error::InvalidCollateral);
+            }
+            Self::require_pool(e, &pool)?;
+            pools.set(pool, true);
+        }
+        Ok(())
+    }
+
+    /// Only pools deployed by the loan manager can be used, as any other contract could report
+    /// whatever currency or collateral it likes.
+    fn require_pool(e: &Env, pool: &Address) -> Result<(), Error> {
+        let pool_addresses: Vec<Address> = e
+            .storage()
+            .persistent()
+            .get(&LoansDataKey::PoolAddresses)
+            .unwrap_or(vec![e]);
+        if !pool_addresses.contains(pool) {
+            return Err(Error::PoolNotFound);
+        }
+        Ok(())
+    }
+
+    /// Ask a registered pool whether it would accept a parameter, so that a governance action
+    /// fails when it's queued instead of after the timelock.
+    fn validate_pool_param(
+        e: &Env,
+        pool: &Address,
+        param: loan_pool::PoolParam,
+        error: Error,
+    ) -> Result<(), Error> {
+        Self::require_pool(e, pool)?;
+        match loan_pool::Client::new(e, pool).try_validate_param(&param) {
+            Ok(Ok(())) => Ok(()),
+            _ => Err(error),
+        }
+    }
+
+    /// Recalculate the health factor of a loan after its collateral or debt changed.
+    fn refresh_health_factor(e: &Env, loan: &mut Loan) -> Result<i128, Error> {
+        loan.health_factor = Self::calculate_health_factor(
+            e,
+            loan.borrowed_from.clone(),
+            loan.borrowed_amount,
+            loan.collateral.clone(),
+        )?;
+        Ok(loan.health_factor)
+    }
+
+    /// Accrue the interest of the borrow pool and apply it to a loan. Returns the new borrowed
+    /// amount, unpaid interest and accrual of the loan.
+    fn accrue_interest(
+        borrow_pool_client: &loan_pool::Client,
+        borrowed_amount: i128,
+        unpaid_interest: i128,
+        last_accrual: i128,
+    ) -> Result<(i128, i128, i128), Error> {
+        borrow_pool_client.add_interest_to_accrual();
+        Self::apply_accrual(
+            borrow_pool_client.get_accrual(),
+            borrowed_amount,
+            unpaid_interest,
+            last_accrual,
+        )
+    }
+
+    /// Apply the interest between the loan's last accrual and the current accrual of the pool.
+    fn apply_accrual(
+        current_accrual: i128,
+        borrowed_amount: i128,
+        unpaid_interest: i128,
+        last_accrual: i128,
+    ) -> Result<(i128, i128, i128), Error> {
+        let interest_since_update_multiplier = current_accrual
+            .checked_mul(DECIMAL)
+            .ok_or(Error::OverOrUnderFlow)?
+            .checked_div(last_accrual)
+            .ok_or(Error::OverOrUnderFlow)?;
+
+        let new_borrowed_amount = borrowed_amount
+            .checked_mul(interest_since_update_multiplier)
+            .ok_or(Error::OverOrUnderFlow)?
+            .checked_div(DECIMAL)
+            .ok_or(Error::OverOrUnderFlow)?;
+
+        let borrow_change = new_borrowed_amount
+            .checked_sub(borrowed_amount)
+            .ok_or(Error::OverOrUnderFlow)?;
+        let new_unpaid_interest = unpaid_interest
+            .checked_add(borrow_change)
+            .ok_or(Error::OverOrUnderFlow)?;
+
+        Ok((new_borrowed_amount, new_unpaid_interest, current_accrual))
+    }
+
+    /// Collateral seized by liquidating `amount` of a loan whose interest is already accrued, and
+    /// the health factor of the loan afterwards.
+    fn quote_liquidation(
+        e: &Env,
+        loan: &Loan,
+        amount: i128,
+        borrow_pool_client: &loan_pool::Client,
+        collateral_pool_client: &loan_pool::Client,
+    ) -> Result<LiquidationQuote, Error> {
+        let collateral_index = loan
+            .collateral
+            .iter()
+            .position(|c| c.pool == collateral_pool_client.address)
+            .ok_or(Error::CollateralNotFound)? as u32;
+        let collateral_amount = loan
+            .collateral
+            .get(collateral_index)
+            .ok_or(Error::CollateralNotFound)?
+            .amount;
+
+        let loan_pool::Currency {
+            ticker: borrowed_ticker,
+            decimals: borrowed_decimals,
+            ..
+        } = borrow_pool_client.get_currency();
+        let borrowed_price =
+            oracle::read_price_for(e, &loan.borrowed_from, borrowed_ticker, PriceSide::Debt)?;
+
+        let loan_pool::LiquidationParams {
+            liquidation_threshold,
+            close_factor,
+            liquidation_bonus,
+        } = collateral_pool_client.get_liquidation_params();
+
+        // Every pool and oracle call is expensive, so the collateral is valued only once. The
+        // price of the seized collateral is kept for the health factor afterwards.
+        let mut other_collateral_value: i128 = 0;
+        let mut collateral_price = 0;
+        let mut collateral_decimals = 0;
+        for (index, Collateral { pool, amount }) in loan.collateral.iter().enumerate() {
+            if index as u32 == collateral_index {
+                (collateral_price, collateral_decimals) =
+                    Self::collateral_price(e, &pool, collateral_pool_client)?;
+            } else {
+                let (price, decimals, factor) = Self::collateral_price_and_factor(e, &pool)?;
+                other_collateral_value = other_collateral_value
+                    .checked_add(Self::collateral_value(price, amount, decimals, factor)?)
+                    .ok_or(Error::OverOrUnderFlow)?;
+            }
+        }
+        let health_factor = Self::health_factor(
+            other_collateral_value
+                .checked_add(Self::collateral_value(
+                    collateral_price,
+                    collateral_amount,
+                    collateral_decimals,
+                    liquidation_threshold,
+                )?)
+                .ok_or(Error::OverOrUnderFlow)?,
+            Self::value(borrowed_price, loan.borrowed_amount, borrowed_decimals)?,
+        )?;
+
+        if health_factor >= HEALTH_FACTOR_THRESHOLD {
+            return Err(Error::LoanNotLiquidatable);
+        }
+        let max_amount = loan
+            .borrowed_amount
+            .checked_mul(close_factor)
+            .ok_or(Error::OverOrUnderFlow)?
+            .checked_div(DECIMAL)
+            .ok_or(Error::OverOrUnderFlow)?;
+        if amount > max_amount {
+            return Err(Error::LiquidationTooLarge);
+        }
+
+        let repaid_value = Self::value(borrowed_price, amount, borrowed_decimals)?;
+        let liquidation_value = match auction::read_config(e) {
+            Some(config) => {
+                let auction = auction::read(e, loan.id).ok_or(Error::AuctionNotFound)?;
+                // Same limit as for the bonus: the seized collateral can't be worth more to the
+                // health factor than the repaid debt.
+                let discount =
+                    auction::discount(e, &config, &auction)?.min(DECIMAL - liquidation_threshold);
+                repaid_value
+                    .checked_mul(DECIMAL)
+                    .ok_or(Error::OverOrUnderFlow)?
+                    .checked_div(DECIMAL - discount)
+                    .ok_or(Error::OverOrUnderFlow)?
+            }
+            None => repaid_value
+                .checked_mul(DECIMAL + liquidation_bonus)
+                .ok_or(Error::OverOrUnderFlow)?
+                .checked_div(DECIMAL)
+                .ok_or(Error::OverOrUnderFlow)?,
+        };
+        let collateral_seized = 10_i128
+            .checked_pow(collateral_decimals)
+            .and_then(|unit| liquidation_value.checked_mul(unit))
+            .ok_or(Error::OverOrUnderFlow)?
+            .checked_div(collateral_price)
+            .ok_or(Error::OverOrUnderFlow)?;
+        if collateral_seized > collateral_amount {
+            return Err(Error::InsufficientCollateral);
+        }
+
+        let remaining_collateral = collateral_amount
+            .checked_sub(collateral_seized)
+            .ok_or(Error::OverOrUnderFlow)?;
+        let new_borrowed_amount = loan
+            .borrowed_amount
+            .checked_sub(amount)
+            .ok_or(Error::OverOrUnderFlow)?;
+        let new_health_factor = Self::health_factor(
+            other_collateral_value
+                .checked_add(Self::collateral_value(
+                    collateral_price,
+                    remaining_collateral,
+                    collateral_decimals,
+                    liquidation_threshold,
+                )?)
+                .ok_or(Error::OverOrUnderFlow)?,
+            Self::value(borrowed_price, new_borrowed_amount, borrowed_decimals)?,
+        )?;
+
+        Ok(LiquidationQuote {
+            preview: LiquidationPreview {
+                max_amount,
+                collateral_seized,
+                bonus_value: liquidation_value
+                    .checked_sub(repaid_value)
+                    .ok_or(Error::OverOrUnderFlow)?,
+                health_factor: new_health_factor,
+            },
+            collateral_index,
+            remaining_collateral,
+        })
+    }
+
+    /// The loan with interest accrued up to now and its health factor at current prices, without
+    /// writing anything.
+    fn project_loan(e: &Env, cache: &mut PoolCache, loan_id: u64) -> Result<Loan, Error> {
+        let mut loan = Self::get_loan(e, loan_id)?;
+        (
+            loan.borrowed_amount,
+            loan.unpaid_interest,
+            loan.last_accrual,
+        ) = Self::apply_accrual(
+            cache.accrual(e, &loan.borrowed_from),
+            loan.borrowed_amount,
+            loan.unpaid_interest,
+            loan.last_accrual,
+        )?;
+
+        let mut collateral_value: i128 = 0;
+        for Collateral { pool, amount } in loan.collateral.iter() {
+            let decimals = cache.currency(e, &pool).decimals;
+            collateral_value = collateral_value
+                .checked_add(Self::collateral_value(
+                    cache.price(e, &pool, PriceSide::Collateral)?,
+                    amount,
+                    decimals,
+                    cache.collateral_factor(e, &pool),
+                )?)
+                .ok_or(Error::OverOrUnderFlow)?;
+        }
+        let borrowed_decimals = cache.currency(e, &loan.borrowed_from).decimals;
+        loan.health_factor = Self::health_factor(
+            collateral_value,
+            Self::value(
+                cache.price(e, &loan.borrowed_from, PriceSide::Debt)?,
+                loan.borrowed_amount,
+                borrowed_decimals,
+            )?,
+        )?;
+        Ok(loan)
+    }
+
+    fn collateral_price_and_factor(e: &Env, pool: &Address) -> Result<(i128, u32, i128), Error> {
+        let collateral_pool_client = loan_pool::Client::new(e, pool);
+        let collateral_factor = collateral_pool_client.get_collateral_factor();
+        let (price, decimals) = Self::collateral_price(e, pool, &collateral_pool_client)?;
+        Ok((price, decimals, collateral_factor))
+    }
+
+    fn collateral_price(
+        e: &Env,
+        pool: &Address,
+        collateral_pool_client: &loan_pool::Client,
+    ) -> Result<(i128, u32), Error> {
+        let loan_pool::Currency {
+            ticker, decimals, ..
+        } = collateral_pool_client.get_currency();
+        let price = oracle::read_price_for(e, pool, ticker, PriceSide::Collateral)?;
+        Ok((price, decimals))
+    }
+
+    /// Value of a token amount in the price scale of the oracle module. Prices are for one whole
+    /// token, so the amount is divided by the token's decimals.
+    fn value(price: i128, amount: i128, decimals: u32) -> Result<i128, Error> {
+        price
+            .checked_mul(amount)
+            .ok_or(Error::OverOrUnderFlow)?
+            .checked_div(
+                10_i128
+                    .checked_pow(decimals)
+                    .ok_or(Error::OverOrUnderFlow)?,
+            )
+            .ok_or(Error::OverOrUnderFlow)
+    }
+
+    /// Value of collateral after the collateral factor is applied.
+    fn collateral_value(
+        price: i128,
+        amount: i128,
+        decimals: u32,
+        collateral_factor: i128,
+    ) -> Result<i128, Error> {
+        Self::value(price, amount, decimals)?
+            .checked_mul(collateral_factor)
+            .ok_or(Error::OverOrUnderFlow)?
+            .checked_div(DECIMAL)
+            .ok_or(Error::OverOrUnderFlow)
+    }
+
+    /// A loan without debt can't be liquidated, so its health factor is the largest possible.
+    fn health_factor(collateral_value: i128, borrowed_value: i128) -> Result<i128, Error> {
+        if borrowed_value == 0 {
+            return Ok(i128::MAX);
+        }
+        collateral_value
+            .checked_mul(DECIMAL)
+            .ok_or(Error::OverOrUnderFlow)?
+            .checked_div(borrowed_value)
+            .ok_or(Error::OverOrUnderFlow)
+    }
+
+    fn execute(e: &Env, action: GovernanceAction) -> Result<(), Error> {
+        match action {
+            GovernanceAction::Upgrade(new_manager_wasm_hash, new_pool_wasm_hash) => {
+                e.storage()
+                    .persistent()
+                    .get(&LoansDataKey::PoolAddresses)
+                    .unwrap_or(vec![e])
+                    .iter()
+                    .for_each(|pool| {
+                        let pool_client = loan_pool::Client::new(e, &pool);
+                        pool_client.upgrade(&new_pool_wasm_hash);
+                        pool_client.migrate();
+                    });
+
+                e.deployer()
+                    .update_current_contract_wasm(new_manager_wasm_hash);
+            }
+            GovernanceAction::SetInterestModel(pool_address, model) => {
+                let pool_client = loan_pool::Client::new(e, &pool_address);
+                pool_client.set_interest_model(&model.into());
+            }
+            GovernanceAction::SetReserveFactor(pool_address, reserve_factor) => {
+                let pool_client = loan_pool::Client::new(e, &pool_address);
+                pool_client.set_reserve_factor(&reserve_factor);
+            }
+            GovernanceAction::SetPoolCaps(pool_address, supply_cap, borrow_cap) => {
+                let pool_client = loan_pool::Client::new(e, &pool_address);
+                pool_client.set_caps(&supply_cap, &borrow_cap);
+            }
+            GovernanceAction::SetFlashLoanFee(pool_address, fee) => {
+                let pool_client = loan_pool::Client::new(e, &pool_address);
+                pool_client.set_flash_loan_fee(&fee);
+            }
+            GovernanceAction::SetLiquidationParams(pool_address, params) => {
+                let pool_client = loan_pool::Client::new(e, &pool_address);
+                pool_client.set_liquidation_params(&params.into());
+            }
+            GovernanceAction::SetTimelockDelay(delay) => {
+                timelock::validate_delay(delay)?;
+                timelock::write_delay(e, delay);
+                events::param_changed(e, ManagerParam::TimelockDelay(delay));
+            }
+            GovernanceAction::SetOracles(oracles) => {
+                oracle::write_oracles(e, &oracles)?;
+                events::param_changed(e, ManagerParam::Oracles(oracles));
+            }
+            GovernanceAction::SetOracle(primary) => {
+                let oracles = oracle::with_primary_oracle(e, primary)?;
+                oracle::write_oracles(e, &oracles)?;
+                events::param_changed(e, ManagerParam::Oracles(oracles));
+            }
+            GovernanceAction::SetMaxPriceDeviation(max_deviation) => {
+                oracle::write_max_price_deviation(e, max_deviation)?;
+                events::param_changed(e, ManagerParam::MaxPriceDeviation(max_deviation));
+            }
+            GovernanceAction::SetPricingMode(pool, mode) => {
+                oracle::write_pricing_mode(e, &pool, &mode)?;
+                events::param_changed(e, ManagerParam::PricingMode(pool, mode));
+            }
+            GovernanceAction::SetMaxPriceAge(pool, max_age) => {
+                oracle::write_max_price_age(e, &pool, max_age)?;
+                events::param_changed(e, ManagerParam::MaxPriceAge(pool, max_age));
+            }
+            GovernanceAction::SetOracleAsset(pool, asset) => {
+                oracle::write_oracle_asset(e, &pool, &asset);
+                events::param_changed(e, ManagerParam::OracleAsset(pool, asset));
+            }
+            GovernanceAction::SetAuctionConfig(config) => {
+                auction::write_config(e, Some(&config))?;
+                events::param_changed(e, ManagerParam::AuctionConfig(config));
+            }
+            GovernanceAction::DisableAuctions => {
+                auction::write_config(e, None)?;
+                events::param_changed(e, ManagerParam::AuctionsDisabled);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::LiquidationParams;
+    use crate::storage_types::{LoanV1, LoansDataKeyV1, OracleAssetId};
+    use soroban_sdk::{
+        testutils::{Address as _, Events, Ledger},
         token::{Client as TokenClient, StellarAssetClient},
-        Env,
+        Env, IntoVal,
     };
     mod loan_manager {
         soroban_sdk::contractimport!(
@@ -567,106 +1550,2543 @@ mod tests {
         );
     }
 
+    const TEST_RESERVE_FACTOR: i128 = 1_000_000;
+    const TEST_TIMELOCK_DELAY: u64 = 2 * timelock::DAY_IN_SECONDS;
+
+    // Pools registered directly in the tests are added to the loan manager like deploy_pool does.
+    fn register_pools(e: &Env, loan_manager: &Address, pools: &[&Address]) {
+        e.as_contract(loan_manager, || {
+            let mut pool_addresses: Vec<Address> = e
+                .storage()
+                .persistent()
+                .get(&LoansDataKey::PoolAddresses)
+                .unwrap_or(vec![e]);
+            for pool in pools {
+                pool_addresses.push_back((*pool).clone());
+            }
+            e.storage()
+                .persistent()
+                .set(&LoansDataKey::PoolAddresses, &pool_addresses);
+        });
+    }
+
+    // Pool of a new token with the given ticker, registered to the loan manager.
+    fn register_test_pool(e: &Env, loan_manager: &Address, ticker: &str) -> Address {
+        let token = e.register_stellar_asset_contract_v2(Address::generate(e));
+        let pool = e.register(loan_pool::WASM, ());
+        loan_pool::Client::new(e, &pool).initialize(
+            loan_manager,
+            &loan_pool::Currency {
+                token_address: token.address(),
+                ticker: Symbol::new(e, ticker),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        register_pools(e, loan_manager, &[&pool]);
+        pool
+    }
+
+    fn pass_timelock_delay(e: &Env) {
+        e.ledger().with_mut(|li| {
+            li.timestamp += TEST_TIMELOCK_DELAY;
+        });
+    }
+
+    fn test_interest_rate_model() -> InterestRateModel {
+        InterestRateModel {
+            base_rate: 200_000,
+            kink_utilization: 9_000_000,
+            rate_at_kink: 1_000_000,
+            max_rate: 3_000_000,
+        }
+    }
+
     #[test]
     fn initialize() {
         let e = Env::default();
         let admin = Address::generate(&e);
+        let oracle = Address::generate(&e);
 
         let contract_id = e.register(LoanManager, ());
         let client = LoanManagerClient::new(&e, &contract_id);
 
-        assert!(client.try_initialize(&admin).is_ok());
+        assert!(client
+            .try_initialize(&admin, &oracle, &TEST_TIMELOCK_DELAY)
+            .is_ok());
+        assert_eq!(client.get_oracles(), vec![&e, oracle]);
     }
 
     #[test]
     fn cannot_re_initialize() {
         let e = Env::default();
         let admin = Address::generate(&e);
+        let oracle = Address::generate(&e);
 
         let contract_id = e.register(LoanManager, ());
         let client = LoanManagerClient::new(&e, &contract_id);
 
-        client.initialize(&admin);
+        client.initialize(&admin, &oracle, &TEST_TIMELOCK_DELAY);
 
-        assert!(client.try_initialize(&admin).is_err())
+        assert!(client
+            .try_initialize(&admin, &oracle, &TEST_TIMELOCK_DELAY)
+            .is_err())
     }
 
     #[test]
-    fn deploy_pool() {
-        // ARRANGE
+    fn migrate_upgraded_manager() {
         let e = Env::default();
         e.mock_all_auths();
 
         let admin = Address::generate(&e);
-        let deployer_client = LoanManagerClient::new(&e, &e.register(LoanManager, ()));
-        deployer_client.initialize(&admin);
+        let oracle = Address::generate(&e);
+        let contract_id = e.register(LoanManager, ());
+        let client = LoanManagerClient::new(&e, &contract_id);
+        // A manager upgraded from the first deployment only has an admin.
+        e.as_contract(&contract_id, || {
+            e.storage().persistent().set(&LoansDataKey::Admin, &admin);
+        });
 
-        // Setup test token
-        let token = e.register_stellar_asset_contract_v2(admin.clone());
-        let ticker = Symbol::new(&e, "XLM");
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetTimelockDelay(TEST_TIMELOCK_DELAY)),
+            Err(Ok(Error::TimelockDelayNotFound))
+        );
+        assert_eq!(
+            client.try_initialize(&admin, &oracle, &TEST_TIMELOCK_DELAY),
+            Err(Ok(Error::AlreadyInitialized))
+        );
+        assert_eq!(client.try_get_oracles(), Err(Ok(Error::OracleNotFound)));
+        assert_eq!(
+            client.try_migrate(&oracle, &0),
+            Err(Ok(Error::InvalidTimelockDelay))
+        );
+
+        client.migrate(&oracle, &TEST_TIMELOCK_DELAY);
+
+        assert_eq!(client.get_oracles(), vec![&e, oracle.clone()]);
+        assert_eq!(client.get_timelock_delay(), TEST_TIMELOCK_DELAY);
+        let id = client.queue_action(&GovernanceAction::SetTimelockDelay(
+            timelock::MIN_TIMELOCK_DELAY,
+        ));
+        pass_timelock_delay(&e);
+        client.execute_action(&id);
+        assert_eq!(
+            client.try_migrate(&oracle, &TEST_TIMELOCK_DELAY),
+            Err(Ok(Error::AlreadyMigrated))
+        );
+        assert_eq!(client.get_timelock_delay(), timelock::MIN_TIMELOCK_DELAY);
+    }
+
+    #[test]
+    fn new_manager_cannot_be_migrated() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let oracle = Address::generate(&e);
+        let client = LoanManagerClient::new(&e, &e.register(LoanManager, ()));
+        client.initialize(&admin, &oracle, &TEST_TIMELOCK_DELAY);
+
+        assert_eq!(
+            client.try_migrate(&oracle, &timelock::MIN_TIMELOCK_DELAY),
+            Err(Ok(Error::AlreadyMigrated))
+        );
+    }
+
+    #[test]
+    fn migrate_legacy_loan() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let oracle = Address::generate(&e);
+        let borrower = Address::generate(&e);
+        let borrow_pool = Address::generate(&e);
+        let collateral_pool = Address::generate(&e);
+        let contract_id = e.register(LoanManager, ());
+        let client = LoanManagerClient::new(&e, &contract_id);
+        client.initialize(&admin, &oracle, &TEST_TIMELOCK_DELAY);
+
+        // The first deployment stored one loan per borrower under their address.
+        e.as_contract(&contract_id, || {
+            e.storage().persistent().set(
+                &LoansDataKeyV1::Loan(borrower.clone()),
+                &LoanV1 {
+                    borrower: borrower.clone(),
+                    borrowed_amount: 1_000,
+                    borrowed_from: borrow_pool.clone(),
+                    collateral_amount: 2_000,
+                    collateral_from: collateral_pool.clone(),
+                    health_factor: 16_000_000,
+                    unpaid_interest: 10,
+                    last_accrual: 10_100_000,
+                },
+            );
+        });
+        assert_eq!(client.get_user_loans(&borrower), vec![&e]);
+
+        let loan_id = client.migrate_loan(&borrower);
+
+        assert_eq!(client.get_user_loans(&borrower), vec![&e, loan_id]);
+        assert_eq!(
+            client.get_loan(&loan_id),
+            Loan {
+                id: loan_id,
+                borrower: borrower.clone(),
+                borrowed_amount: 1_000,
+                borrowed_from: borrow_pool,
+                collateral: vec![
+                    &e,
+                    Collateral {
+                        pool: collateral_pool,
+                        amount: 2_000,
+                    },
+                ],
+                health_factor: 16_000_000,
+                unpaid_interest: 10,
+                last_accrual: 10_100_000,
+            }
+        );
+        assert_eq!(client.get_active_loan_count(), 1);
+        e.as_contract(&contract_id, || {
+            assert!(!e
+                .storage()
+                .persistent()
+                .has(&LoansDataKeyV1::Loan(borrower.clone())));
+        });
+        assert_eq!(
+            client.try_migrate_loan(&borrower),
+            Err(Ok(Error::LoanNotFound))
+        );
+    }
+
+    #[test]
+    fn deploy_pool() {
+        // ARRANGE
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let oracle = Address::generate(&e);
+        let deployer_client = LoanManagerClient::new(&e, &e.register(LoanManager, ()));
+        deployer_client.initialize(&admin, &oracle, &TEST_TIMELOCK_DELAY);
+
+        // Setup test token
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let ticker = Symbol::new(&e, "XLM");
+
+        let wasm_hash = e.deployer().upload_contract_wasm(loan_pool::WASM);
+        let salt = BytesN::from_array(&e, &[0; 32]);
+
+        // ACT
+        // Deploy contract using loan_manager as factory
+        let loan_pool_addr = deployer_client.deploy_pool(
+            &wasm_hash,
+            &salt,
+            &token.address(),
+            &ticker,
+            &8_000_000,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        // ASSERT
+        // No authorizations needed - the contract acts as a factory.
+        // assert_eq!(e.auths(), &[]);
+
+        // Invoke contract to check that it is initialized.
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_addr);
+        let pool_balance = loan_pool_client.get_contract_balance();
+        assert_eq!(pool_balance, 0);
+    }
+
+    #[test]
+    fn upgrade_manager_and_pool() {
+        // ARRANGE
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let oracle = Address::generate(&e);
+
+        let deployer_client = LoanManagerClient::new(&e, &e.register(LoanManager, ()));
+        deployer_client.initialize(&admin, &oracle, &TEST_TIMELOCK_DELAY);
+
+        // Setup test token
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let ticker = Symbol::new(&e, "XLM");
+
+        let manager_wasm_hash = e.deployer().upload_contract_wasm(loan_manager::WASM);
+        let pool_wasm_hash = e.deployer().upload_contract_wasm(loan_pool::WASM);
+        let salt = BytesN::from_array(&e, &[0; 32]);
+
+        // ACT
+        deployer_client.deploy_pool(
+            &pool_wasm_hash,
+            &salt,
+            &token.address(),
+            &ticker,
+            &8_000_000,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        let id = deployer_client.queue_action(&GovernanceAction::Upgrade(
+            manager_wasm_hash,
+            pool_wasm_hash,
+        ));
+        pass_timelock_delay(&e);
+        deployer_client.execute_action(&id);
+
+        // ASSERT
+        assert!(deployer_client.get_pending_actions().is_empty());
+    }
+
+    #[test]
+    fn timelocked_action_cannot_be_executed_early() {
+        // ARRANGE
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let oracle = Address::generate(&e);
+        let deployer_client = LoanManagerClient::new(&e, &e.register(LoanManager, ()));
+        deployer_client.initialize(&admin, &oracle, &TEST_TIMELOCK_DELAY);
+
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let wasm_hash = e.deployer().upload_contract_wasm(loan_pool::WASM);
+        let salt = BytesN::from_array(&e, &[0; 32]);
+        let loan_pool_addr = deployer_client.deploy_pool(
+            &wasm_hash,
+            &salt,
+            &token.address(),
+            &Symbol::new(&e, "XLM"),
+            &8_000_000,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        // ACT
+        let id = deployer_client.queue_action(&GovernanceAction::SetReserveFactor(
+            loan_pool_addr.clone(),
+            2_000_000,
+        ));
+
+        // ASSERT
+        let pending = deployer_client.get_pending_actions();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending.get(0).unwrap().eta, TEST_TIMELOCK_DELAY);
+        assert_eq!(
+            deployer_client.try_execute_action(&id),
+            Err(Ok(Error::TimelockNotReady))
+        );
+
+        deployer_client.cancel_action(&id);
+        pass_timelock_delay(&e);
+        assert_eq!(
+            deployer_client.try_execute_action(&id),
+            Err(Ok(Error::ActionNotFound))
+        );
+
+        // Actions that are not executed within the grace period expire.
+        let id = deployer_client.queue_action(&GovernanceAction::SetReserveFactor(
+            loan_pool_addr.clone(),
+            2_000_000,
+        ));
+        e.ledger().with_mut(|li| {
+            li.timestamp += TEST_TIMELOCK_DELAY + timelock::GRACE_PERIOD + 1;
+        });
+        assert_eq!(
+            deployer_client.try_execute_action(&id),
+            Err(Ok(Error::ActionExpired))
+        );
+
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_addr);
+        assert_eq!(loan_pool_client.get_reserve_factor(), TEST_RESERVE_FACTOR);
+    }
+
+    // Loan manager with one registered pool, for checking which actions can be queued.
+    fn setup_governance(e: &Env) -> (LoanManagerClient, Address) {
+        e.mock_all_auths();
+
+        let admin = Address::generate(e);
+        let oracle = Address::generate(e);
+        let contract_id = e.register(LoanManager, ());
+        let client = LoanManagerClient::new(e, &contract_id);
+        client.initialize(&admin, &oracle, &TEST_TIMELOCK_DELAY);
+        let pool = register_test_pool(e, &contract_id, "XLM");
+        (client, pool)
+    }
+
+    #[test]
+    fn queue_upgrade_only_once() {
+        let e = Env::default();
+        let (client, _) = setup_governance(&e);
+        let upgrade = GovernanceAction::Upgrade(
+            BytesN::from_array(&e, &[1; 32]),
+            BytesN::from_array(&e, &[2; 32]),
+        );
+
+        let id = client.queue_action(&upgrade);
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::Upgrade(
+                BytesN::from_array(&e, &[3; 32]),
+                BytesN::from_array(&e, &[4; 32]),
+            )),
+            Err(Ok(Error::UpgradeAlreadyQueued))
+        );
+
+        client.cancel_action(&id);
+        assert!(client.try_queue_action(&upgrade).is_ok());
+
+        // An expired upgrade doesn't block a new one and is dropped from the queue.
+        e.ledger().with_mut(|li| {
+            li.timestamp += TEST_TIMELOCK_DELAY + timelock::GRACE_PERIOD + 1;
+        });
+        assert!(client.get_pending_actions().is_empty());
+        let id = client.queue_action(&upgrade);
+        assert_eq!(client.get_pending_actions().len(), 1);
+        assert_eq!(client.get_pending_actions().get(0).unwrap().id, id);
+    }
+
+    #[test]
+    fn queue_invalid_interest_model() {
+        let e = Env::default();
+        let (client, pool) = setup_governance(&e);
+
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetInterestModel(
+                Address::generate(&e),
+                test_interest_rate_model(),
+            )),
+            Err(Ok(Error::PoolNotFound))
+        );
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetInterestModel(
+                pool.clone(),
+                InterestRateModel {
+                    kink_utilization: DECIMAL,
+                    ..test_interest_rate_model()
+                },
+            )),
+            Err(Ok(Error::InvalidInterestRateModel))
+        );
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetInterestModel(
+                pool.clone(),
+                InterestRateModel {
+                    base_rate: 2_000_000,
+                    ..test_interest_rate_model()
+                },
+            )),
+            Err(Ok(Error::InvalidInterestRateModel))
+        );
+        assert!(client
+            .try_queue_action(&GovernanceAction::SetInterestModel(
+                pool,
+                test_interest_rate_model(),
+            ))
+            .is_ok());
+    }
+
+    #[test]
+    fn queue_invalid_reserve_factor() {
+        let e = Env::default();
+        let (client, pool) = setup_governance(&e);
+
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetReserveFactor(
+                Address::generate(&e),
+                TEST_RESERVE_FACTOR,
+            )),
+            Err(Ok(Error::PoolNotFound))
+        );
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetReserveFactor(
+                pool.clone(),
+                DECIMAL + 1
+            )),
+            Err(Ok(Error::InvalidReserveFactor))
+        );
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetReserveFactor(pool.clone(), -1)),
+            Err(Ok(Error::InvalidReserveFactor))
+        );
+        assert!(client
+            .try_queue_action(&GovernanceAction::SetReserveFactor(pool, DECIMAL))
+            .is_ok());
+    }
+
+    #[test]
+    fn queue_invalid_pool_caps() {
+        let e = Env::default();
+        let (client, pool) = setup_governance(&e);
+
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetPoolCaps(
+                Address::generate(&e),
+                None,
+                None
+            )),
+            Err(Ok(Error::PoolNotFound))
+        );
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetPoolCaps(pool.clone(), Some(-1), None)),
+            Err(Ok(Error::InvalidCap))
+        );
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetPoolCaps(pool.clone(), None, Some(-1))),
+            Err(Ok(Error::InvalidCap))
+        );
+        assert!(client
+            .try_queue_action(&GovernanceAction::SetPoolCaps(pool, Some(0), None))
+            .is_ok());
+    }
+
+    #[test]
+    fn queue_invalid_flash_loan_fee() {
+        let e = Env::default();
+        let (client, pool) = setup_governance(&e);
+
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetFlashLoanFee(Address::generate(&e), 0)),
+            Err(Ok(Error::PoolNotFound))
+        );
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetFlashLoanFee(pool.clone(), -1)),
+            Err(Ok(Error::InvalidFlashLoanFee))
+        );
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetFlashLoanFee(
+                pool.clone(),
+                DECIMAL + 1
+            )),
+            Err(Ok(Error::InvalidFlashLoanFee))
+        );
+        assert!(client
+            .try_queue_action(&GovernanceAction::SetFlashLoanFee(pool, 90_000))
+            .is_ok());
+    }
+
+    #[test]
+    fn queue_invalid_liquidation_params() {
+        let e = Env::default();
+        let (client, pool) = setup_governance(&e);
+        let params = LiquidationParams {
+            liquidation_threshold: 8_000_000,
+            close_factor: 5_000_000,
+            liquidation_bonus: 500_000,
+        };
+
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetLiquidationParams(
+                Address::generate(&e),
+                params.clone(),
+            )),
+            Err(Ok(Error::PoolNotFound))
+        );
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetLiquidationParams(
+                pool.clone(),
+                LiquidationParams {
+                    close_factor: DECIMAL + 1,
+                    ..params.clone()
+                },
+            )),
+            Err(Ok(Error::InvalidLiquidationParams))
+        );
+        // 98% threshold with a 5% bonus would seize more than the collateral is worth.
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetLiquidationParams(
+                pool.clone(),
+                LiquidationParams {
+                    liquidation_threshold: 9_800_000,
+                    ..params.clone()
+                },
+            )),
+            Err(Ok(Error::InvalidLiquidationParams))
+        );
+        assert!(client
+            .try_queue_action(&GovernanceAction::SetLiquidationParams(pool, params))
+            .is_ok());
+    }
+
+    #[test]
+    fn queue_disable_auctions_when_disabled() {
+        let e = Env::default();
+        let (client, _) = setup_governance(&e);
+
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::DisableAuctions),
+            Err(Ok(Error::AuctionsDisabled))
+        );
+    }
+
+    #[test]
+    fn set_timelock_delay() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let oracle = Address::generate(&e);
+        let client = LoanManagerClient::new(&e, &e.register(LoanManager, ()));
+        client.initialize(&admin, &oracle, &TEST_TIMELOCK_DELAY);
+
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetTimelockDelay(
+                timelock::MAX_TIMELOCK_DELAY + 1
+            )),
+            Err(Ok(Error::InvalidTimelockDelay))
+        );
+        // A zero delay would let actions be executed in the same ledger they are queued.
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetTimelockDelay(0)),
+            Err(Ok(Error::InvalidTimelockDelay))
+        );
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetTimelockDelay(
+                timelock::MIN_TIMELOCK_DELAY - 1
+            )),
+            Err(Ok(Error::InvalidTimelockDelay))
+        );
+
+        let id = client.queue_action(&GovernanceAction::SetTimelockDelay(
+            timelock::MIN_TIMELOCK_DELAY,
+        ));
+        assert_eq!(
+            client.try_execute_action(&id),
+            Err(Ok(Error::TimelockNotReady))
+        );
+        pass_timelock_delay(&e);
+        client.execute_action(&id);
+        assert_eq!(client.get_timelock_delay(), timelock::MIN_TIMELOCK_DELAY);
+
+        // The shorter delay applies to actions queued after the change.
+        let id = client.queue_action(&GovernanceAction::SetTimelockDelay(TEST_TIMELOCK_DELAY));
+        e.ledger().with_mut(|li| {
+            li.timestamp += timelock::MIN_TIMELOCK_DELAY;
+        });
+        client.execute_action(&id);
+        assert_eq!(client.get_timelock_delay(), TEST_TIMELOCK_DELAY);
+
+        let client = LoanManagerClient::new(&e, &e.register(LoanManager, ()));
+        assert_eq!(
+            client.try_initialize(&admin, &oracle, &0),
+            Err(Ok(Error::InvalidTimelockDelay))
+        );
+    }
+
+    #[test]
+    fn set_oracles() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let oracle = Address::generate(&e);
+        let client = LoanManagerClient::new(&e, &e.register(LoanManager, ()));
+        client.initialize(&admin, &oracle, &TEST_TIMELOCK_DELAY);
+
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetOracles(vec![&e])),
+            Err(Ok(Error::InvalidOracles))
+        );
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetOracles(vec![
+                &e,
+                oracle.clone(),
+                oracle.clone()
+            ])),
+            Err(Ok(Error::InvalidOracles))
+        );
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetMaxPriceDeviation(0)),
+            Err(Ok(Error::InvalidMaxPriceDeviation))
+        );
+
+        let new_oracles = vec![&e, oracle.clone(), Address::generate(&e)];
+        let id = client.queue_action(&GovernanceAction::SetOracles(new_oracles.clone()));
+        assert_eq!(client.get_oracles(), vec![&e, oracle]);
+
+        pass_timelock_delay(&e);
+        client.execute_action(&id);
+        assert_eq!(client.get_oracles(), new_oracles);
+    }
+
+    #[test]
+    fn set_oracle() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let oracle = Address::generate(&e);
+        let fallback = Address::generate(&e);
+        let client = LoanManagerClient::new(&e, &e.register(LoanManager, ()));
+        client.initialize(&admin, &oracle, &TEST_TIMELOCK_DELAY);
+        assert_eq!(client.get_oracle(), oracle);
+
+        let id = client.queue_action(&GovernanceAction::SetOracles(vec![
+            &e,
+            oracle.clone(),
+            fallback.clone(),
+        ]));
+        pass_timelock_delay(&e);
+        client.execute_action(&id);
+
+        // Setting the single oracle only replaces the primary one.
+        let new_oracle = Address::generate(&e);
+        let id = client.queue_action(&GovernanceAction::SetOracle(new_oracle.clone()));
+        assert_eq!(client.get_oracle(), oracle);
+        pass_timelock_delay(&e);
+        client.execute_action(&id);
+        assert_eq!(client.get_oracle(), new_oracle);
+        assert_eq!(
+            client.get_oracles(),
+            vec![&e, new_oracle.clone(), fallback.clone()]
+        );
+
+        // A fallback that becomes the primary is not listed twice.
+        let id = client.queue_action(&GovernanceAction::SetOracle(fallback.clone()));
+        pass_timelock_delay(&e);
+        client.execute_action(&id);
+        assert_eq!(client.get_oracles(), vec![&e, fallback]);
+    }
+
+    #[test]
+    fn aggregated_prices() {
+        let e = Env::default();
+        e.mock_all_auths();
+        e.ledger().with_mut(|li| {
+            li.timestamp = 1_000_000;
+        });
+
+        let admin = Address::generate(&e);
+        let primary_addr = e.register(oracle::WASM, ());
+        let secondary_addr = e.register(oracle::WASM, ());
+        let third_addr = e.register(oracle::WASM, ());
+        let primary = oracle::Client::new(&e, &primary_addr);
+        let secondary = oracle::Client::new(&e, &secondary_addr);
+        let third = oracle::Client::new(&e, &third_addr);
+        let contract_id = e.register(LoanManager, ());
+        let client = LoanManagerClient::new(&e, &contract_id);
+        client.initialize(&admin, &primary_addr, &TEST_TIMELOCK_DELAY);
+
+        let xlm = register_test_pool(&e, &contract_id, "XLM");
+        let asset = oracle::Asset::Other(Symbol::new(&e, "XLM"));
+        primary.set_price(&asset, &1_000);
+        secondary.set_price(&asset, &1_020);
+        third.set_price(&asset, &1_030);
+
+        let id = client.queue_action(&GovernanceAction::SetOracles(vec![
+            &e,
+            primary_addr.clone(),
+            secondary_addr.clone(),
+        ]));
+        pass_timelock_delay(&e);
+        client.execute_action(&id);
+
+        // The median of two prices is their average.
+        assert_eq!(client.get_price(&xlm), 1_010);
+
+        // The secondary source is used when the primary is stale or broken.
+        primary.set_price_age(&(oracle::DEFAULT_MAX_PRICE_AGE + 1));
+        assert_eq!(client.get_price(&xlm), 1_020);
+        primary.set_price_age(&0);
+        primary.set_price(&asset, &0);
+        assert_eq!(client.get_price(&xlm), 1_020);
+
+        // The primary's error is returned when no source can be used.
+        secondary.set_price_age(&(oracle::DEFAULT_MAX_PRICE_AGE + 1));
+        assert_eq!(client.try_get_price(&xlm), Err(Ok(Error::InvalidPrice)));
+
+        // Sources that disagree too much are rejected.
+        primary.set_price(&asset, &1_000);
+        secondary.set_price_age(&0);
+        third.set_price(&asset, &1_200);
+        let id = client.queue_action(&GovernanceAction::SetOracles(vec![
+            &e,
+            primary_addr,
+            secondary_addr,
+            third_addr,
+        ]));
+        pass_timelock_delay(&e);
+        client.execute_action(&id);
+        assert_eq!(client.try_get_price(&xlm), Err(Ok(Error::PriceDeviation)));
+
+        let id = client.queue_action(&GovernanceAction::SetMaxPriceDeviation(2_000_000));
+        pass_timelock_delay(&e);
+        client.execute_action(&id);
+        assert_eq!(client.get_max_price_deviation(), 2_000_000);
+        assert_eq!(client.get_price(&xlm), 1_020);
+    }
+
+    #[test]
+    fn stale_and_invalid_prices() {
+        let e = Env::default();
+        e.mock_all_auths();
+        e.ledger().with_mut(|li| {
+            li.timestamp = 1_000_000;
+        });
+
+        let admin = Address::generate(&e);
+        let reflector_addr = e.register(oracle::WASM, ());
+        let reflector_client = oracle::Client::new(&e, &reflector_addr);
+        let contract_id = e.register(LoanManager, ());
+        let client = LoanManagerClient::new(&e, &contract_id);
+        client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+
+        let xlm = register_test_pool(&e, &contract_id, "XLM");
+        let other_xlm = register_test_pool(&e, &contract_id, "XLM");
+        let usdc = register_test_pool(&e, &contract_id, "USDC");
+        assert_eq!(client.get_price(&xlm), 10_i128.pow(oracle::PRICE_DECIMALS));
+
+        // Prices older than the default max age can't be used.
+        reflector_client.set_price_age(&(oracle::DEFAULT_MAX_PRICE_AGE + 1));
+        assert_eq!(client.try_get_price(&xlm), Err(Ok(Error::StalePrice)));
+
+        // The max age can be raised for a single pool.
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetMaxPriceAge(xlm.clone(), 0)),
+            Err(Ok(Error::InvalidMaxPriceAge))
+        );
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetMaxPriceAge(
+                Address::generate(&e),
+                3_600
+            )),
+            Err(Ok(Error::PoolNotFound))
+        );
+        let id = client.queue_action(&GovernanceAction::SetMaxPriceAge(xlm.clone(), 3_600));
+        pass_timelock_delay(&e);
+        client.execute_action(&id);
+        assert_eq!(client.get_max_price_age(&xlm), 3_600);
+        assert_eq!(client.get_price(&xlm), 10_i128.pow(oracle::PRICE_DECIMALS));
+        assert_eq!(client.try_get_price(&usdc), Err(Ok(Error::StalePrice)));
+        // Another pool of the same ticker keeps its own max age.
+        assert_eq!(
+            client.get_max_price_age(&other_xlm),
+            oracle::DEFAULT_MAX_PRICE_AGE
+        );
+        assert_eq!(client.try_get_price(&other_xlm), Err(Ok(Error::StalePrice)));
+
+        // Zero and negative prices are rejected.
+        let usdc_asset = oracle::Asset::Other(Symbol::new(&e, "USDC"));
+        reflector_client.set_price_age(&0);
+        reflector_client.set_price(&usdc_asset, &0);
+        assert_eq!(client.try_get_price(&usdc), Err(Ok(Error::InvalidPrice)));
+        reflector_client.set_price(&usdc_asset, &-5);
+        assert_eq!(client.try_get_price(&usdc), Err(Ok(Error::InvalidPrice)));
+    }
+
+    #[test]
+    fn pricing_modes() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let reflector_addr = e.register(oracle::WASM, ());
+        let reflector_client = oracle::Client::new(&e, &reflector_addr);
+        let contract_id = e.register(LoanManager, ());
+        let client = LoanManagerClient::new(&e, &contract_id);
+        client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+
+        let collateral_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        loan_pool::Client::new(&e, &collateral_pool_id).initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: collateral_token.address(),
+                ticker: Symbol::new(&e, "USDC"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        let borrow_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let borrow_pool_id = e.register(loan_pool::WASM, ());
+        loan_pool::Client::new(&e, &borrow_pool_id).initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: borrow_token.address(),
+                ticker: Symbol::new(&e, "XLM"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        let unit = 10_i128.pow(oracle::PRICE_DECIMALS);
+        let xlm = Symbol::new(&e, "XLM");
+        let usdc = Symbol::new(&e, "USDC");
+        let collateral = vec![
+            &e,
+            Collateral {
+                pool: collateral_pool_id.clone(),
+                amount: 2_000,
+            },
+        ];
+
+        // The spot price of the collateral is pushed up for a single ledger.
+        reflector_client.set_price(&oracle::Asset::Other(usdc.clone()), &(10 * unit));
+        reflector_client.set_twap(&oracle::Asset::Other(usdc.clone()), &unit);
+        reflector_client.set_twap(&oracle::Asset::Other(xlm.clone()), &(2 * unit));
+        register_pools(&e, &contract_id, &[&collateral_pool_id, &borrow_pool_id]);
+        assert_eq!(
+            client.get_pricing_mode(&collateral_pool_id),
+            PricingMode::Spot
+        );
+        assert_eq!(
+            client.calculate_health_factor(&borrow_pool_id, &1_000, &collateral),
+            160_000_000
+        );
+
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetPricingMode(
+                collateral_pool_id.clone(),
+                PricingMode::Twap(0)
+            )),
+            Err(Ok(Error::InvalidPricingMode))
+        );
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetPricingMode(
+                collateral_pool_id.clone(),
+                PricingMode::Conservative(oracle::MAX_TWAP_RECORDS + 1)
+            )),
+            Err(Ok(Error::InvalidPricingMode))
+        );
+
+        // Collateral is valued at the lower of spot and TWAP.
+        let id = client.queue_action(&GovernanceAction::SetPricingMode(
+            collateral_pool_id.clone(),
+            PricingMode::Conservative(5),
+        ));
+        pass_timelock_delay(&e);
+        client.execute_action(&id);
+        assert_eq!(
+            client.get_pricing_mode(&collateral_pool_id),
+            PricingMode::Conservative(5)
+        );
+        assert_eq!(
+            client.calculate_health_factor(&borrow_pool_id, &1_000, &collateral),
+            16_000_000
+        );
+
+        // Debt priced with TWAP.
+        let id = client.queue_action(&GovernanceAction::SetPricingMode(
+            borrow_pool_id.clone(),
+            PricingMode::Twap(5),
+        ));
+        pass_timelock_delay(&e);
+        client.execute_action(&id);
+        assert_eq!(
+            client.calculate_health_factor(&borrow_pool_id, &1_000, &collateral),
+            8_000_000
+        );
+        // The spot price is still available as is.
+        assert_eq!(client.get_price(&borrow_pool_id), unit);
+    }
+
+    #[test]
+    fn normalize_decimals() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let reflector_addr = e.register(oracle::WASM, ());
+        let reflector_client = oracle::Client::new(&e, &reflector_addr);
+        let contract_id = e.register(LoanManager, ());
+        let client = LoanManagerClient::new(&e, &contract_id);
+        client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+
+        // A 6 decimal token as collateral and a 7 decimal token borrowed.
+        let collateral_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        loan_pool::Client::new(&e, &collateral_pool_id).initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: collateral_token.address(),
+                ticker: Symbol::new(&e, "USDC"),
+                decimals: 6,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        let borrow_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let borrow_pool_id = e.register(loan_pool::WASM, ());
+        loan_pool::Client::new(&e, &borrow_pool_id).initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: borrow_token.address(),
+                ticker: Symbol::new(&e, "XLM"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        // Oracle with 8 decimals: USDC at 1.0 and XLM at 0.1.
+        let usdc = Symbol::new(&e, "USDC");
+        reflector_client.set_decimals(&8);
+        reflector_client.set_price(&oracle::Asset::Other(usdc.clone()), &100_000_000);
+        reflector_client.set_price(&oracle::Asset::Other(Symbol::new(&e, "XLM")), &10_000_000);
+        assert_eq!(
+            client.get_price(&collateral_pool_id),
+            10_i128.pow(oracle::PRICE_DECIMALS)
+        );
+
+        // 1 USDC with a 0.8 collateral factor against 4 XLM.
+        let collateral = vec![
+            &e,
+            Collateral {
+                pool: collateral_pool_id,
+                amount: 1_000_000,
+            },
+        ];
+        assert_eq!(
+            client.calculate_health_factor(&borrow_pool_id, &40_000_000, &collateral),
+            20_000_000
+        );
+    }
+
+    #[test]
+    fn oracle_assets() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let reflector_addr = e.register(oracle::WASM, ());
+        let dedicated_addr = e.register(oracle::WASM, ());
+        let contract_id = e.register(LoanManager, ());
+        let client = LoanManagerClient::new(&e, &contract_id);
+        client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+
+        let collateral_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let collateral_pool_id = client.deploy_pool(
+            &e.deployer().upload_contract_wasm(loan_pool::WASM),
+            &BytesN::from_array(&e, &[0; 32]),
+            &collateral_token.address(),
+            &Symbol::new(&e, "XLM"),
+            &8_000_000,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        let borrow_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let borrow_pool_id = e.register(loan_pool::WASM, ());
+        loan_pool::Client::new(&e, &borrow_pool_id).initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: borrow_token.address(),
+                ticker: Symbol::new(&e, "USDC"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        // Deployed pools are priced by their ticker from the shared oracles.
+        assert_eq!(
+            client.get_oracle_asset(&collateral_pool_id),
+            oracle::ticker_asset(Symbol::new(&e, "XLM"))
+        );
+        let collateral = vec![
+            &e,
+            Collateral {
+                pool: collateral_pool_id.clone(),
+                amount: 1_000,
+            },
+        ];
+        assert_eq!(
+            client.calculate_health_factor(&borrow_pool_id, &400, &collateral),
+            20_000_000
+        );
+
+        let stellar_asset = OracleAsset {
+            asset: OracleAssetId::Stellar(collateral_token.address()),
+            oracle: Some(dedicated_addr.clone()),
+        };
+        assert_eq!(
+            client.try_queue_action(&GovernanceAction::SetOracleAsset(
+                Address::generate(&e),
+                stellar_asset.clone()
+            )),
+            Err(Ok(Error::PoolNotFound))
+        );
+
+        // The dedicated oracle prices the token by its address.
+        let id = client.queue_action(&GovernanceAction::SetOracleAsset(
+            collateral_pool_id.clone(),
+            stellar_asset.clone(),
+        ));
+        pass_timelock_delay(&e);
+        client.execute_action(&id);
+        assert_eq!(client.get_oracle_asset(&collateral_pool_id), stellar_asset);
+        oracle::Client::new(&e, &dedicated_addr).set_price(
+            &oracle::Asset::Stellar(collateral_token.address()),
+            &(2 * 10_i128.pow(oracle::PRICE_DECIMALS)),
+        );
+        assert_eq!(
+            client.calculate_health_factor(&borrow_pool_id, &400, &collateral),
+            40_000_000
+        );
+        assert_eq!(
+            client.get_price(&collateral_pool_id),
+            2 * 10_i128.pow(oracle::PRICE_DECIMALS)
+        );
+    }
+
+    #[test]
+    fn set_interest_model() {
+        // ARRANGE
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let oracle = Address::generate(&e);
+        let deployer_client = LoanManagerClient::new(&e, &e.register(LoanManager, ()));
+        deployer_client.initialize(&admin, &oracle, &TEST_TIMELOCK_DELAY);
+
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let ticker = Symbol::new(&e, "XLM");
+
+        let wasm_hash = e.deployer().upload_contract_wasm(loan_pool::WASM);
+        let salt = BytesN::from_array(&e, &[0; 32]);
+        let loan_pool_addr = deployer_client.deploy_pool(
+            &wasm_hash,
+            &salt,
+            &token.address(),
+            &ticker,
+            &8_000_000,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        // ACT
+        let model = InterestRateModel {
+            base_rate: 100_000,
+            kink_utilization: 8_000_000,
+            rate_at_kink: 800_000,
+            max_rate: 5_000_000,
+        };
+        let id = deployer_client.queue_action(&GovernanceAction::SetInterestModel(
+            loan_pool_addr.clone(),
+            model.clone(),
+        ));
+        pass_timelock_delay(&e);
+        deployer_client.execute_action(&id);
+
+        // ASSERT
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_addr);
+        assert_eq!(loan_pool_client.get_interest_model(), model.into());
+    }
+
+    #[test]
+    fn create_loan() {
+        // ARRANGE
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+
+        let admin = Address::generate(&e);
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
+        let loan_token_client = TokenClient::new(&e, &loan_token.address());
+        loan_asset.mint(&admin, &1000);
+        let loan_currency = loan_pool::Currency {
+            token_address: loan_token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let admin2 = Address::generate(&e);
+        let collateral_token = e.register_stellar_asset_contract_v2(admin2.clone());
+        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
+        let collateral_token_client = TokenClient::new(&e, &collateral_token.address());
+        let collateral_currency = loan_pool::Currency {
+            token_address: collateral_token.address(),
+            ticker: Symbol::new(&e, "USDC"),
+            decimals: 7,
+        };
+
+        // Register mock Reflector contract.
+        let reflector_addr = e.register(oracle::WASM, ());
+
+        // Mint the user some coins
+        let user = Address::generate(&e);
+        collateral_asset.mint(&user, &1000);
+
+        assert_eq!(collateral_token_client.balance(&user), 1000);
+
+        // Set up a loan pool with funds for borrowing.
+        let loan_pool_id = e.register(loan_pool::WASM, ());
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+
+        // Set up a loan_pool for the collaterals.
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool_id);
+
+        // Register loan manager contract.
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
+
+        // ACT
+        // Initialize the loan pool and deposit some of the admin's funds.
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &1000);
+
+        collateral_pool_client.initialize(
+            &contract_id,
+            &collateral_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        let loan_id = contract_client.create_loan(
+            &user,
+            &10,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 100,
+                },
+            ],
+        );
+
+        // ASSERT
+        assert_eq!(loan_id, 0);
+        assert_eq!(contract_client.get_user_loans(&user), vec![&e, 0]);
+        assert_eq!(loan_token_client.balance(&user), 10);
+        assert_eq!(collateral_token_client.balance(&user), 900);
+    }
+
+    #[test]
+    fn cannot_borrow_from_frozen_pool() {
+        // ARRANGE
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+
+        let admin = Address::generate(&e);
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
+        loan_asset.mint(&admin, &1000);
+        let loan_currency = loan_pool::Currency {
+            token_address: loan_token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let collateral_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
+        let collateral_currency = loan_pool::Currency {
+            token_address: collateral_token.address(),
+            ticker: Symbol::new(&e, "USDC"),
+            decimals: 7,
+        };
+
+        // Register mock Reflector contract.
+        let reflector_addr = e.register(oracle::WASM, ());
+
+        let user = Address::generate(&e);
+        collateral_asset.mint(&user, &1000);
+
+        let loan_pool_id = e.register(loan_pool::WASM, ());
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool_id);
+
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &1000);
+        collateral_pool_client.initialize(
+            &contract_id,
+            &collateral_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        // ACT
+        contract_client.set_pool_status(&loan_pool_id, &PoolStatus::BorrowFrozen);
+
+        // ASSERT
+        assert!(contract_client
+            .try_create_loan(
+                &user,
+                &10,
+                &loan_pool_id,
+                &vec![
+                    &e,
+                    Collateral {
+                        pool: collateral_pool_id.clone(),
+                        amount: 100
+                    }
+                ]
+            )
+            .is_err());
+        assert_eq!(
+            loan_pool_client.get_status(),
+            loan_pool::PoolStatus::BorrowFrozen
+        );
+    }
+
+    #[test]
+    fn cannot_write_off_solvent_loan() {
+        // ARRANGE
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+
+        let admin = Address::generate(&e);
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
+        loan_asset.mint(&admin, &1000);
+        let loan_currency = loan_pool::Currency {
+            token_address: loan_token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let collateral_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
+        let collateral_currency = loan_pool::Currency {
+            token_address: collateral_token.address(),
+            ticker: Symbol::new(&e, "USDC"),
+            decimals: 7,
+        };
+
+        // Register mock Reflector contract.
+        let reflector_addr = e.register(oracle::WASM, ());
+
+        let user = Address::generate(&e);
+        collateral_asset.mint(&user, &1000);
+
+        let loan_pool_id = e.register(loan_pool::WASM, ());
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool_id);
+
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
+
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &1000);
+        collateral_pool_client.initialize(
+            &contract_id,
+            &collateral_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        let loan_id = contract_client.create_loan(
+            &user,
+            &10,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 100,
+                },
+            ],
+        );
+
+        // ACT
+        let result = contract_client.try_write_off_bad_debt(&loan_id);
+
+        // ASSERT
+        assert_eq!(result, Err(Ok(Error::LoanNotInsolvent)));
+        assert_eq!(contract_client.get_loan(&loan_id).borrowed_amount, 10);
+        assert_eq!(loan_pool_client.get_contract_balance(), 1000);
+    }
+
+    #[test]
+    fn write_off_bad_debt() {
+        // ARRANGE
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+
+        let admin = Address::generate(&e);
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
+        let loan_token_client = TokenClient::new(&e, &loan_token.address());
+        loan_asset.mint(&admin, &2_000);
+        let loan_currency = loan_pool::Currency {
+            token_address: loan_token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let usdc = Symbol::new(&e, "USDC");
+        let collateral_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
+        let collateral_token_client = TokenClient::new(&e, &collateral_token.address());
+        let collateral_currency = loan_pool::Currency {
+            token_address: collateral_token.address(),
+            ticker: usdc.clone(),
+            decimals: 7,
+        };
+
+        // Register mock Reflector contract.
+        let reflector_addr = e.register(oracle::WASM, ());
+        let reflector_client = oracle::Client::new(&e, &reflector_addr);
+
+        let user = Address::generate(&e);
+        collateral_asset.mint(&user, &200);
+
+        let loan_pool_id = e.register(loan_pool::WASM, ());
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool_id);
+
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
+
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &1_000);
+        collateral_pool_client.initialize(
+            &contract_id,
+            &collateral_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        let loan_id = contract_client.create_loan(
+            &user,
+            &100,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 200,
+                },
+            ],
+        );
+
+        // ACT
+        // The collateral drops to a quarter of its value and covers only half of the debt.
+        reflector_client.set_price(&oracle::Asset::Other(usdc), &25_000_000_000_000);
+        let (written_off, socialized) = contract_client.write_off_bad_debt(&loan_id);
+
+        // ASSERT
+        assert_eq!(written_off, 50);
+        assert_eq!(socialized, 50);
+        assert_eq!(
+            contract_client.try_get_loan(&loan_id),
+            Err(Ok(Error::LoanNotFound))
+        );
+        // The admin paid for the collateral, so the suppliers only lose the shortfall.
+        assert_eq!(loan_token_client.balance(&admin), 950);
+        assert_eq!(collateral_token_client.balance(&admin), 200);
+        assert_eq!(loan_pool_client.get_contract_balance(), 950);
+        assert_eq!(loan_pool_client.get_available_balance(), 950);
+        assert_eq!(loan_pool_client.get_exchange_rate(), 9_500_000);
+        assert_eq!(loan_pool_client.get_user_positions(&user).liabilities, 0);
+        assert_eq!(
+            collateral_pool_client.get_user_positions(&user).collateral,
+            0
+        );
+    }
+
+    #[test]
+    fn write_off_bad_debt_at_spot_prices() {
+        // ARRANGE
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+
+        let admin = Address::generate(&e);
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
+        let loan_token_client = TokenClient::new(&e, &loan_token.address());
+        loan_asset.mint(&admin, &2_000);
+        let loan_currency = loan_pool::Currency {
+            token_address: loan_token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let usdc = Symbol::new(&e, "USDC");
+        let collateral_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
+        let collateral_token_client = TokenClient::new(&e, &collateral_token.address());
+        let collateral_currency = loan_pool::Currency {
+            token_address: collateral_token.address(),
+            ticker: usdc.clone(),
+            decimals: 7,
+        };
+
+        // Register mock Reflector contract.
+        let reflector_addr = e.register(oracle::WASM, ());
+        let reflector_client = oracle::Client::new(&e, &reflector_addr);
+
+        let user = Address::generate(&e);
+        collateral_asset.mint(&user, &200);
+
+        let loan_pool_id = e.register(loan_pool::WASM, ());
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool_id);
+
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
+        let id = contract_client.queue_action(&GovernanceAction::SetPricingMode(
+            collateral_pool_id.clone(),
+            PricingMode::Conservative(5),
+        ));
+        pass_timelock_delay(&e);
+        contract_client.execute_action(&id);
+
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &1_000);
+        collateral_pool_client.initialize(
+            &contract_id,
+            &collateral_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        let loan_id = contract_client.create_loan(
+            &user,
+            &100,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 200,
+                },
+            ],
+        );
+
+        // ACT
+        // The average price of the collateral drops to a quarter while the spot price holds. The
+        // collateral is still worth twice the debt at spot, so nothing is written off.
+        reflector_client.set_twap(&oracle::Asset::Other(usdc), &25_000_000_000_000);
+        let result = contract_client.try_write_off_bad_debt(&loan_id);
+
+        // ASSERT
+        assert_eq!(result, Err(Ok(Error::LoanNotInsolvent)));
+        assert_eq!(loan_token_client.balance(&admin), 1_000);
+        assert_eq!(collateral_token_client.balance(&admin), 0);
+        assert_eq!(collateral_token_client.balance(&user), 0);
+    }
+
+    #[test]
+    fn add_interest() {
+        // ARRANGE
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000;
+            li.timestamp = 1;
+            li.min_persistent_entry_ttl = 10_000_000;
+            li.min_temp_entry_ttl = 1_000_000;
+            li.max_entry_ttl = 1_000_001;
+        });
+
+        let admin = Address::generate(&e);
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
+        loan_asset.mint(&admin, &1_000_000);
+        let loan_currency = loan_pool::Currency {
+            token_address: loan_token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let admin2 = Address::generate(&e);
+        let collateral_token = e.register_stellar_asset_contract_v2(admin2.clone());
+        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
+        let collateral_token_client = TokenClient::new(&e, &collateral_token.address());
+        let collateral_currency = loan_pool::Currency {
+            token_address: collateral_token.address(),
+            ticker: Symbol::new(&e, "USDC"),
+            decimals: 7,
+        };
+
+        // Register mock Reflector contract.
+        let reflector_addr = e.register(oracle::WASM, ());
+
+        // Mint the user some coins
+        let user = Address::generate(&e);
+        collateral_asset.mint(&user, &1_000_000);
+
+        assert_eq!(collateral_token_client.balance(&user), 1_000_000);
+
+        // Set up a loan pool with funds for borrowing.
+        let loan_pool_id = e.register(loan_pool::WASM, ());
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+
+        // Set up a loan_pool for the collaterals.
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool_id);
+
+        // Register loan manager contract.
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
+
+        // ACT
+        // Initialize the loan pool and deposit some of the admin's funds.
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &10_001);
+
+        collateral_pool_client.initialize(
+            &contract_id,
+            &collateral_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        // Create a loan.
+        let loan_id = contract_client.create_loan(
+            &user,
+            &10_000,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 100_000,
+                },
+            ],
+        );
+
+        let user_loan = contract_client.get_loan(&loan_id);
+
+        assert_eq!(user_loan.borrowed_amount, 10_000);
+        assert_eq!(collateral_token_client.balance(&user), 900_000);
+
+        // Here borrowed amount should be the same as time has not moved. add_interest() is only called to store the LastUpdate sequence number.
+        assert_eq!(user_loan.borrowed_amount, 10_000);
+        assert_eq!(user_loan.health_factor, 80_000_000);
+        assert_eq!(collateral_token_client.balance(&user), 900_000);
+
+        // Move time
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000 + 100_000;
+            li.timestamp = 1 + 31_556_926;
+        });
+
+        // A new instance of reflector mock needs to be created, they only live for one ledger.
+        e.register_at(&reflector_addr, oracle::WASM, ());
+
+        // The projected loan has the interest up to now without anything being stored.
+        let projected_loan = contract_client.get_loan_health(&user).get(0).unwrap();
+        assert_eq!(projected_loan.borrowed_amount, 12_998);
+        assert_eq!(projected_loan.health_factor, 61_547_930);
+        assert_eq!(contract_client.get_loan(&loan_id).borrowed_amount, 10_000);
+
+        contract_client.add_interest(&loan_id);
+
+        let user_loan = contract_client.get_loan(&loan_id);
+
+        assert_eq!(user_loan.borrowed_amount, 12_998);
+        assert_eq!(user_loan.health_factor, 61_547_930);
+        assert_eq!(user_loan.collateral.get(0).unwrap().amount, 100_000);
+        assert_eq!(user_loan, projected_loan);
+        assert_eq!(contract_client.get_loan_health(&user), vec![&e, user_loan]);
+    }
+
+    #[test]
+    fn loan_health_of_several_loans() {
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000;
+            li.timestamp = 1;
+            li.min_persistent_entry_ttl = 1_000_000;
+            li.min_temp_entry_ttl = 1_000_000;
+            li.max_entry_ttl = 1_000_001;
+        });
+
+        let admin = Address::generate(&e);
+        let user = Address::generate(&e);
+        let reflector_addr = e.register(oracle::WASM, ());
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+
+        // Two pools to borrow from and one for the collateral.
+        let mut pools = Vec::new(&e);
+        for ticker in ["XLM", "EURC", "USDC"] {
+            let token = e.register_stellar_asset_contract_v2(admin.clone());
+            let asset = StellarAssetClient::new(&e, &token.address());
+            asset.mint(&admin, &1_000_000);
+            asset.mint(&user, &1_000_000);
+            let pool_id = e.register(loan_pool::WASM, ());
+            let pool_client = loan_pool::Client::new(&e, &pool_id);
+            pool_client.initialize(
+                &contract_id,
+                &loan_pool::Currency {
+                    token_address: token.address(),
+                    ticker: Symbol::new(&e, ticker),
+                    decimals: 7,
+                },
+                &8_000_000,
+                &test_interest_rate_model().into(),
+                &TEST_RESERVE_FACTOR,
+            );
+            pool_client.deposit(&admin, &1_000_000);
+            pools.push_back(pool_id);
+        }
+        let xlm_pool_id = pools.get(0).unwrap();
+        let eurc_pool_id = pools.get(1).unwrap();
+        let usdc_pool_id = pools.get(2).unwrap();
+        register_pools(
+            &e,
+            &contract_id,
+            &[&xlm_pool_id, &eurc_pool_id, &usdc_pool_id],
+        );
+
+        let collateral = vec![
+            &e,
+            Collateral {
+                pool: usdc_pool_id.clone(),
+                amount: 100_000,
+            },
+        ];
+        let xlm_loan_id = contract_client.create_loan(&user, &1_000, &xlm_pool_id, &collateral);
+        let eurc_loan_id = contract_client.create_loan(&user, &1_000, &eurc_pool_id, &collateral);
+
+        // A borrower without loans has nothing to project.
+        assert_eq!(
+            contract_client.get_loan_health(&Address::generate(&e)),
+            vec![&e]
+        );
+
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000 + 100_000;
+            li.timestamp = 1 + 31_556_926;
+        });
+        // A new instance of reflector mock needs to be created, they only live for one ledger.
+        e.register_at(&reflector_addr, oracle::WASM, ());
+
+        // Each loan accrues the interest of its own pool, and none of it is stored.
+        let projected_loans = contract_client.get_loan_health(&user);
+        assert_eq!(projected_loans.len(), 2);
+        let projected_xlm_loan = projected_loans.get(0).unwrap();
+        let projected_eurc_loan = projected_loans.get(1).unwrap();
+        assert_eq!(projected_xlm_loan.id, xlm_loan_id);
+        assert_eq!(projected_xlm_loan.borrowed_from, xlm_pool_id);
+        assert_eq!(projected_xlm_loan.borrowed_amount, 1_020);
+        assert_eq!(projected_xlm_loan.health_factor, 784_313_725);
+        assert_eq!(projected_eurc_loan.id, eurc_loan_id);
+        assert_eq!(projected_eurc_loan.borrowed_from, eurc_pool_id);
+        assert_eq!(projected_eurc_loan.borrowed_amount, 1_020);
+        assert_eq!(projected_eurc_loan.health_factor, 784_313_725);
+        assert_eq!(
+            contract_client.get_loan(&xlm_loan_id).borrowed_amount,
+            1_000
+        );
+        assert_eq!(
+            contract_client.get_loan(&eurc_loan_id).borrowed_amount,
+            1_000
+        );
+
+        contract_client.add_interest(&xlm_loan_id);
+        contract_client.add_interest(&eurc_loan_id);
+        assert_eq!(
+            contract_client.get_loan_health(&user),
+            vec![
+                &e,
+                contract_client.get_loan(&xlm_loan_id),
+                contract_client.get_loan(&eurc_loan_id)
+            ]
+        );
+        assert_eq!(projected_loans, contract_client.get_loan_health(&user));
+    }
+
+    #[test]
+    fn repay() {
+        // ARRANGE
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000;
+            li.timestamp = 1;
+            li.min_persistent_entry_ttl = 1_000_000;
+            li.min_temp_entry_ttl = 1_000_000;
+            li.max_entry_ttl = 1_000_001;
+        });
+
+        let admin = Address::generate(&e);
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
+        let loan_token_client = TokenClient::new(&e, &loan_token.address());
+        loan_asset.mint(&admin, &1_000_000);
+        let loan_currency = loan_pool::Currency {
+            token_address: loan_token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let admin2 = Address::generate(&e);
+        let collateral_token = e.register_stellar_asset_contract_v2(admin2.clone());
+        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
+        let collateral_token_client = TokenClient::new(&e, &collateral_token.address());
+        let collateral_currency = loan_pool::Currency {
+            token_address: collateral_token.address(),
+            ticker: Symbol::new(&e, "USDC"),
+            decimals: 7,
+        };
+
+        // Register mock Reflector contract.
+        let reflector_addr = e.register(oracle::WASM, ());
+
+        // Mint the user some coins
+        let user = Address::generate(&e);
+        collateral_asset.mint(&user, &1_000_000);
+
+        assert_eq!(collateral_token_client.balance(&user), 1_000_000);
+
+        // Set up a loan pool with funds for borrowing.
+        let loan_pool_id = e.register(loan_pool::WASM, ());
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+
+        // Set up a loan_pool for the collaterals.
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool_id);
+
+        // Register loan manager contract.
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
+
+        // ACT
+        // Initialize the loan pool and deposit some of the admin's funds.
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &1_000_000);
+
+        collateral_pool_client.initialize(
+            &contract_id,
+            &collateral_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        // Create a loan.
+        let loan_id = contract_client.create_loan(
+            &user,
+            &1_000,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 100_000,
+                },
+            ],
+        );
+
+        // Move in time
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000 + 100_000;
+            li.timestamp = 1 + 31_556_926;
+        });
+
+        e.register_at(&reflector_addr, oracle::WASM, ());
+
+        // ASSERT
+        assert_eq!(loan_token_client.balance(&user), 1_000);
+        assert_eq!(collateral_token_client.balance(&user), 900_000);
+
+        let user_loan = contract_client.get_loan(&loan_id);
+
+        assert_eq!(user_loan.borrowed_amount, 1_000);
+        assert_eq!(user_loan.collateral.get(0).unwrap().amount, 100_000);
+
+        contract_client.repay(&loan_id, &100);
+        let user_loan = contract_client.get_loan(&loan_id);
+        assert_eq!(user_loan.borrowed_amount, 920);
+
+        assert_eq!((920, 820), contract_client.repay(&loan_id, &100));
+        assert_eq!(999198, loan_pool_client.get_available_balance());
+        assert_eq!(1000018, loan_pool_client.get_contract_balance());
+        assert_eq!(1000000, loan_pool_client.get_total_balance_shares());
+        assert_eq!(2, loan_pool_client.get_total_reserves());
+
+        let treasury = Address::generate(&e);
+        contract_client.claim_reserves(&loan_pool_id, &treasury, &2);
+        assert_eq!(0, loan_pool_client.get_total_reserves());
+        assert_eq!(2, loan_token_client.balance(&treasury));
+    }
+
+    #[test]
+    fn repay_and_close() {
+        // ARRANGE
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000;
+            li.timestamp = 1;
+            li.min_persistent_entry_ttl = 1_000_000;
+            li.min_temp_entry_ttl = 1_000_000;
+            li.max_entry_ttl = 1_000_001;
+        });
+
+        let admin = Address::generate(&e);
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
+        let loan_token_client = TokenClient::new(&e, &loan_token.address());
+        loan_asset.mint(&admin, &1_000_000);
+        let loan_currency = loan_pool::Currency {
+            token_address: loan_token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let admin2 = Address::generate(&e);
+        let collateral_token = e.register_stellar_asset_contract_v2(admin2.clone());
+        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
+        let collateral_token_client = TokenClient::new(&e, &collateral_token.address());
+        let collateral_currency = loan_pool::Currency {
+            token_address: collateral_token.address(),
+            ticker: Symbol::new(&e, "USDC"),
+            decimals: 7,
+        };
+
+        // Register mock Reflector contract.
+        let reflector_addr = e.register(oracle::WASM, ());
+
+        // Mint the user some coins
+        let user = Address::generate(&e);
+        loan_asset.mint(&user, &50);
+        collateral_asset.mint(&user, &1_000_000);
+
+        assert_eq!(collateral_token_client.balance(&user), 1_000_000);
+
+        // Set up a loan pool with funds for borrowing.
+        let loan_pool_id = e.register(loan_pool::WASM, ());
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+
+        // Set up a loan_pool for the collaterals.
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool_id);
+
+        // Register loan manager contract.
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
+
+        // ACT
+        // Initialize the loan pool and deposit some of the admin's funds.
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &1_000_000);
+
+        collateral_pool_client.initialize(
+            &contract_id,
+            &collateral_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        // Create a loan.
+        let loan_id = contract_client.create_loan(
+            &user,
+            &1_000,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 100_000,
+                },
+            ],
+        );
+
+        // Move in time
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000 + 100_000;
+            li.timestamp = 1 + 31_556_926;
+        });
+
+        // ASSERT
+        // A new instance of reflector mock needs to be created, they only live for one ledger.
+        e.register_at(&reflector_addr, oracle::WASM, ());
+
+        assert_eq!(loan_token_client.balance(&user), 1_050);
+        assert_eq!(collateral_token_client.balance(&user), 900_000);
+
+        let user_loan = contract_client.get_loan(&loan_id);
+
+        assert_eq!(user_loan.borrowed_amount, 1_000);
+        assert_eq!(user_loan.collateral.get(0).unwrap().amount, 100_000);
+
+        assert_eq!(
+            1020,
+            contract_client.repay_and_close_manager(&loan_id, &(user_loan.borrowed_amount + 45))
+        );
+        let (address, topics, data) = e.events().all().last().unwrap();
+        assert_eq!(address, contract_id);
+        assert_eq!(
+            topics,
+            (Symbol::new(&e, "loan_closed"), user.clone()).into_val(&e)
+        );
+        let event: events::LoanClosedEvent = data.into_val(&e);
+        assert_eq!(
+            event,
+            events::LoanClosedEvent {
+                version: events::EVENT_VERSION,
+                loan_id,
+                borrower: user.clone(),
+                repaid_amount: 1020,
+                written_off_amount: 0,
+                collateral: vec![
+                    &e,
+                    Collateral {
+                        pool: collateral_pool_id.clone(),
+                        amount: 100_000,
+                    },
+                ],
+            }
+        );
+
+        assert_eq!(1000018, loan_pool_client.get_available_balance());
+        assert_eq!(1000018, loan_pool_client.get_contract_balance());
+        assert_eq!(1000000, loan_pool_client.get_total_balance_shares());
+    }
+
+    #[test]
+    #[should_panic(expected = "Amount can not be greater than borrowed amount!")]
+    fn repay_more_than_borrowed() {
+        // ARRANGE
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+
+        let admin = Address::generate(&e);
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
+        loan_asset.mint(&admin, &1_000_000);
+        let loan_currency = loan_pool::Currency {
+            token_address: loan_token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let admin2 = Address::generate(&e);
+        let collateral_token = e.register_stellar_asset_contract_v2(admin2.clone());
+        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
+        let collateral_token_client = TokenClient::new(&e, &collateral_token.address());
+        let collateral_currency = loan_pool::Currency {
+            token_address: collateral_token.address(),
+            ticker: Symbol::new(&e, "USDC"),
+            decimals: 7,
+        };
+
+        // Register mock Reflector contract.
+        let reflector_addr = e.register(oracle::WASM, ());
+
+        // Mint the user some coins
+        let user = Address::generate(&e);
+        collateral_asset.mint(&user, &1_000_000);
+
+        assert_eq!(collateral_token_client.balance(&user), 1_000_000);
+
+        // Set up a loan pool with funds for borrowing.
+        let loan_pool_id = e.register(loan_pool::WASM, ());
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+
+        // Set up a loan_pool for the collaterals.
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool_id);
+
+        // Register loan manager contract.
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
+
+        // ACT
+        // Initialize the loan pool and deposit some of the admin's funds.
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &1_000_000);
+
+        collateral_pool_client.initialize(
+            &contract_id,
+            &collateral_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        // Create a loan.
+        let loan_id = contract_client.create_loan(
+            &user,
+            &1_000,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 100_000,
+                },
+            ],
+        );
+
+        contract_client.repay(&loan_id, &2_000);
+    }
+    #[test]
+    fn multiple_loans_per_user() {
+        // ARRANGE
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000;
+            li.timestamp = 1;
+            li.min_persistent_entry_ttl = 1_000_000;
+            li.min_temp_entry_ttl = 1_000_000;
+            li.max_entry_ttl = 1_000_001;
+        });
+
+        let admin = Address::generate(&e);
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
+        let loan_token_client = TokenClient::new(&e, &loan_token.address());
+        loan_asset.mint(&admin, &1_000_000);
+        let loan_currency = loan_pool::Currency {
+            token_address: loan_token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let admin2 = Address::generate(&e);
+        let collateral_token = e.register_stellar_asset_contract_v2(admin2.clone());
+        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
+        let collateral_token_client = TokenClient::new(&e, &collateral_token.address());
+        let collateral_currency = loan_pool::Currency {
+            token_address: collateral_token.address(),
+            ticker: Symbol::new(&e, "USDC"),
+            decimals: 7,
+        };
+
+        // Register mock Reflector contract.
+        let reflector_addr = e.register(oracle::WASM, ());
+
+        // Mint the user some coins
+        let user = Address::generate(&e);
+        loan_asset.mint(&user, &50);
+        collateral_asset.mint(&user, &1_000_000);
+
+        // Set up a loan pool with funds for borrowing.
+        let loan_pool_id = e.register(loan_pool::WASM, ());
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+
+        // Set up a loan_pool for the collaterals.
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool_id);
+
+        // Register loan manager contract.
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
+
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &1_000_000);
+
+        collateral_pool_client.initialize(
+            &contract_id,
+            &collateral_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        // ACT
+        // Open two loans with the same pools.
+        let collateral = vec![
+            &e,
+            Collateral {
+                pool: collateral_pool_id.clone(),
+                amount: 100_000,
+            },
+        ];
+        let first_loan = contract_client.create_loan(&user, &1_000, &loan_pool_id, &collateral);
+        let second_loan = contract_client.create_loan(&user, &1_000, &loan_pool_id, &collateral);
+
+        assert_eq!(first_loan, 0);
+        assert_eq!(second_loan, 1);
+        assert_eq!(contract_client.get_user_loans(&user), vec![&e, 0, 1]);
+        assert_eq!(
+            loan_pool_client.get_user_positions(&user).liabilities,
+            2_000
+        );
+        assert_eq!(collateral_token_client.balance(&user), 800_000);
+
+        // Move in time
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000 + 100_000;
+            li.timestamp = 1 + 31_556_926;
+        });
+        // A new instance of reflector mock needs to be created, they only live for one ledger.
+        e.register_at(&reflector_addr, oracle::WASM, ());
+
+        contract_client.repay_and_close_manager(&first_loan, &1_045);
+
+        // ASSERT
+        // Closing the first loan leaves the second one untouched.
+        assert_eq!(
+            contract_client.try_get_loan(&first_loan),
+            Err(Ok(Error::LoanNotFound))
+        );
+        assert_eq!(contract_client.get_user_loans(&user), vec![&e, 1]);
+        let loan = contract_client.get_loan(&second_loan);
+        assert_eq!(loan.id, second_loan);
+        assert_eq!(loan.borrowed_amount, 1_000);
+        assert_eq!(
+            loan_pool_client.get_user_positions(&user).liabilities,
+            1_000
+        );
+        assert_eq!(
+            collateral_pool_client.get_user_positions(&user).collateral,
+            100_000
+        );
+        assert_eq!(loan_token_client.balance(&user), 1_030);
+        assert_eq!(collateral_token_client.balance(&user), 900_000);
+    }
+
+    #[test]
+    fn list_active_loans() {
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000;
+            li.timestamp = 1;
+            li.min_persistent_entry_ttl = 1_000_000;
+            li.min_temp_entry_ttl = 1_000_000;
+            li.max_entry_ttl = 1_000_001;
+        });
+
+        let admin = Address::generate(&e);
+        let user = Address::generate(&e);
+        let other_user = Address::generate(&e);
+        let reflector_addr = e.register(oracle::WASM, ());
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
+        loan_asset.mint(&admin, &1_000_000);
+        loan_asset.mint(&user, &100);
+        let loan_pool_id = e.register(loan_pool::WASM, ());
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: loan_token.address(),
+                ticker: Symbol::new(&e, "XLM"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &1_000_000);
+
+        let collateral_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
+        collateral_asset.mint(&user, &1_000_000);
+        collateral_asset.mint(&other_user, &1_000_000);
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
+        loan_pool::Client::new(&e, &collateral_pool_id).initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: collateral_token.address(),
+                ticker: Symbol::new(&e, "USDC"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        let collateral = vec![
+            &e,
+            Collateral {
+                pool: collateral_pool_id.clone(),
+                amount: 100_000,
+            },
+        ];
+        contract_client.create_loan(&user, &1_000, &loan_pool_id, &collateral);
+        contract_client.create_loan(&other_user, &1_000, &loan_pool_id, &collateral);
+        contract_client.create_loan(&user, &1_000, &loan_pool_id, &collateral);
+        assert_eq!(contract_client.get_active_loan_count(), 3);
+
+        let ids = |loans: Vec<Loan>| {
+            let mut ids = Vec::new(&e);
+            for loan in loans.iter() {
+                ids.push_back(loan.id);
+            }
+            ids
+        };
+        assert_eq!(ids(contract_client.get_loans(&0, &10)), vec![&e, 0, 1, 2]);
+        assert_eq!(ids(contract_client.get_loans(&1, &1)), vec![&e, 1]);
+        assert_eq!(ids(contract_client.get_loans(&3, &10)), vec![&e]);
+
+        // The listed loans have interest accrued up to now, the stored ones don't.
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000 + 100_000;
+            li.timestamp = 1 + 31_556_926;
+        });
+        // A new instance of reflector mock needs to be created, they only live for one ledger.
+        e.register_at(&reflector_addr, oracle::WASM, ());
+        let loan = contract_client.get_loans(&0, &1).get(0).unwrap();
+        assert_eq!(loan.borrowed_amount, 1_020);
+        assert_eq!(loan.health_factor, 784_313_725);
+        assert_eq!(contract_client.get_loan(&0).borrowed_amount, 1_000);
+
+        // The last loan takes the place of a closed one.
+        contract_client.repay_and_close_manager(&0, &1_100);
+        assert_eq!(contract_client.get_active_loan_count(), 2);
+        assert_eq!(ids(contract_client.get_loans(&0, &10)), vec![&e, 2, 1]);
+
+        // A fully repaid loan is no longer listed even though it stays open for its collateral,
+        // and it's listed again once it has debt.
+        contract_client.repay(&2, &1_020);
+        assert_eq!(contract_client.get_loan(&2).borrowed_amount, 0);
+        assert_eq!(contract_client.get_user_loans(&user), vec![&e, 2]);
+        assert_eq!(contract_client.get_active_loan_count(), 1);
+        assert_eq!(ids(contract_client.get_loans(&0, &10)), vec![&e, 1]);
+
+        contract_client.borrow_more(&2, &500);
+        assert_eq!(contract_client.get_active_loan_count(), 2);
+        assert_eq!(ids(contract_client.get_loans(&0, &10)), vec![&e, 1, 2]);
+
+        // A repaid loan is closed when its last collateral is withdrawn.
+        contract_client.repay(&2, &500);
+        contract_client.withdraw_collateral(&2, &collateral_pool_id, &100_000);
+        assert_eq!(
+            contract_client.try_get_loan(&2),
+            Err(Ok(Error::LoanNotFound))
+        );
+        assert_eq!(contract_client.get_user_loans(&user), vec![&e]);
+        assert_eq!(contract_client.get_active_loan_count(), 1);
+        assert_eq!(ids(contract_client.get_loans(&0, &10)), vec![&e, 1]);
+    }
+
+    #[test]
+    fn borrow_more() {
+        // ARRANGE
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000;
+            li.timestamp = 1;
+            li.min_persistent_entry_ttl = 1_000_000;
+            li.min_temp_entry_ttl = 1_000_000;
+            li.max_entry_ttl = 1_000_001;
+        });
+
+        let admin = Address::generate(&e);
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
+        let loan_token_client = TokenClient::new(&e, &loan_token.address());
+        loan_asset.mint(&admin, &1_000_000);
+        let loan_currency = loan_pool::Currency {
+            token_address: loan_token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let admin2 = Address::generate(&e);
+        let collateral_token = e.register_stellar_asset_contract_v2(admin2.clone());
+        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
+        let collateral_currency = loan_pool::Currency {
+            token_address: collateral_token.address(),
+            ticker: Symbol::new(&e, "USDC"),
+            decimals: 7,
+        };
+
+        // Register mock Reflector contract.
+        let reflector_addr = e.register(oracle::WASM, ());
+
+        // Mint the user some coins
+        let user = Address::generate(&e);
+        collateral_asset.mint(&user, &1_000_000);
 
-        let wasm_hash = e.deployer().upload_contract_wasm(loan_pool::WASM);
-        let salt = BytesN::from_array(&e, &[0; 32]);
+        // Set up a loan pool with funds for borrowing.
+        let loan_pool_id = e.register(loan_pool::WASM, ());
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
 
-        // ACT
-        // Deploy contract using loan_manager as factory
-        let loan_pool_addr =
-            deployer_client.deploy_pool(&wasm_hash, &salt, &token.address(), &ticker, &8_000_000);
+        // Set up a loan_pool for the collaterals.
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool_id);
 
-        // ASSERT
-        // No authorizations needed - the contract acts as a factory.
-        // assert_eq!(e.auths(), &[]);
+        // Register loan manager contract.
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
 
-        // Invoke contract to check that it is initialized.
-        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_addr);
-        let pool_balance = loan_pool_client.get_contract_balance();
-        assert_eq!(pool_balance, 0);
-    }
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &1_000_000);
 
-    #[test]
-    fn upgrade_manager_and_pool() {
-        // ARRANGE
-        let e = Env::default();
-        e.mock_all_auths();
+        collateral_pool_client.initialize(
+            &contract_id,
+            &collateral_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
 
-        let admin = Address::generate(&e);
+        let loan_id = contract_client.create_loan(
+            &user,
+            &1_000,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 2_000,
+                },
+            ],
+        );
 
-        let deployer_client = LoanManagerClient::new(&e, &e.register(LoanManager, ()));
-        deployer_client.initialize(&admin);
+        // Move in time
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000 + 100_000;
+            li.timestamp = 1 + 31_556_926;
+        });
+        // A new instance of reflector mock needs to be created, they only live for one ledger.
+        e.register_at(&reflector_addr, oracle::WASM, ());
 
-        // Setup test token
-        let token = e.register_stellar_asset_contract_v2(admin.clone());
-        let ticker = Symbol::new(&e, "XLM");
+        // ACT
+        // Interest is accrued before the new borrow is added.
+        assert_eq!(contract_client.borrow_more(&loan_id, &200), 1_220);
 
-        let manager_wasm_hash = e.deployer().upload_contract_wasm(loan_manager::WASM);
-        let pool_wasm_hash = e.deployer().upload_contract_wasm(loan_pool::WASM);
-        let salt = BytesN::from_array(&e, &[0; 32]);
+        // ASSERT
+        let loan = contract_client.get_loan(&loan_id);
+        assert_eq!(loan.borrowed_amount, 1_220);
+        assert_eq!(loan.unpaid_interest, 20);
+        assert_eq!(loan.health_factor, 13_114_754);
+        assert_eq!(loan_token_client.balance(&user), 1_200);
+        assert_eq!(
+            loan_pool_client.get_user_positions(&user).liabilities,
+            1_200
+        );
 
-        // ACT
-        deployer_client.deploy_pool(
-            &pool_wasm_hash,
-            &salt,
-            &token.address(),
-            &ticker,
-            &8_000_000,
+        assert_eq!(
+            contract_client.try_borrow_more(&loan_id, &400),
+            Err(Ok(Error::HealthFactorTooLow))
+        );
+        assert_eq!(
+            contract_client.try_borrow_more(&loan_id, &0),
+            Err(Ok(Error::InvalidBorrowAmount))
         );
-        deployer_client.upgrade(&manager_wasm_hash, &pool_wasm_hash);
     }
 
     #[test]
-    fn create_loan() {
+    fn add_and_withdraw_collateral() {
         // ARRANGE
         let e = Env::default();
         e.mock_all_auths_allowing_non_root_auth();
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000;
+            li.timestamp = 1;
+            li.min_persistent_entry_ttl = 1_000_000;
+            li.min_temp_entry_ttl = 1_000_000;
+            li.max_entry_ttl = 1_000_001;
+        });
 
         let admin = Address::generate(&e);
         let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
         let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
-        let loan_token_client = TokenClient::new(&e, &loan_token.address());
-        loan_asset.mint(&admin, &1000);
+        loan_asset.mint(&admin, &1_000_000);
         let loan_currency = loan_pool::Currency {
             token_address: loan_token.address(),
             ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
         };
 
         let admin2 = Address::generate(&e);
@@ -676,17 +4096,15 @@ mod tests {
         let collateral_currency = loan_pool::Currency {
             token_address: collateral_token.address(),
             ticker: Symbol::new(&e, "USDC"),
+            decimals: 7,
         };
 
         // Register mock Reflector contract.
-        let reflector_addr = Address::from_string(&String::from_str(&e, REFLECTOR_ADDRESS));
-        e.register_at(&reflector_addr, oracle::WASM, ());
+        let reflector_addr = e.register(oracle::WASM, ());
 
         // Mint the user some coins
         let user = Address::generate(&e);
-        collateral_asset.mint(&user, &1000);
-
-        assert_eq!(collateral_token_client.balance(&user), 1000);
+        collateral_asset.mint(&user, &1_000_000);
 
         // Set up a loan pool with funds for borrowing.
         let loan_pool_id = e.register(loan_pool::WASM, ());
@@ -699,30 +4117,96 @@ mod tests {
         // Register loan manager contract.
         let contract_id = e.register(LoanManager, ());
         let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
 
-        // ACT
-        // Initialize the loan pool and deposit some of the admin's funds.
-        loan_pool_client.initialize(&contract_id, &loan_currency, &8_000_000);
-        loan_pool_client.deposit(&admin, &1000);
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &1_000_000);
 
-        collateral_pool_client.initialize(&contract_id, &collateral_currency, &8_000_000);
+        collateral_pool_client.initialize(
+            &contract_id,
+            &collateral_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
 
-        contract_client.create_loan(&user, &10, &loan_pool_id, &100, &collateral_pool_id);
+        let loan_id = contract_client.create_loan(
+            &user,
+            &1_000,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 2_000,
+                },
+            ],
+        );
+        assert_eq!(contract_client.get_loan(&loan_id).health_factor, 16_000_000);
 
-        // ASSERT
-        assert_eq!(loan_token_client.balance(&user), 10);
-        assert_eq!(collateral_token_client.balance(&user), 900);
+        // ACT & ASSERT
+        assert_eq!(
+            contract_client.add_collateral(&loan_id, &collateral_pool_id, &1_000),
+            24_000_000
+        );
+        assert_eq!(
+            contract_client.get_loan(&loan_id).collateral,
+            vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 3_000,
+                },
+            ]
+        );
+
+        assert_eq!(
+            contract_client.withdraw_collateral(&loan_id, &collateral_pool_id, &1_500),
+            12_000_000
+        );
+        assert_eq!(
+            contract_client.try_withdraw_collateral(&loan_id, &collateral_pool_id, &300),
+            Err(Ok(Error::HealthFactorTooLow))
+        );
+        assert_eq!(
+            contract_client.try_withdraw_collateral(&loan_id, &collateral_pool_id, &2_000),
+            Err(Ok(Error::InsufficientCollateral))
+        );
+        assert_eq!(
+            contract_client.try_withdraw_collateral(&loan_id, &loan_pool_id, &1),
+            Err(Ok(Error::CollateralNotFound))
+        );
+        assert_eq!(
+            contract_client.try_add_collateral(&loan_id, &Address::generate(&e), &1_000),
+            Err(Ok(Error::PoolNotFound))
+        );
+
+        let loan = contract_client.get_loan(&loan_id);
+        assert_eq!(loan.collateral.get(0).unwrap().amount, 1_500);
+        assert_eq!(loan.health_factor, 12_000_000);
+        assert_eq!(
+            collateral_pool_client.get_user_positions(&user).collateral,
+            1_500
+        );
+        assert_eq!(collateral_token_client.balance(&user), 998_500);
     }
 
     #[test]
-    fn add_interest() {
+    fn liquidate() {
         // ARRANGE
         let e = Env::default();
         e.mock_all_auths_allowing_non_root_auth();
         e.ledger().with_mut(|li| {
             li.sequence_number = 100_000;
             li.timestamp = 1;
-            li.min_persistent_entry_ttl = 10_000_000;
+            li.min_persistent_entry_ttl = 1_000_000;
             li.min_temp_entry_ttl = 1_000_000;
             li.max_entry_ttl = 1_000_001;
         });
@@ -734,6 +4218,7 @@ mod tests {
         let loan_currency = loan_pool::Currency {
             token_address: loan_token.address(),
             ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
         };
 
         let admin2 = Address::generate(&e);
@@ -743,11 +4228,11 @@ mod tests {
         let collateral_currency = loan_pool::Currency {
             token_address: collateral_token.address(),
             ticker: Symbol::new(&e, "USDC"),
+            decimals: 7,
         };
 
         // Register mock Reflector contract.
-        let reflector_addr = Address::from_string(&String::from_str(&e, REFLECTOR_ADDRESS));
-        e.register_at(&reflector_addr, oracle::WASM, ());
+        let reflector_addr = e.register(oracle::WASM, ());
 
         // Mint the user some coins
         let user = Address::generate(&e);
@@ -766,26 +4251,51 @@ mod tests {
         // Register loan manager contract.
         let contract_id = e.register(LoanManager, ());
         let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
 
         // ACT
         // Initialize the loan pool and deposit some of the admin's funds.
-        loan_pool_client.initialize(&contract_id, &loan_currency, &8_000_000);
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
         loan_pool_client.deposit(&admin, &10_001);
 
-        collateral_pool_client.initialize(&contract_id, &collateral_currency, &8_000_000);
+        collateral_pool_client.initialize(
+            &contract_id,
+            &collateral_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
 
         // Create a loan.
-        contract_client.create_loan(&user, &10_000, &loan_pool_id, &100_000, &collateral_pool_id);
+        let loan_id = contract_client.create_loan(
+            &user,
+            &10_000,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 12_505,
+                },
+            ],
+        );
 
-        let user_loan = contract_client.get_loan(&user);
+        let user_loan = contract_client.get_loan(&loan_id);
 
         assert_eq!(user_loan.borrowed_amount, 10_000);
-        assert_eq!(collateral_token_client.balance(&user), 900_000);
+
+        contract_client.add_interest(&loan_id);
 
         // Here borrowed amount should be the same as time has not moved. add_interest() is only called to store the LastUpdate sequence number.
         assert_eq!(user_loan.borrowed_amount, 10_000);
-        assert_eq!(user_loan.health_factor, 80_000_000);
-        assert_eq!(collateral_token_client.balance(&user), 900_000);
+        assert_eq!(user_loan.health_factor, 10_004_000);
 
         // Move time
         e.ledger().with_mut(|li| {
@@ -794,265 +4304,604 @@ mod tests {
         });
 
         // A new instance of reflector mock needs to be created, they only live for one ledger.
-        let reflector_addr = Address::from_string(&String::from_str(&e, REFLECTOR_ADDRESS));
         e.register_at(&reflector_addr, oracle::WASM, ());
 
-        contract_client.add_interest(&user);
+        // The preview accrues the interest without storing it.
+        assert_eq!(
+            contract_client.preview_liquidation(&loan_id, &5000, &collateral_pool_id),
+            LiquidationPreview {
+                max_amount: 6_499,
+                collateral_seized: 5_250,
+                bonus_value: 2_500_000_000,
+                health_factor: 7_256_814,
+            }
+        );
+        assert_eq!(contract_client.get_loan(&loan_id).borrowed_amount, 10_000);
+
+        contract_client.add_interest(&loan_id);
 
-        let user_loan = contract_client.get_loan(&user);
+        let user_loan = contract_client.get_loan(&loan_id);
 
         assert_eq!(user_loan.borrowed_amount, 12_998);
-        assert_eq!(user_loan.health_factor, 61_547_930);
-        assert_eq!(user_loan.collateral_amount, 100_000);
+        assert_eq!(user_loan.health_factor, 7_696_568);
+        assert_eq!(user_loan.collateral.get(0).unwrap().amount, 12_505);
+
+        e.ledger().with_mut(|li| {
+            li.sequence_number = 100_000 + 1_000;
+        });
+
+        e.register_at(&reflector_addr, oracle::WASM, ());
+
+        assert_eq!(
+            contract_client.try_liquidate(&admin, &loan_id, &0, &collateral_pool_id),
+            Err(Ok(Error::InvalidLiquidationAmount))
+        );
+        assert_eq!(
+            contract_client.try_liquidate(&admin, &loan_id, &-5000, &collateral_pool_id),
+            Err(Ok(Error::InvalidLiquidationAmount))
+        );
+        contract_client.liquidate(&admin, &loan_id, &5000, &collateral_pool_id);
+
+        let user_loan = contract_client.get_loan(&loan_id);
+
+        assert_eq!(user_loan.borrowed_amount, 7_998);
+        assert_eq!(user_loan.health_factor, 7_256_814);
+        assert_eq!(user_loan.collateral.get(0).unwrap().amount, 7_255);
     }
 
     #[test]
-    fn repay() {
-        // ARRANGE
+    fn liquidation_params() {
         let e = Env::default();
         e.mock_all_auths_allowing_non_root_auth();
-        e.ledger().with_mut(|li| {
-            li.sequence_number = 100_000;
-            li.timestamp = 1;
-            li.min_persistent_entry_ttl = 1_000_000;
-            li.min_temp_entry_ttl = 1_000_000;
-            li.max_entry_ttl = 1_000_001;
-        });
 
         let admin = Address::generate(&e);
+        let user = Address::generate(&e);
+        let reflector_addr = e.register(oracle::WASM, ());
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+
         let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
-        let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
-        let loan_token_client = TokenClient::new(&e, &loan_token.address());
-        loan_asset.mint(&admin, &1_000_000);
-        let loan_currency = loan_pool::Currency {
-            token_address: loan_token.address(),
-            ticker: Symbol::new(&e, "XLM"),
-        };
+        StellarAssetClient::new(&e, &loan_token.address()).mint(&admin, &1_000_000);
+        let loan_pool_id = e.register(loan_pool::WASM, ());
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: loan_token.address(),
+                ticker: Symbol::new(&e, "XLM"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &20_000);
 
-        let admin2 = Address::generate(&e);
-        let collateral_token = e.register_stellar_asset_contract_v2(admin2.clone());
-        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
-        let collateral_token_client = TokenClient::new(&e, &collateral_token.address());
-        let collateral_currency = loan_pool::Currency {
-            token_address: collateral_token.address(),
-            ticker: Symbol::new(&e, "USDC"),
-        };
+        let collateral_token = e.register_stellar_asset_contract_v2(admin.clone());
+        StellarAssetClient::new(&e, &collateral_token.address()).mint(&user, &1_000_000);
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
+        loan_pool::Client::new(&e, &collateral_pool_id).initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: collateral_token.address(),
+                ticker: Symbol::new(&e, "USDC"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
 
-        // Register mock Reflector contract.
-        let reflector_addr = Address::from_string(&String::from_str(&e, REFLECTOR_ADDRESS));
+        // 60% of a loan can be repaid at once and the liquidator gets 10% extra.
+        let id = contract_client.queue_action(&GovernanceAction::SetLiquidationParams(
+            collateral_pool_id.clone(),
+            LiquidationParams {
+                liquidation_threshold: 8_000_000,
+                close_factor: 6_000_000,
+                liquidation_bonus: 1_000_000,
+            },
+        ));
+        pass_timelock_delay(&e);
         e.register_at(&reflector_addr, oracle::WASM, ());
+        contract_client.execute_action(&id);
 
-        // Mint the user some coins
-        let user = Address::generate(&e);
-        collateral_asset.mint(&user, &1_000_000);
+        let loan_id = contract_client.create_loan(
+            &user,
+            &10_000,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 12_600,
+                },
+            ],
+        );
+        assert_eq!(
+            contract_client.try_liquidate(&admin, &loan_id, &1_000, &collateral_pool_id),
+            Err(Ok(Error::LoanNotLiquidatable))
+        );
 
-        assert_eq!(collateral_token_client.balance(&user), 1_000_000);
+        // Health factor drops to 0.9072 when the collateral loses 10% of its value.
+        oracle::Client::new(&e, &reflector_addr).set_price(
+            &oracle::Asset::Other(Symbol::new(&e, "USDC")),
+            &(9 * 10_i128.pow(oracle::PRICE_DECIMALS - 1)),
+        );
+        assert_eq!(
+            contract_client.try_liquidate(&admin, &loan_id, &6_001, &collateral_pool_id),
+            Err(Ok(Error::LiquidationTooLarge))
+        );
 
-        // Set up a loan pool with funds for borrowing.
+        // 6_000 worth of debt with the 10% bonus is 7_333 of collateral at 0.9.
+        assert_eq!(
+            contract_client.preview_liquidation(&loan_id, &6_000, &collateral_pool_id),
+            LiquidationPreview {
+                max_amount: 6_000,
+                collateral_seized: 7_333,
+                bonus_value: 6_000_000_000,
+                health_factor: 9_480_600,
+            }
+        );
+        assert_eq!(
+            contract_client.liquidate(&admin, &loan_id, &6_000, &collateral_pool_id),
+            (4_000, 5_267)
+        );
+        assert_eq!(contract_client.get_loan(&loan_id).health_factor, 9_480_600);
+    }
+
+    #[test]
+    fn auction_liquidation() {
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+
+        let admin = Address::generate(&e);
+        let user = Address::generate(&e);
+        let reflector_addr = e.register(oracle::WASM, ());
+        let reflector_client = oracle::Client::new(&e, &reflector_addr);
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        StellarAssetClient::new(&e, &loan_token.address()).mint(&admin, &1_000_000);
         let loan_pool_id = e.register(loan_pool::WASM, ());
         let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: loan_token.address(),
+                ticker: Symbol::new(&e, "XLM"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &20_000);
 
-        // Set up a loan_pool for the collaterals.
+        let collateral_token = e.register_stellar_asset_contract_v2(admin.clone());
+        StellarAssetClient::new(&e, &collateral_token.address()).mint(&user, &1_000_000);
         let collateral_pool_id = e.register(loan_pool::WASM, ());
-        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool_id);
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
+        loan_pool::Client::new(&e, &collateral_pool_id).initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: collateral_token.address(),
+                ticker: Symbol::new(&e, "USDC"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
 
-        // Register loan manager contract.
+        assert_eq!(
+            contract_client.try_queue_action(&GovernanceAction::SetAuctionConfig(AuctionConfig {
+                start_discount: 3_000_000,
+                max_discount: 2_000_000,
+                duration: 100,
+            })),
+            Err(Ok(Error::InvalidAuctionConfig))
+        );
+        // The discount grows from 0% to 20% over 100 ledgers.
+        let config = AuctionConfig {
+            start_discount: 0,
+            max_discount: 2_000_000,
+            duration: 100,
+        };
+        let id = contract_client.queue_action(&GovernanceAction::SetAuctionConfig(config.clone()));
+        pass_timelock_delay(&e);
+        e.register_at(&reflector_addr, oracle::WASM, ());
+        contract_client.execute_action(&id);
+        assert_eq!(contract_client.get_auction_config(), Some(config));
+
+        let loan_id = contract_client.create_loan(
+            &user,
+            &10_000,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 12_600,
+                },
+            ],
+        );
+        assert_eq!(
+            contract_client.try_start_auction(&loan_id),
+            Err(Ok(Error::LoanNotLiquidatable))
+        );
+
+        let usdc = oracle::Asset::Other(Symbol::new(&e, "USDC"));
+        let low_price = 9 * 10_i128.pow(oracle::PRICE_DECIMALS - 1);
+        reflector_client.set_price(&usdc, &low_price);
+        assert_eq!(
+            contract_client.try_liquidate(&admin, &loan_id, &2_000, &collateral_pool_id),
+            Err(Ok(Error::AuctionNotFound))
+        );
+
+        let start_ledger = e.ledger().sequence();
+        assert_eq!(
+            contract_client.start_auction(&loan_id),
+            Auction {
+                loan_id,
+                start_ledger
+            }
+        );
+        assert_eq!(
+            contract_client.try_start_auction(&loan_id),
+            Err(Ok(Error::AuctionAlreadyStarted))
+        );
+        assert_eq!(contract_client.get_auction_discount(&loan_id), 0);
+
+        // Halfway through the discount is 10%, so 2_000 of debt buys 2_469 of collateral at 0.9.
+        // A new instance of reflector mock needs to be created, they only live for one ledger.
+        e.ledger()
+            .with_mut(|li| li.sequence_number = start_ledger + 50);
+        e.register_at(&reflector_addr, oracle::WASM, ());
+        reflector_client.set_price(&usdc, &low_price);
+        assert_eq!(contract_client.get_auction_discount(&loan_id), 1_000_000);
+        assert_eq!(
+            contract_client.liquidate(&admin, &loan_id, &2_000, &collateral_pool_id),
+            (8_000, 10_131)
+        );
+
+        // The discount stops growing at the max and the auction stays open while the loan is
+        // still unhealthy.
+        e.ledger()
+            .with_mut(|li| li.sequence_number = start_ledger + 500);
+        e.register_at(&reflector_addr, oracle::WASM, ());
+        reflector_client.set_price(&usdc, &low_price);
+        assert_eq!(contract_client.get_auction_discount(&loan_id), 2_000_000);
+        assert_eq!(
+            contract_client.try_cancel_auction(&loan_id),
+            Err(Ok(Error::LoanStillLiquidatable))
+        );
+
+        // The auction can be cancelled once the price recovers.
+        reflector_client.set_price(&usdc, &10_i128.pow(oracle::PRICE_DECIMALS));
+        contract_client.cancel_auction(&loan_id);
+        assert_eq!(contract_client.get_auction(&loan_id), None);
+    }
+
+    #[test]
+    fn auction_ends_when_loan_recovers() {
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+
+        let admin = Address::generate(&e);
+        let user = Address::generate(&e);
+        let reflector_addr = e.register(oracle::WASM, ());
+        let reflector_client = oracle::Client::new(&e, &reflector_addr);
         let contract_id = e.register(LoanManager, ());
         let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
 
-        // ACT
-        // Initialize the loan pool and deposit some of the admin's funds.
-        loan_pool_client.initialize(&contract_id, &loan_currency, &8_000_000);
-        loan_pool_client.deposit(&admin, &1_000_000);
-
-        collateral_pool_client.initialize(&contract_id, &collateral_currency, &8_000_000);
-
-        // Create a loan.
-        contract_client.create_loan(&user, &1_000, &loan_pool_id, &100_000, &collateral_pool_id);
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        StellarAssetClient::new(&e, &loan_token.address()).mint(&admin, &1_000_000);
+        let loan_pool_id = e.register(loan_pool::WASM, ());
+        let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: loan_token.address(),
+                ticker: Symbol::new(&e, "XLM"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &20_000);
 
-        // Move in time
-        e.ledger().with_mut(|li| {
-            li.sequence_number = 100_000 + 100_000;
-            li.timestamp = 1 + 31_556_926;
-        });
+        let collateral_token = e.register_stellar_asset_contract_v2(admin.clone());
+        StellarAssetClient::new(&e, &collateral_token.address()).mint(&user, &1_000_000);
+        let collateral_pool_id = e.register(loan_pool::WASM, ());
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
+        loan_pool::Client::new(&e, &collateral_pool_id).initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: collateral_token.address(),
+                ticker: Symbol::new(&e, "USDC"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
 
-        let reflector_addr = Address::from_string(&String::from_str(&e, REFLECTOR_ADDRESS));
+        // The discount grows from 0% to 20% over 100 ledgers.
+        let id = contract_client.queue_action(&GovernanceAction::SetAuctionConfig(AuctionConfig {
+            start_discount: 0,
+            max_discount: 2_000_000,
+            duration: 100,
+        }));
+        pass_timelock_delay(&e);
         e.register_at(&reflector_addr, oracle::WASM, ());
+        contract_client.execute_action(&id);
 
-        // ASSERT
-        assert_eq!(loan_token_client.balance(&user), 1_000);
-        assert_eq!(collateral_token_client.balance(&user), 900_000);
+        let loan_id = contract_client.create_loan(
+            &user,
+            &10_000,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 12_600,
+                },
+            ],
+        );
 
-        let user_loan = contract_client.get_loan(&user);
+        let usdc = oracle::Asset::Other(Symbol::new(&e, "USDC"));
+        reflector_client.set_price(&usdc, &(9 * 10_i128.pow(oracle::PRICE_DECIMALS - 1)));
+        let start_ledger = e.ledger().sequence();
+        contract_client.start_auction(&loan_id);
 
-        assert_eq!(user_loan.borrowed_amount, 1_000);
-        assert_eq!(user_loan.collateral_amount, 100_000);
+        // Repaying a fifth of the debt brings the health factor back to 1.134, which ends the
+        // auction.
+        contract_client.repay(&loan_id, &2_000);
+        assert_eq!(contract_client.get_auction(&loan_id), None);
 
-        contract_client.repay(&user, &100);
-        let user_loan = contract_client.get_loan(&user);
-        assert_eq!(user_loan.borrowed_amount, 920);
+        // When the loan relapses much later, it isn't liquidated at the max discount of the old
+        // auction. A new one starts from the start discount.
+        e.ledger()
+            .with_mut(|li| li.sequence_number = start_ledger + 500);
+        e.register_at(&reflector_addr, oracle::WASM, ());
+        reflector_client.set_price(&usdc, &(7 * 10_i128.pow(oracle::PRICE_DECIMALS - 1)));
+        assert_eq!(
+            contract_client.try_liquidate(&admin, &loan_id, &1_000, &collateral_pool_id),
+            Err(Ok(Error::AuctionNotFound))
+        );
+        assert_eq!(
+            contract_client.start_auction(&loan_id),
+            Auction {
+                loan_id,
+                start_ledger: start_ledger + 500
+            }
+        );
+        assert_eq!(contract_client.get_auction_discount(&loan_id), 0);
 
-        assert_eq!((920, 820), contract_client.repay(&user, &100));
-        assert_eq!(999198, loan_pool_client.get_available_balance());
-        assert_eq!(1000018, loan_pool_client.get_contract_balance());
-        assert_eq!(1000000, loan_pool_client.get_total_balance_shares());
+        // Adding collateral also ends it.
+        contract_client.add_collateral(&loan_id, &collateral_pool_id, &10_000);
+        assert_eq!(contract_client.get_auction(&loan_id), None);
     }
 
     #[test]
-    fn repay_and_close() {
-        // ARRANGE
+    fn disabling_auctions_ends_them() {
         let e = Env::default();
         e.mock_all_auths_allowing_non_root_auth();
-        e.ledger().with_mut(|li| {
-            li.sequence_number = 100_000;
-            li.timestamp = 1;
-            li.min_persistent_entry_ttl = 1_000_000;
-            li.min_temp_entry_ttl = 1_000_000;
-            li.max_entry_ttl = 1_000_001;
-        });
 
         let admin = Address::generate(&e);
-        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
-        let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
-        let loan_token_client = TokenClient::new(&e, &loan_token.address());
-        loan_asset.mint(&admin, &1_000_000);
-        let loan_currency = loan_pool::Currency {
-            token_address: loan_token.address(),
-            ticker: Symbol::new(&e, "XLM"),
-        };
-
-        let admin2 = Address::generate(&e);
-        let collateral_token = e.register_stellar_asset_contract_v2(admin2.clone());
-        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
-        let collateral_token_client = TokenClient::new(&e, &collateral_token.address());
-        let collateral_currency = loan_pool::Currency {
-            token_address: collateral_token.address(),
-            ticker: Symbol::new(&e, "USDC"),
-        };
-
-        // Register mock Reflector contract.
-        let reflector_addr = Address::from_string(&String::from_str(&e, REFLECTOR_ADDRESS));
-        e.register_at(&reflector_addr, oracle::WASM, ());
-
-        // Mint the user some coins
         let user = Address::generate(&e);
-        loan_asset.mint(&user, &50);
-        collateral_asset.mint(&user, &1_000_000);
-
-        assert_eq!(collateral_token_client.balance(&user), 1_000_000);
+        let reflector_addr = e.register(oracle::WASM, ());
+        let reflector_client = oracle::Client::new(&e, &reflector_addr);
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
 
-        // Set up a loan pool with funds for borrowing.
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        StellarAssetClient::new(&e, &loan_token.address()).mint(&admin, &1_000_000);
         let loan_pool_id = e.register(loan_pool::WASM, ());
         let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: loan_token.address(),
+                ticker: Symbol::new(&e, "XLM"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &20_000);
 
-        // Set up a loan_pool for the collaterals.
+        let collateral_token = e.register_stellar_asset_contract_v2(admin.clone());
+        StellarAssetClient::new(&e, &collateral_token.address()).mint(&user, &1_000_000);
         let collateral_pool_id = e.register(loan_pool::WASM, ());
-        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool_id);
-
-        // Register loan manager contract.
-        let contract_id = e.register(LoanManager, ());
-        let contract_client = LoanManagerClient::new(&e, &contract_id);
-
-        // ACT
-        // Initialize the loan pool and deposit some of the admin's funds.
-        loan_pool_client.initialize(&contract_id, &loan_currency, &8_000_000);
-        loan_pool_client.deposit(&admin, &1_000_000);
-
-        collateral_pool_client.initialize(&contract_id, &collateral_currency, &8_000_000);
-
-        // Create a loan.
-        contract_client.create_loan(&user, &1_000, &loan_pool_id, &100_000, &collateral_pool_id);
-
-        // Move in time
-        e.ledger().with_mut(|li| {
-            li.sequence_number = 100_000 + 100_000;
-            li.timestamp = 1 + 31_556_926;
-        });
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
+        loan_pool::Client::new(&e, &collateral_pool_id).initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: collateral_token.address(),
+                ticker: Symbol::new(&e, "USDC"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
 
-        // ASSERT
-        // A new instance of reflector mock needs to be created, they only live for one ledger.
-        let reflector_addr = Address::from_string(&String::from_str(&e, REFLECTOR_ADDRESS));
+        let config = AuctionConfig {
+            start_discount: 0,
+            max_discount: 2_000_000,
+            duration: 100,
+        };
+        let id = contract_client.queue_action(&GovernanceAction::SetAuctionConfig(config.clone()));
+        pass_timelock_delay(&e);
         e.register_at(&reflector_addr, oracle::WASM, ());
+        contract_client.execute_action(&id);
 
-        assert_eq!(loan_token_client.balance(&user), 1_050);
-        assert_eq!(collateral_token_client.balance(&user), 900_000);
+        let loan_id = contract_client.create_loan(
+            &user,
+            &10_000,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 12_600,
+                },
+            ],
+        );
 
-        let user_loan = contract_client.get_loan(&user);
+        let usdc = oracle::Asset::Other(Symbol::new(&e, "USDC"));
+        let low_price = 9 * 10_i128.pow(oracle::PRICE_DECIMALS - 1);
+        reflector_client.set_price(&usdc, &low_price);
+        let start_ledger = e.ledger().sequence();
+        contract_client.start_auction(&loan_id);
 
-        assert_eq!(user_loan.borrowed_amount, 1_000);
-        assert_eq!(user_loan.collateral_amount, 100_000);
+        e.ledger()
+            .with_mut(|li| li.sequence_number = start_ledger + 500);
+        let id = contract_client.queue_action(&GovernanceAction::DisableAuctions);
+        pass_timelock_delay(&e);
+        e.register_at(&reflector_addr, oracle::WASM, ());
+        reflector_client.set_price(&usdc, &low_price);
+        contract_client.execute_action(&id);
+        assert_eq!(contract_client.get_auction(&loan_id), None);
 
+        // Liquidations use the fixed bonus again.
+        let preview = contract_client.preview_liquidation(&loan_id, &1_000, &collateral_pool_id);
+        contract_client.liquidate(&admin, &loan_id, &1_000, &collateral_pool_id);
         assert_eq!(
-            1020,
-            contract_client.repay_and_close_manager(&user, &(user_loan.borrowed_amount + 45))
+            contract_client.get_loan(&loan_id).health_factor,
+            preview.health_factor
         );
 
-        assert_eq!(1000018, loan_pool_client.get_available_balance());
-        assert_eq!(1000018, loan_pool_client.get_contract_balance());
-        assert_eq!(1000000, loan_pool_client.get_total_balance_shares());
+        // The old auction doesn't come back with its max discount when auctions are turned on
+        // again.
+        let id = contract_client.queue_action(&GovernanceAction::SetAuctionConfig(config));
+        pass_timelock_delay(&e);
+        e.ledger()
+            .with_mut(|li| li.sequence_number = start_ledger + 600);
+        e.register_at(&reflector_addr, oracle::WASM, ());
+        reflector_client.set_price(&usdc, &low_price);
+        contract_client.execute_action(&id);
+        assert_eq!(contract_client.get_auction(&loan_id), None);
+        assert_eq!(
+            contract_client.try_liquidate(&admin, &loan_id, &1_000, &collateral_pool_id),
+            Err(Ok(Error::AuctionNotFound))
+        );
+        contract_client.start_auction(&loan_id);
+        assert_eq!(contract_client.get_auction_discount(&loan_id), 0);
     }
 
     #[test]
-    #[should_panic(expected = "Amount can not be greater than borrowed amount!")]
-    fn repay_more_than_borrowed() {
-        // ARRANGE
+    fn auction_discount_capped_by_threshold() {
         let e = Env::default();
         e.mock_all_auths_allowing_non_root_auth();
 
         let admin = Address::generate(&e);
-        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
-        let loan_asset = StellarAssetClient::new(&e, &loan_token.address());
-        loan_asset.mint(&admin, &1_000_000);
-        let loan_currency = loan_pool::Currency {
-            token_address: loan_token.address(),
-            ticker: Symbol::new(&e, "XLM"),
-        };
-
-        let admin2 = Address::generate(&e);
-        let collateral_token = e.register_stellar_asset_contract_v2(admin2.clone());
-        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
-        let collateral_token_client = TokenClient::new(&e, &collateral_token.address());
-        let collateral_currency = loan_pool::Currency {
-            token_address: collateral_token.address(),
-            ticker: Symbol::new(&e, "USDC"),
-        };
-
-        // Register mock Reflector contract.
-        let reflector_addr = Address::from_string(&String::from_str(&e, REFLECTOR_ADDRESS));
-        e.register_at(&reflector_addr, oracle::WASM, ());
-
-        // Mint the user some coins
         let user = Address::generate(&e);
-        collateral_asset.mint(&user, &1_000_000);
-
-        assert_eq!(collateral_token_client.balance(&user), 1_000_000);
+        let reflector_addr = e.register(oracle::WASM, ());
+        let reflector_client = oracle::Client::new(&e, &reflector_addr);
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
 
-        // Set up a loan pool with funds for borrowing.
+        let loan_token = e.register_stellar_asset_contract_v2(admin.clone());
+        StellarAssetClient::new(&e, &loan_token.address()).mint(&admin, &1_000_000);
         let loan_pool_id = e.register(loan_pool::WASM, ());
         let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: loan_token.address(),
+                ticker: Symbol::new(&e, "XLM"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        loan_pool_client.deposit(&admin, &20_000);
 
-        // Set up a loan_pool for the collaterals.
+        let collateral_token = e.register_stellar_asset_contract_v2(admin.clone());
+        StellarAssetClient::new(&e, &collateral_token.address()).mint(&user, &1_000_000);
         let collateral_pool_id = e.register(loan_pool::WASM, ());
-        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool_id);
+        register_pools(&e, &contract_id, &[&loan_pool_id, &collateral_pool_id]);
+        loan_pool::Client::new(&e, &collateral_pool_id).initialize(
+            &contract_id,
+            &loan_pool::Currency {
+                token_address: collateral_token.address(),
+                ticker: Symbol::new(&e, "USDC"),
+                decimals: 7,
+            },
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
 
-        // Register loan manager contract.
-        let contract_id = e.register(LoanManager, ());
-        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        // The discount grows from 0% to 50% over 100 ledgers, more than the 20% left by the 80%
+        // liquidation threshold of the collateral.
+        let id = contract_client.queue_action(&GovernanceAction::SetAuctionConfig(AuctionConfig {
+            start_discount: 0,
+            max_discount: 5_000_000,
+            duration: 100,
+        }));
+        pass_timelock_delay(&e);
+        e.register_at(&reflector_addr, oracle::WASM, ());
+        contract_client.execute_action(&id);
 
-        // ACT
-        // Initialize the loan pool and deposit some of the admin's funds.
-        loan_pool_client.initialize(&contract_id, &loan_currency, &8_000_000);
-        loan_pool_client.deposit(&admin, &1_000_000);
+        let loan_id = contract_client.create_loan(
+            &user,
+            &10_000,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: collateral_pool_id.clone(),
+                    amount: 12_600,
+                },
+            ],
+        );
 
-        collateral_pool_client.initialize(&contract_id, &collateral_currency, &8_000_000);
+        let usdc = oracle::Asset::Other(Symbol::new(&e, "USDC"));
+        let low_price = 9 * 10_i128.pow(oracle::PRICE_DECIMALS - 1);
+        reflector_client.set_price(&usdc, &low_price);
+        let start_ledger = contract_client.start_auction(&loan_id).start_ledger;
 
-        // Create a loan.
-        contract_client.create_loan(&user, &1_000, &loan_pool_id, &100_000, &collateral_pool_id);
+        // A discount under the cap is used as is, 5% makes 2_000 of debt buy 2_339 of collateral.
+        e.ledger()
+            .with_mut(|li| li.sequence_number = start_ledger + 10);
+        e.register_at(&reflector_addr, oracle::WASM, ());
+        reflector_client.set_price(&usdc, &low_price);
+        assert_eq!(contract_client.get_auction_discount(&loan_id), 500_000);
+        assert_eq!(
+            contract_client.liquidate(&admin, &loan_id, &2_000, &collateral_pool_id),
+            (8_000, 10_261)
+        );
 
-        contract_client.repay(&user, &2_000);
+        // At 50% the same debt would buy 4_444 of collateral, the cap of 20% limits it to 2_777.
+        e.ledger()
+            .with_mut(|li| li.sequence_number = start_ledger + 500);
+        e.register_at(&reflector_addr, oracle::WASM, ());
+        reflector_client.set_price(&usdc, &low_price);
+        assert_eq!(contract_client.get_auction_discount(&loan_id), 5_000_000);
+        let preview = contract_client.preview_liquidation(&loan_id, &2_000, &collateral_pool_id);
+        assert_eq!(preview.collateral_seized, 2_777);
+        assert_eq!(preview.bonus_value, 5_000_000_000);
+        assert_eq!(
+            contract_client.liquidate(&admin, &loan_id, &2_000, &collateral_pool_id),
+            (6_000, 7_484)
+        );
     }
+
     #[test]
-    fn liquidate() {
+    fn liquidate_chosen_collateral() {
         // ARRANGE
         let e = Env::default();
         e.mock_all_auths_allowing_non_root_auth();
@@ -1071,90 +4920,202 @@ mod tests {
         let loan_currency = loan_pool::Currency {
             token_address: loan_token.address(),
             ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
         };
 
-        let admin2 = Address::generate(&e);
-        let collateral_token = e.register_stellar_asset_contract_v2(admin2.clone());
-        let collateral_asset = StellarAssetClient::new(&e, &collateral_token.address());
-        let collateral_token_client = TokenClient::new(&e, &collateral_token.address());
-        let collateral_currency = loan_pool::Currency {
-            token_address: collateral_token.address(),
+        let usdc_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let usdc_asset = StellarAssetClient::new(&e, &usdc_token.address());
+        let usdc_currency = loan_pool::Currency {
+            token_address: usdc_token.address(),
             ticker: Symbol::new(&e, "USDC"),
+            decimals: 7,
+        };
+        let eurc_token = e.register_stellar_asset_contract_v2(admin.clone());
+        let eurc_asset = StellarAssetClient::new(&e, &eurc_token.address());
+        let eurc_token_client = TokenClient::new(&e, &eurc_token.address());
+        let eurc_currency = loan_pool::Currency {
+            token_address: eurc_token.address(),
+            ticker: Symbol::new(&e, "EURC"),
+            decimals: 7,
         };
 
         // Register mock Reflector contract.
-        let reflector_addr = Address::from_string(&String::from_str(&e, REFLECTOR_ADDRESS));
-        e.register_at(&reflector_addr, oracle::WASM, ());
+        let reflector_addr = e.register(oracle::WASM, ());
 
-        // Mint the user some coins
         let user = Address::generate(&e);
-        collateral_asset.mint(&user, &1_000_000);
-
-        assert_eq!(collateral_token_client.balance(&user), 1_000_000);
+        usdc_asset.mint(&user, &1_000_000);
+        eurc_asset.mint(&user, &1_000_000);
 
-        // Set up a loan pool with funds for borrowing.
         let loan_pool_id = e.register(loan_pool::WASM, ());
         let loan_pool_client = loan_pool::Client::new(&e, &loan_pool_id);
+        let usdc_pool_id = e.register(loan_pool::WASM, ());
+        let usdc_pool_client = loan_pool::Client::new(&e, &usdc_pool_id);
+        let eurc_pool_id = e.register(loan_pool::WASM, ());
+        let eurc_pool_client = loan_pool::Client::new(&e, &eurc_pool_id);
 
-        // Set up a loan_pool for the collaterals.
-        let collateral_pool_id = e.register(loan_pool::WASM, ());
-        let collateral_pool_client = loan_pool::Client::new(&e, &collateral_pool_id);
-
-        // Register loan manager contract.
         let contract_id = e.register(LoanManager, ());
         let contract_client = LoanManagerClient::new(&e, &contract_id);
+        contract_client.initialize(&admin, &reflector_addr, &TEST_TIMELOCK_DELAY);
+        register_pools(
+            &e,
+            &contract_id,
+            &[&loan_pool_id, &usdc_pool_id, &eurc_pool_id],
+        );
 
-        // ACT
-        // Initialize the loan pool and deposit some of the admin's funds.
-        loan_pool_client.initialize(&contract_id, &loan_currency, &8_000_000);
+        loan_pool_client.initialize(
+            &contract_id,
+            &loan_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
         loan_pool_client.deposit(&admin, &10_001);
+        usdc_pool_client.initialize(
+            &contract_id,
+            &usdc_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
+        eurc_pool_client.initialize(
+            &contract_id,
+            &eurc_currency,
+            &8_000_000,
+            &test_interest_rate_model().into(),
+            &TEST_RESERVE_FACTOR,
+        );
 
-        collateral_pool_client.initialize(&contract_id, &collateral_currency, &8_000_000);
-
-        // Create a loan.
-        contract_client.create_loan(&user, &10_000, &loan_pool_id, &12_505, &collateral_pool_id);
-
-        let user_loan = contract_client.get_loan(&user);
-
-        assert_eq!(user_loan.borrowed_amount, 10_000);
-
-        contract_client.add_interest(&user);
-
-        // Here borrowed amount should be the same as time has not moved. add_interest() is only called to store the LastUpdate sequence number.
-        assert_eq!(user_loan.borrowed_amount, 10_000);
-        assert_eq!(user_loan.health_factor, 10_004_000);
+        // Two collateral pools are counted together, so this is the same loan as in `liquidate`.
+        let loan_id = contract_client.create_loan(
+            &user,
+            &10_000,
+            &loan_pool_id,
+            &vec![
+                &e,
+                Collateral {
+                    pool: usdc_pool_id.clone(),
+                    amount: 5_000,
+                },
+                Collateral {
+                    pool: eurc_pool_id.clone(),
+                    amount: 7_505,
+                },
+            ],
+        );
+        assert_eq!(contract_client.get_loan(&loan_id).health_factor, 10_004_000);
 
         // Move time
         e.ledger().with_mut(|li| {
             li.sequence_number = 100_000 + 100_000;
             li.timestamp = 1 + 31_556_926;
         });
-
-        // A new instance of reflector mock needs to be created, they only live for one ledger.
-        let reflector_addr = Address::from_string(&String::from_str(&e, REFLECTOR_ADDRESS));
         e.register_at(&reflector_addr, oracle::WASM, ());
 
-        contract_client.add_interest(&user);
+        // ACT
+        // The bonus does not fit in the USDC collateral.
+        assert_eq!(
+            contract_client.try_liquidate(&admin, &loan_id, &5000, &usdc_pool_id),
+            Err(Ok(Error::InsufficientCollateral))
+        );
+        assert_eq!(
+            contract_client.try_liquidate(&admin, &loan_id, &5000, &loan_pool_id),
+            Err(Ok(Error::CollateralNotFound))
+        );
+        contract_client.liquidate(&admin, &loan_id, &5000, &eurc_pool_id);
 
-        let user_loan = contract_client.get_loan(&user);
+        // ASSERT
+        let user_loan = contract_client.get_loan(&loan_id);
+        assert_eq!(user_loan.borrowed_amount, 7_998);
+        assert_eq!(user_loan.health_factor, 7_256_814);
+        assert_eq!(
+            user_loan.collateral,
+            vec![
+                &e,
+                Collateral {
+                    pool: usdc_pool_id.clone(),
+                    amount: 5_000,
+                },
+                Collateral {
+                    pool: eurc_pool_id.clone(),
+                    amount: 2_255,
+                },
+            ]
+        );
+        assert_eq!(eurc_token_client.balance(&admin), 5_250);
+    }
 
-        assert_eq!(user_loan.borrowed_amount, 12_998);
-        assert_eq!(user_loan.health_factor, 7_696_568);
-        assert_eq!(user_loan.collateral_amount, 12_505);
+    #[test]
+    fn cannot_use_same_collateral_pool_twice() {
+        let e = Env::default();
+        e.mock_all_auths();
 
-        e.ledger().with_mut(|li| {
-            li.sequence_number = 100_000 + 1_000;
-        });
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        let user = Address::generate(&e);
+        let pool = Address::generate(&e);
+        register_pools(&e, &contract_id, &[&pool]);
 
-        let reflector_addr = Address::from_string(&String::from_str(&e, REFLECTOR_ADDRESS));
-        e.register_at(&reflector_addr, oracle::WASM, ());
+        let collateral = Collateral {
+            pool: pool.clone(),
+            amount: 100,
+        };
+        assert_eq!(
+            contract_client.try_create_loan(
+                &user,
+                &10,
+                &pool,
+                &vec![&e, collateral.clone(), collateral]
+            ),
+            Err(Ok(Error::InvalidCollateral))
+        );
+        assert_eq!(
+            contract_client.try_create_loan(&user, &10, &pool, &vec![&e]),
+            Err(Ok(Error::NoCollateral))
+        );
+    }
 
-        contract_client.liquidate(&admin, &user, &5000);
+    #[test]
+    fn cannot_use_unregistered_pools() {
+        let e = Env::default();
+        e.mock_all_auths();
 
-        let user_loan = contract_client.get_loan(&user);
+        let contract_id = e.register(LoanManager, ());
+        let contract_client = LoanManagerClient::new(&e, &contract_id);
+        let user = Address::generate(&e);
+        let pool = Address::generate(&e);
+        let fake_pool = Address::generate(&e);
+        register_pools(&e, &contract_id, &[&pool]);
 
-        assert_eq!(user_loan.borrowed_amount, 7_998);
-        assert_eq!(user_loan.health_factor, 7_256_814);
-        assert_eq!(user_loan.collateral_amount, 7_255);
+        // The pools are checked before any of them is called.
+        assert_eq!(
+            contract_client.try_create_loan(
+                &user,
+                &10,
+                &fake_pool,
+                &vec![
+                    &e,
+                    Collateral {
+                        pool: pool.clone(),
+                        amount: 100,
+                    }
+                ]
+            ),
+            Err(Ok(Error::PoolNotFound))
+        );
+        assert_eq!(
+            contract_client.try_create_loan(
+                &user,
+                &10,
+                &pool,
+                &vec![
+                    &e,
+                    Collateral {
+                        pool: fake_pool.clone(),
+                        amount: 100,
+                    }
+                ]
+            ),
+            Err(Ok(Error::PoolNotFound))
+        );
     }
 }
@@ -0,0 +1,80 @@
+use crate::contract::loan_pool;
+use soroban_sdk::contracttype;
+
+// Parameters of a loan pool's interest rate curve, see loan_pool::InterestRateModel.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InterestRateModel {
+    pub base_rate: i128,
+    pub kink_utilization: i128,
+    pub rate_at_kink: i128,
+    pub max_rate: i128,
+}
+
+impl From<InterestRateModel> for loan_pool::InterestRateModel {
+    fn from(model: InterestRateModel) -> Self {
+        loan_pool::InterestRateModel {
+            base_rate: model.base_rate,
+            kink_utilization: model.kink_utilization,
+            rate_at_kink: model.rate_at_kink,
+            max_rate: model.max_rate,
+        }
+    }
+}
+
+// How loans backed by a pool's token are liquidated, see loan_pool::LiquidationParams.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LiquidationParams {
+    pub liquidation_threshold: i128,
+    pub close_factor: i128,
+    pub liquidation_bonus: i128,
+}
+
+impl From<LiquidationParams> for loan_pool::LiquidationParams {
+    fn from(params: LiquidationParams) -> Self {
+        loan_pool::LiquidationParams {
+            liquidation_threshold: params.liquidation_threshold,
+            close_factor: params.close_factor,
+            liquidation_bonus: params.liquidation_bonus,
+        }
+    }
+}
+
+// Operating state of a loan pool, see loan_pool::PoolStatus.
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum PoolStatus {
+    Active = 0,
+    BorrowFrozen = 1,
+    DepositFrozen = 2,
+    Paused = 3,
+    Deprecated = 4,
+}
+
+impl From<PoolStatus> for loan_pool::PoolStatus {
+    fn from(status: PoolStatus) -> Self {
+        match status {
+            PoolStatus::Active => loan_pool::PoolStatus::Active,
+            PoolStatus::BorrowFrozen => loan_pool::PoolStatus::BorrowFrozen,
+            PoolStatus::DepositFrozen => loan_pool::PoolStatus::DepositFrozen,
+            PoolStatus::Paused => loan_pool::PoolStatus::Paused,
+            PoolStatus::Deprecated => loan_pool::PoolStatus::Deprecated,
+        }
+    }
+}
+
+// Outcome of a liquidation, see LoanManager::preview_liquidation.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LiquidationPreview {
+    // Largest amount of debt that can be repaid in one liquidation
+    pub max_amount: i128,
+    // Collateral the liquidator receives
+    pub collateral_seized: i128,
+    // Value of the seized collateral over the repaid debt, in the price scale of the oracle module
+    pub bonus_value: i128,
+    // Health factor of the loan after the liquidation
+    pub health_factor: i128,
+}
@@ -0,0 +1,204 @@
+use crate::storage_types::{
+    Auction, AuctionConfig, Collateral, Loan, OracleAsset, PendingAction, PricingMode,
+};
+use soroban_sdk::{contracttype, symbol_short, Address, Env, Symbol, Vec};
+
+/// Layout version of the loan manager's events, independent of the pools' version.
+pub const EVENT_VERSION: u32 = 1;
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LoanCreatedEvent {
+    pub version: u32,
+    pub loan: Loan,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LoanUpdatedEvent {
+    pub version: u32,
+    pub loan: Loan,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RepayEvent {
+    pub version: u32,
+    pub borrower: Address,
+    pub amount: i128,
+    // State of the loan after the repayment
+    pub loan: Loan,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LiquidateEvent {
+    pub version: u32,
+    pub liquidator: Address,
+    pub borrower: Address,
+    pub amount: i128,
+    pub collateral_seized: i128,
+    // State of the loan after the liquidation
+    pub loan: Loan,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LoanClosedEvent {
+    pub version: u32,
+    pub loan_id: u64,
+    pub borrower: Address,
+    // Debt paid back by the borrower
+    pub repaid_amount: i128,
+    // Debt removed as bad debt
+    pub written_off_amount: i128,
+    // Collateral returned to the borrower, or seized by the admin on a write-off
+    pub collateral: Vec<Collateral>,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ManagerParam {
+    Admin(Address),
+    PoolAdded(Address),
+    TimelockDelay(u64),
+    Oracles(Vec<Address>),
+    MaxPriceAge(Address, u64),
+    MaxPriceDeviation(i128),
+    PricingMode(Address, PricingMode),
+    OracleAsset(Address, OracleAsset),
+    AuctionConfig(AuctionConfig),
+    AuctionsDisabled,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParamChangedEvent {
+    pub version: u32,
+    pub param: ManagerParam,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AuctionEvent {
+    pub version: u32,
+    pub auction: Auction,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TimelockEvent {
+    pub version: u32,
+    pub action: PendingAction,
+}
+
+pub fn loan_created(e: &Env, loan: Loan) {
+    let topics = (Symbol::new(e, "loan_created"), loan.borrower.clone());
+    let event = LoanCreatedEvent {
+        version: EVENT_VERSION,
+        loan,
+    };
+    e.events().publish(topics, event);
+}
+
+pub fn loan_updated(e: &Env, loan: Loan) {
+    let topics = (Symbol::new(e, "loan_updated"), loan.borrower.clone());
+    let event = LoanUpdatedEvent {
+        version: EVENT_VERSION,
+        loan,
+    };
+    e.events().publish(topics, event);
+}
+
+pub fn repay(e: &Env, amount: i128, loan: Loan) {
+    let borrower = loan.borrower.clone();
+    let event = RepayEvent {
+        version: EVENT_VERSION,
+        borrower: borrower.clone(),
+        amount,
+        loan,
+    };
+    e.events()
+        .publish((symbol_short!("repay"), borrower), event);
+}
+
+pub fn liquidate(e: &Env, liquidator: Address, amount: i128, collateral_seized: i128, loan: Loan) {
+    let borrower = loan.borrower.clone();
+    let event = LiquidateEvent {
+        version: EVENT_VERSION,
+        liquidator,
+        borrower: borrower.clone(),
+        amount,
+        collateral_seized,
+        loan,
+    };
+    e.events()
+        .publish((symbol_short!("liquidate"), borrower), event);
+}
+
+pub fn loan_closed(
+    e: &Env,
+    loan_id: u64,
+    borrower: Address,
+    repaid_amount: i128,
+    written_off_amount: i128,
+    collateral: Vec<Collateral>,
+) {
+    let event = LoanClosedEvent {
+        version: EVENT_VERSION,
+        loan_id,
+        borrower: borrower.clone(),
+        repaid_amount,
+        written_off_amount,
+        collateral,
+    };
+    e.events()
+        .publish((Symbol::new(e, "loan_closed"), borrower), event);
+}
+
+pub fn param_changed(e: &Env, param: ManagerParam) {
+    let event = ParamChangedEvent {
+        version: EVENT_VERSION,
+        param,
+    };
+    e.events()
+        .publish((Symbol::new(e, "param_changed"),), event);
+}
+
+pub fn auction_started(e: &Env, auction: Auction) {
+    publish_auction_event(e, symbol_short!("started"), auction);
+}
+
+pub fn auction_ended(e: &Env, auction: Auction) {
+    publish_auction_event(e, symbol_short!("ended"), auction);
+}
+
+fn publish_auction_event(e: &Env, kind: Symbol, auction: Auction) {
+    let topics = (symbol_short!("auction"), kind, auction.loan_id);
+    let event = AuctionEvent {
+        version: EVENT_VERSION,
+        auction,
+    };
+    e.events().publish(topics, event);
+}
+
+pub fn action_queued(e: &Env, action: PendingAction) {
+    publish_timelock_event(e, symbol_short!("queued"), action);
+}
+
+pub fn action_executed(e: &Env, action: PendingAction) {
+    publish_timelock_event(e, symbol_short!("executed"), action);
+}
+
+pub fn action_cancelled(e: &Env, action: PendingAction) {
+    publish_timelock_event(e, symbol_short!("cancelled"), action);
+}
+
+fn publish_timelock_event(e: &Env, kind: Symbol, action: PendingAction) {
+    let topics = (symbol_short!("action"), kind, action.id);
+    let event = TimelockEvent {
+        version: EVENT_VERSION,
+        action,
+    };
+    e.events().publish(topics, event);
+}
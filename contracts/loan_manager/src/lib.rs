@@ -1,7 +1,12 @@
 #![no_std]
 #![allow(clippy::unused_unit)]
+#![allow(clippy::too_many_arguments)]
 
+mod auction;
 mod contract;
+mod dto;
+mod events;
 mod oracle;
 mod positions;
 mod storage_types;
+mod timelock;
@@ -1,7 +1,370 @@
-use soroban_sdk::contractimport;
+use crate::contract::Error;
+use crate::storage_types::{
+    LoansDataKey, OracleAsset, OracleAssetId, PricingMode, POSITIONS_BUMP_AMOUNT,
+    POSITIONS_LIFETIME_THRESHOLD,
+};
+use soroban_sdk::{contractimport, Address, Env, Map, Symbol, Vec};
 
 #[cfg(not(test))]
 contractimport!(file = "../../target/wasm32-unknown-unknown/release/reflector_oracle.wasm");
 
 #[cfg(test)]
 contractimport!(file = "../../target/wasm32-unknown-unknown/release/reflector_oracle_mock.wasm");
+
+const DECIMAL: i128 = 10_000_000;
+// Max price age of assets that don't have their own. Reflector updates its prices every 5 minutes.
+pub(crate) const DEFAULT_MAX_PRICE_AGE: u64 = 15 * 60;
+// Largest allowed difference between a source and the median price, 500_000 = 5%.
+pub(crate) const DEFAULT_MAX_PRICE_DEVIATION: i128 = 500_000;
+// Reflector keeps a limited history of prices.
+pub(crate) const MAX_TWAP_RECORDS: u32 = 20;
+// Prices of all sources are scaled to this many decimals, the same as Reflector uses.
+pub(crate) const PRICE_DECIMALS: u32 = 14;
+
+/// Which side of a loan an asset is priced for.
+#[derive(Clone, Copy)]
+pub enum PriceSide {
+    Collateral,
+    Debt,
+}
+
+/// Price sources in priority order. They all have to implement the Reflector interface.
+pub fn validate_oracles(e: &Env, oracles: &Vec<Address>) -> Result<(), Error> {
+    if oracles.is_empty() {
+        return Err(Error::InvalidOracles);
+    }
+    let mut seen: Map<Address, bool> = Map::new(e);
+    for oracle in oracles.iter() {
+        if seen.contains_key(oracle.clone()) {
+            return Err(Error::InvalidOracles);
+        }
+        seen.set(oracle, true);
+    }
+    Ok(())
+}
+
+pub fn write_oracles(e: &Env, oracles: &Vec<Address>) -> Result<(), Error> {
+    validate_oracles(e, oracles)?;
+    let key = LoansDataKey::Oracles;
+
+    e.storage().persistent().set(&key, oracles);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
+    Ok(())
+}
+
+pub fn read_oracles(e: &Env) -> Result<Vec<Address>, Error> {
+    let key = LoansDataKey::Oracles;
+
+    let oracles: Vec<Address> = e
+        .storage()
+        .persistent()
+        .get(&key)
+        .ok_or(Error::OracleNotFound)?;
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
+    Ok(oracles)
+}
+
+pub fn read_primary_oracle(e: &Env) -> Result<Address, Error> {
+    read_oracles(e)?.first().ok_or(Error::OracleNotFound)
+}
+
+/// The current oracles with `oracle` as the primary one. The old primary is dropped, and so is
+/// `oracle` from the fallbacks.
+pub fn with_primary_oracle(e: &Env, oracle: Address) -> Result<Vec<Address>, Error> {
+    let mut oracles = Vec::from_array(e, [oracle.clone()]);
+    for fallback in read_oracles(e)?.iter().skip(1) {
+        if fallback != oracle {
+            oracles.push_back(fallback);
+        }
+    }
+    Ok(oracles)
+}
+
+pub fn validate_max_price_age(max_age: u64) -> Result<(), Error> {
+    if max_age == 0 {
+        return Err(Error::InvalidMaxPriceAge);
+    }
+    Ok(())
+}
+
+pub fn write_max_price_age(e: &Env, pool: &Address, max_age: u64) -> Result<(), Error> {
+    validate_max_price_age(max_age)?;
+    let key = LoansDataKey::MaxPriceAge(pool.clone());
+
+    e.storage().persistent().set(&key, &max_age);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
+    Ok(())
+}
+
+pub fn read_max_price_age(e: &Env, pool: &Address) -> u64 {
+    let key = LoansDataKey::MaxPriceAge(pool.clone());
+
+    let max_age: Option<u64> = e.storage().persistent().get(&key);
+    if max_age.is_some() {
+        e.storage().persistent().extend_ttl(
+            &key,
+            POSITIONS_LIFETIME_THRESHOLD,
+            POSITIONS_BUMP_AMOUNT,
+        );
+    }
+    max_age.unwrap_or(DEFAULT_MAX_PRICE_AGE)
+}
+
+pub fn validate_max_price_deviation(max_deviation: i128) -> Result<(), Error> {
+    if max_deviation <= 0 || max_deviation > DECIMAL {
+        return Err(Error::InvalidMaxPriceDeviation);
+    }
+    Ok(())
+}
+
+pub fn write_max_price_deviation(e: &Env, max_deviation: i128) -> Result<(), Error> {
+    validate_max_price_deviation(max_deviation)?;
+    let key = LoansDataKey::MaxPriceDeviation;
+
+    e.storage().persistent().set(&key, &max_deviation);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
+    Ok(())
+}
+
+pub fn read_max_price_deviation(e: &Env) -> i128 {
+    let key = LoansDataKey::MaxPriceDeviation;
+
+    let max_deviation: Option<i128> = e.storage().persistent().get(&key);
+    if max_deviation.is_some() {
+        e.storage().persistent().extend_ttl(
+            &key,
+            POSITIONS_LIFETIME_THRESHOLD,
+            POSITIONS_BUMP_AMOUNT,
+        );
+    }
+    max_deviation.unwrap_or(DEFAULT_MAX_PRICE_DEVIATION)
+}
+
+pub fn validate_pricing_mode(mode: &PricingMode) -> Result<(), Error> {
+    match *mode {
+        PricingMode::Spot => Ok(()),
+        PricingMode::Twap(records) | PricingMode::Conservative(records) => {
+            if records == 0 || records > MAX_TWAP_RECORDS {
+                return Err(Error::InvalidPricingMode);
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn write_pricing_mode(e: &Env, pool: &Address, mode: &PricingMode) -> Result<(), Error> {
+    validate_pricing_mode(mode)?;
+    let key = LoansDataKey::PricingMode(pool.clone());
+
+    e.storage().persistent().set(&key, mode);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
+    Ok(())
+}
+
+pub fn read_pricing_mode(e: &Env, pool: &Address) -> PricingMode {
+    let key = LoansDataKey::PricingMode(pool.clone());
+
+    let mode: Option<PricingMode> = e.storage().persistent().get(&key);
+    if mode.is_some() {
+        e.storage().persistent().extend_ttl(
+            &key,
+            POSITIONS_LIFETIME_THRESHOLD,
+            POSITIONS_BUMP_AMOUNT,
+        );
+    }
+    mode.unwrap_or(PricingMode::Spot)
+}
+
+pub fn write_oracle_asset(e: &Env, pool: &Address, asset: &OracleAsset) {
+    let key = LoansDataKey::OracleAsset(pool.clone());
+
+    e.storage().persistent().set(&key, asset);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
+}
+
+/// Oracle asset of a pool. Pools without one are priced by their ticker from the shared oracles.
+pub fn read_oracle_asset(e: &Env, pool: &Address, ticker: Symbol) -> OracleAsset {
+    let key = LoansDataKey::OracleAsset(pool.clone());
+
+    let asset: Option<OracleAsset> = e.storage().persistent().get(&key);
+    if asset.is_some() {
+        e.storage().persistent().extend_ttl(
+            &key,
+            POSITIONS_LIFETIME_THRESHOLD,
+            POSITIONS_BUMP_AMOUNT,
+        );
+    }
+    asset.unwrap_or(ticker_asset(ticker))
+}
+
+pub fn ticker_asset(ticker: Symbol) -> OracleAsset {
+    OracleAsset {
+        asset: OracleAssetId::Other(ticker),
+        oracle: None,
+    }
+}
+
+/// Latest spot price of a pool's token.
+pub fn read_price(e: &Env, pool: &Address, ticker: Symbol) -> Result<i128, Error> {
+    let asset = read_oracle_asset(e, pool, ticker);
+    aggregate_price(e, pool, &asset, PricingMode::Spot, PriceSide::Debt)
+}
+
+/// Price of a pool's token for valuing one side of a loan, using the asset's pricing mode.
+pub fn read_price_for(
+    e: &Env,
+    pool: &Address,
+    ticker: Symbol,
+    side: PriceSide,
+) -> Result<i128, Error> {
+    let asset = read_oracle_asset(e, pool, ticker);
+    let mode = read_pricing_mode(e, pool);
+    aggregate_price(e, pool, &asset, mode, side)
+}
+
+/// Median of the usable prices of the asset. A source is skipped if it fails, has no price, or its
+/// price is not positive or older than the pool's max age, so a single source keeps working when
+/// the others are down. If no source is usable, the error of the primary source is returned.
+/// An asset with its own oracle is priced by that oracle alone.
+fn aggregate_price(
+    e: &Env,
+    pool: &Address,
+    oracle_asset: &OracleAsset,
+    mode: PricingMode,
+    side: PriceSide,
+) -> Result<i128, Error> {
+    let max_age = read_max_price_age(e, pool);
+    let asset = match oracle_asset.asset.clone() {
+        OracleAssetId::Stellar(address) => Asset::Stellar(address),
+        OracleAssetId::Other(symbol) => Asset::Other(symbol),
+    };
+    let oracles = match oracle_asset.oracle.clone() {
+        Some(oracle) => Vec::from_array(e, [oracle]),
+        None => read_oracles(e)?,
+    };
+
+    // Kept in ascending order.
+    let mut prices: Vec<i128> = Vec::new(e);
+    let mut primary_error = None;
+    for oracle in oracles.iter() {
+        match source_price(e, &oracle, &asset, max_age, mode, side) {
+            Ok(price) => {
+                let index = prices.binary_search(price).unwrap_or_else(|index| index);
+                prices.insert(index, price);
+            }
+            Err(err) => {
+                primary_error.get_or_insert(err);
+            }
+        }
+    }
+
+    let median = median(&prices).ok_or(primary_error.unwrap_or(Error::NoLastPrice))?;
+
+    // The prices are sorted, so the extremes are the furthest from the median.
+    let max_deviation = read_max_price_deviation(e);
+    for price in [prices.first(), prices.last()].into_iter().flatten() {
+        let deviation = price
+            .checked_sub(median)
+            .ok_or(Error::OverOrUnderFlow)?
+            .checked_abs()
+            .ok_or(Error::OverOrUnderFlow)?
+            .checked_mul(DECIMAL)
+            .ok_or(Error::OverOrUnderFlow)?
+            .checked_div(median)
+            .ok_or(Error::OverOrUnderFlow)?;
+        if deviation > max_deviation {
+            return Err(Error::PriceDeviation);
+        }
+    }
+    Ok(median)
+}
+
+/// Price of a single source. The spot price is checked even if only the TWAP is used, since an
+/// average of old prices is as stale as the prices themselves.
+fn source_price(
+    e: &Env,
+    oracle: &Address,
+    asset: &Asset,
+    max_age: u64,
+    mode: PricingMode,
+    side: PriceSide,
+) -> Result<i128, Error> {
+    let client = Client::new(e, oracle);
+    let price_data = match client.try_lastprice(asset) {
+        Ok(Ok(Some(price_data))) => price_data,
+        _ => return Err(Error::NoLastPrice),
+    };
+
+    let decimals = match client.try_decimals() {
+        Ok(Ok(decimals)) => decimals,
+        _ => return Err(Error::NoLastPrice),
+    };
+
+    let spot = normalize(price_data.price, decimals)?;
+    if spot <= 0 {
+        return Err(Error::InvalidPrice);
+    }
+    let age = e.ledger().timestamp().saturating_sub(price_data.timestamp);
+    if age > max_age {
+        return Err(Error::StalePrice);
+    }
+
+    let records = match mode {
+        PricingMode::Spot => return Ok(spot),
+        PricingMode::Twap(records) | PricingMode::Conservative(records) => records,
+    };
+    let twap = match client.try_twap(asset, &records) {
+        Ok(Ok(Some(twap))) => normalize(twap, decimals)?,
+        _ => return Err(Error::NoLastPrice),
+    };
+    if twap <= 0 {
+        return Err(Error::InvalidPrice);
+    }
+
+    // Collateral is valued at the lower price and debt at the higher one, so a short spike in
+    // either price can't make a loan look healthier or unhealthier than it is.
+    Ok(match (mode, side) {
+        (PricingMode::Conservative(_), PriceSide::Collateral) => spot.min(twap),
+        (PricingMode::Conservative(_), PriceSide::Debt) => spot.max(twap),
+        _ => twap,
+    })
+}
+
+/// Scale a price with the given decimals to `PRICE_DECIMALS`.
+fn normalize(price: i128, decimals: u32) -> Result<i128, Error> {
+    if decimals >= PRICE_DECIMALS {
+        let divisor = 10_i128
+            .checked_pow(decimals - PRICE_DECIMALS)
+            .ok_or(Error::OverOrUnderFlow)?;
+        Ok(price / divisor)
+    } else {
+        10_i128
+            .checked_pow(PRICE_DECIMALS - decimals)
+            .and_then(|multiplier| price.checked_mul(multiplier))
+            .ok_or(Error::OverOrUnderFlow)
+    }
+}
+
+fn median(sorted: &Vec<i128>) -> Option<i128> {
+    let len = sorted.len();
+    let upper = sorted.get(len / 2)?;
+    if len % 2 == 1 {
+        Some(upper)
+    } else {
+        let lower = sorted.get(len / 2 - 1)?;
+        // Rounds down, without overflowing on large prices.
+        Some(lower + (upper - lower) / 2)
+    }
+}
@@ -1,28 +1,213 @@
+use crate::auction;
+use crate::contract::HEALTH_FACTOR_THRESHOLD;
+use crate::events;
 use crate::storage_types::{
-    Loan, LoansDataKey, POSITIONS_BUMP_AMOUNT, POSITIONS_LIFETIME_THRESHOLD,
+    Collateral, Loan, LoanV1, LoansDataKey, LoansDataKeyV1, POSITIONS_BUMP_AMOUNT,
+    POSITIONS_LIFETIME_THRESHOLD,
 };
-use soroban_sdk::{Address, Env};
+use soroban_sdk::{vec, Address, Env, Vec};
 
-pub fn init_loan(e: &Env, addr: Address, loan: Loan) {
-    write_positions(e, addr, loan);
+/// Store a new loan under the next free loan ID and add it to the borrower's loans.
+pub fn init_loan(e: &Env, mut loan: Loan) -> u64 {
+    let key = LoansDataKey::NextLoanId;
+
+    let id: u64 = e.storage().persistent().get(&key).unwrap_or(0);
+    e.storage().persistent().set(&key, &(id + 1));
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
+
+    loan.id = id;
+    write_positions(e, &loan);
+
+    let mut user_loans = read_user_loans(e, &loan.borrower);
+    user_loans.push_back(id);
+    write_user_loans(e, &loan.borrower, &user_loans);
+
+    events::loan_created(e, loan);
+    id
 }
 
-fn write_positions(e: &Env, addr: Address, loan: Loan) {
-    let key = LoansDataKey::Loan(addr);
+/// Move a loan stored by the borrower's address in the first deployment under a new loan ID.
+/// Returns the new ID, or None if the borrower had no such loan.
+pub fn migrate_legacy_loan(e: &Env, borrower: &Address) -> Option<u64> {
+    let key = LoansDataKeyV1::Loan(borrower.clone());
+    let legacy: LoanV1 = e.storage().persistent().get(&key)?;
+    e.storage().persistent().remove(&key);
 
-    e.storage().persistent().set(&key, &loan);
+    let loan = Loan {
+        // Assigned when the loan is stored.
+        id: 0,
+        borrower: legacy.borrower,
+        borrowed_amount: legacy.borrowed_amount,
+        borrowed_from: legacy.borrowed_from,
+        collateral: vec![
+            e,
+            Collateral {
+                pool: legacy.collateral_from,
+                amount: legacy.collateral_amount,
+            },
+        ],
+        health_factor: legacy.health_factor,
+        unpaid_interest: legacy.unpaid_interest,
+        last_accrual: legacy.last_accrual,
+    };
+    Some(init_loan(e, loan))
+}
+
+pub fn write_positions(e: &Env, loan: &Loan) {
+    let key = LoansDataKey::Loan(loan.id);
+
+    e.storage().persistent().set(&key, loan);
 
     e.storage()
         .persistent()
         .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
 
-    e.events().publish(("Loan", "created"), key);
+    // Only loans with debt are listed. A repaid loan stays open until its collateral is taken out.
+    if loan.borrowed_amount > 0 {
+        add_active_loan(e, loan.id);
+    } else {
+        remove_active_loan(e, loan.id);
+    }
+
+    // A loan that is healthy again is no longer auctioned. If it becomes unhealthy again, a new
+    // auction starts from the start discount.
+    if loan.health_factor >= HEALTH_FACTOR_THRESHOLD {
+        auction::remove(e, loan.id);
+    }
 }
 
-pub fn read_positions(e: &Env, addr: Address) -> Option<Loan> {
-    let key = LoansDataKey::Loan(addr);
+pub fn read_positions(e: &Env, loan_id: u64) -> Option<Loan> {
+    let key = LoansDataKey::Loan(loan_id);
 
     let value: Option<Loan> = e.storage().persistent().get(&key)?;
 
     value
 }
+
+/// Remove a closed loan and drop it from the borrower's loans.
+pub fn remove_positions(e: &Env, loan: &Loan) {
+    e.storage()
+        .persistent()
+        .remove(&LoansDataKey::Loan(loan.id));
+
+    let mut user_loans = read_user_loans(e, &loan.borrower);
+    if let Some(index) = user_loans.first_index_of(loan.id) {
+        user_loans.remove(index);
+    }
+    write_user_loans(e, &loan.borrower, &user_loans);
+    remove_active_loan(e, loan.id);
+}
+
+pub fn read_user_loans(e: &Env, user: &Address) -> Vec<u64> {
+    let key = LoansDataKey::UserLoans(user.clone());
+
+    let loans: Option<Vec<u64>> = e.storage().persistent().get(&key);
+    if loans.is_some() {
+        e.storage().persistent().extend_ttl(
+            &key,
+            POSITIONS_LIFETIME_THRESHOLD,
+            POSITIONS_BUMP_AMOUNT,
+        );
+    }
+    loans.unwrap_or(Vec::new(e))
+}
+
+pub fn read_active_loan_count(e: &Env) -> u32 {
+    let key = LoansDataKey::ActiveLoanCount;
+
+    let count: Option<u32> = e.storage().persistent().get(&key);
+    if count.is_some() {
+        e.storage().persistent().extend_ttl(
+            &key,
+            POSITIONS_LIFETIME_THRESHOLD,
+            POSITIONS_BUMP_AMOUNT,
+        );
+    }
+    count.unwrap_or(0)
+}
+
+/// Id of the open loan at the given position of the list of all open loans.
+pub fn read_active_loan(e: &Env, position: u32) -> Option<u64> {
+    let key = LoansDataKey::ActiveLoan(position);
+
+    let loan_id: Option<u64> = e.storage().persistent().get(&key);
+    if loan_id.is_some() {
+        e.storage().persistent().extend_ttl(
+            &key,
+            POSITIONS_LIFETIME_THRESHOLD,
+            POSITIONS_BUMP_AMOUNT,
+        );
+    }
+    loan_id
+}
+
+fn add_active_loan(e: &Env, loan_id: u64) {
+    if e.storage()
+        .persistent()
+        .has(&LoansDataKey::ActiveLoanPosition(loan_id))
+    {
+        return;
+    }
+    let count = read_active_loan_count(e);
+    write_active_loan(e, count, loan_id);
+    write_active_loan_count(e, count + 1);
+}
+
+/// The last loan of the list is moved to the position of the removed one, so that the list stays
+/// without gaps.
+fn remove_active_loan(e: &Env, loan_id: u64) {
+    let position_key = LoansDataKey::ActiveLoanPosition(loan_id);
+    let Some(position) = e.storage().persistent().get::<_, u32>(&position_key) else {
+        return;
+    };
+    let last = read_active_loan_count(e) - 1;
+    if position != last {
+        if let Some(last_loan_id) = read_active_loan(e, last) {
+            write_active_loan(e, position, last_loan_id);
+        }
+    }
+    e.storage()
+        .persistent()
+        .remove(&LoansDataKey::ActiveLoan(last));
+    e.storage().persistent().remove(&position_key);
+    write_active_loan_count(e, last);
+}
+
+fn write_active_loan(e: &Env, position: u32, loan_id: u64) {
+    let key = LoansDataKey::ActiveLoan(position);
+    e.storage().persistent().set(&key, &loan_id);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
+
+    let key = LoansDataKey::ActiveLoanPosition(loan_id);
+    e.storage().persistent().set(&key, &position);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
+}
+
+fn write_active_loan_count(e: &Env, count: u32) {
+    let key = LoansDataKey::ActiveLoanCount;
+    e.storage().persistent().set(&key, &count);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
+}
+
+fn write_user_loans(e: &Env, user: &Address, loans: &Vec<u64>) {
+    let key = LoansDataKey::UserLoans(user.clone());
+
+    if loans.is_empty() {
+        e.storage().persistent().remove(&key);
+    } else {
+        e.storage().persistent().set(&key, loans);
+        e.storage().persistent().extend_ttl(
+            &key,
+            POSITIONS_LIFETIME_THRESHOLD,
+            POSITIONS_BUMP_AMOUNT,
+        );
+    }
+}
@@ -1,4 +1,5 @@
-use soroban_sdk::{contracttype, Address};
+use crate::dto::{InterestRateModel, LiquidationParams};
+use soroban_sdk::{contracttype, Address, BytesN, Symbol, Vec};
 
 /* Ledger Thresholds */
 
@@ -8,9 +9,31 @@ pub(crate) const POSITIONS_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
 pub(crate) const POSITIONS_LIFETIME_THRESHOLD: u32 = POSITIONS_BUMP_AMOUNT - DAY_IN_LEDGERS;
 
 /* Storage Types */
-#[derive(Clone)]
+// Tokens deposited as collateral to a pool
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Collateral {
+    pub pool: Address,
+    pub amount: i128,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 #[contracttype]
 pub struct Loan {
+    pub id: u64,
+    pub borrower: Address,
+    pub borrowed_amount: i128,
+    pub borrowed_from: Address,
+    pub collateral: Vec<Collateral>,
+    pub health_factor: i128,
+    pub unpaid_interest: i128,
+    pub last_accrual: i128,
+}
+
+// Loan as stored by the first deployment, one per borrower with a single collateral
+#[derive(Clone)]
+#[contracttype]
+pub struct LoanV1 {
     pub borrower: Address,
     pub borrowed_amount: i128,
     pub borrowed_from: Address,
@@ -21,12 +44,138 @@ pub struct Loan {
     pub last_accrual: i128,
 }
 
+// How the price of an asset is read from the oracles.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum PricingMode {
+    // Latest price
+    Spot,
+    // Time-weighted average of the given number of price records
+    Twap(u32),
+    // Lower of spot and TWAP for collateral, higher of them for debt
+    Conservative(u32),
+}
+
+// How a pool's token is identified in the oracle, same as the oracle's own asset type.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum OracleAssetId {
+    // Classic Stellar asset by its contract address
+    Stellar(Address),
+    // Any other asset by its symbol
+    Other(Symbol),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct OracleAsset {
+    pub asset: OracleAssetId,
+    // Oracle used for this asset only, instead of the shared oracles
+    pub oracle: Option<Address>,
+}
+
+// Dutch auction liquidations. The discount on seized collateral grows linearly from the start
+// discount to the max discount over the duration, 10000000 = 100%.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct AuctionConfig {
+    pub start_discount: i128,
+    pub max_discount: i128,
+    // Ledgers until the max discount is reached
+    pub duration: u32,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Auction {
+    pub loan_id: u64,
+    pub start_ledger: u32,
+}
+
+// Admin actions that only take effect after the timelock delay.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum GovernanceAction {
+    // New wasm hashes of the loan manager and the pools
+    Upgrade(BytesN<32>, BytesN<32>),
+    SetInterestModel(Address, InterestRateModel),
+    SetReserveFactor(Address, i128),
+    // Supply cap and borrow cap of the pool
+    SetPoolCaps(Address, Option<i128>, Option<i128>),
+    SetFlashLoanFee(Address, i128),
+    SetLiquidationParams(Address, LiquidationParams),
+    // New delay in seconds
+    SetTimelockDelay(u64),
+    // New price oracles in priority order
+    SetOracles(Vec<Address>),
+    // Replaces the primary price oracle and keeps the fallbacks
+    SetOracle(Address),
+    // Largest allowed difference from the median price, 10000000 = 100%
+    SetMaxPriceDeviation(i128),
+    // Pool address and how its token is priced
+    SetPricingMode(Address, PricingMode),
+    // Pool address and the max age of its token's price in seconds
+    SetMaxPriceAge(Address, u64),
+    // Pool address and the oracle asset that prices its token
+    SetOracleAsset(Address, OracleAsset),
+    // Liquidate by auction instead of with the fixed bonus of the collateral pool
+    SetAuctionConfig(AuctionConfig),
+    DisableAuctions,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PendingAction {
+    pub id: u64,
+    pub action: GovernanceAction,
+    // Timestamp after which the action can be executed
+    pub eta: u64,
+}
+
 #[derive(Clone)]
 #[contracttype]
 pub enum LoansDataKey {
     Admin,
+    // Price oracle contracts in priority order
+    Oracles,
+    // Largest allowed difference between the prices of the oracles
+    MaxPriceDeviation,
+    // Pricing mode of a pool's token
+    PricingMode(Address),
+    // Seconds after which the price of a pool's token is too old to be used
+    MaxPriceAge(Address),
+    // Oracle asset of a pool
+    OracleAsset(Address),
+    // Liquidation auctions are used if this is set
+    AuctionConfig,
+    // Running liquidation auction of a loan
+    Auction(u64),
+    // Ledger at which auctions were last turned off. Auctions started until then are ended.
+    AuctionsDisabledLedger,
     PoolAddresses,
-    // Users positions in the pool
-    Loan(Address),
+    // Loans by loan id
+    Loan(u64),
+    // Ids of the open loans of a user
+    UserLoans(Address),
+    // Id of the next created loan
+    NextLoanId,
+    // Ids of all open loans by their position in the list, and the position of each id
+    ActiveLoan(u32),
+    ActiveLoanPosition(u64),
+    ActiveLoanCount,
     LastUpdated,
+    // Seconds between queueing and executing a governance action
+    TimelockDelay,
+    // Queued governance actions by id
+    PendingActions,
+    // Id of the next queued governance action
+    NextActionId,
+}
+
+// Keys of the first deployment that are still read when migrating
+#[derive(Clone)]
+#[contracttype]
+pub enum LoansDataKeyV1 {
+    // Loan by its borrower
+    Loan(Address),
 }
@@ -0,0 +1,126 @@
+use crate::contract::Error;
+use crate::storage_types::{
+    GovernanceAction, LoansDataKey, PendingAction, POSITIONS_BUMP_AMOUNT,
+    POSITIONS_LIFETIME_THRESHOLD,
+};
+use soroban_sdk::{Env, Map, Vec};
+
+pub(crate) const DAY_IN_SECONDS: u64 = 86_400;
+// Shortest allowed delay, so that users always have time to exit before an action lands.
+pub(crate) const MIN_TIMELOCK_DELAY: u64 = DAY_IN_SECONDS;
+// Longest allowed delay, so that a typo can't lock governance for good.
+pub(crate) const MAX_TIMELOCK_DELAY: u64 = 30 * DAY_IN_SECONDS;
+// Time after the eta during which a queued action can still be executed.
+pub(crate) const GRACE_PERIOD: u64 = 14 * DAY_IN_SECONDS;
+
+pub fn validate_delay(delay: u64) -> Result<(), Error> {
+    if !(MIN_TIMELOCK_DELAY..=MAX_TIMELOCK_DELAY).contains(&delay) {
+        return Err(Error::InvalidTimelockDelay);
+    }
+    Ok(())
+}
+
+pub fn write_delay(e: &Env, delay: u64) {
+    let key = LoansDataKey::TimelockDelay;
+
+    e.storage().persistent().set(&key, &delay);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
+}
+
+pub fn read_delay(e: &Env) -> Result<u64, Error> {
+    let key = LoansDataKey::TimelockDelay;
+
+    let delay: u64 = e
+        .storage()
+        .persistent()
+        .get(&key)
+        .ok_or(Error::TimelockDelayNotFound)?;
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
+    Ok(delay)
+}
+
+fn read_queue(e: &Env) -> Map<u64, PendingAction> {
+    e.storage()
+        .persistent()
+        .get(&LoansDataKey::PendingActions)
+        .unwrap_or(Map::new(e))
+}
+
+fn write_queue(e: &Env, queue: &Map<u64, PendingAction>) {
+    let key = LoansDataKey::PendingActions;
+
+    e.storage().persistent().set(&key, queue);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, POSITIONS_LIFETIME_THRESHOLD, POSITIONS_BUMP_AMOUNT);
+}
+
+fn is_expired(e: &Env, pending: &PendingAction) -> bool {
+    e.ledger().timestamp() > pending.eta.saturating_add(GRACE_PERIOD)
+}
+
+/// Add the action to the queue, to be executed after the current delay. Expired actions are
+/// dropped from the queue.
+pub fn queue(e: &Env, action: GovernanceAction) -> Result<PendingAction, Error> {
+    let id_key = LoansDataKey::NextActionId;
+
+    let id: u64 = e.storage().persistent().get(&id_key).unwrap_or(0);
+    let eta = e
+        .ledger()
+        .timestamp()
+        .checked_add(read_delay(e)?)
+        .ok_or(Error::OverOrUnderFlow)?;
+
+    let pending = PendingAction { id, action, eta };
+    let mut queue = read_queue(e);
+    for (expired_id, _) in queue.iter().filter(|(_, queued)| is_expired(e, queued)) {
+        queue.remove(expired_id);
+    }
+    queue.set(id, pending.clone());
+    write_queue(e, &queue);
+    e.storage().persistent().set(&id_key, &(id + 1));
+    e.storage().persistent().extend_ttl(
+        &id_key,
+        POSITIONS_LIFETIME_THRESHOLD,
+        POSITIONS_BUMP_AMOUNT,
+    );
+
+    Ok(pending)
+}
+
+/// Remove the action from the queue if it's ready to be executed. An expired action stays queued
+/// until it's cancelled or the next action is queued, since an error rolls back the removal.
+pub fn dequeue_ready(e: &Env, id: u64) -> Result<PendingAction, Error> {
+    let pending = read_queue(e).get(id).ok_or(Error::ActionNotFound)?;
+
+    if e.ledger().timestamp() < pending.eta {
+        return Err(Error::TimelockNotReady);
+    }
+    if is_expired(e, &pending) {
+        return Err(Error::ActionExpired);
+    }
+    remove(e, id)
+}
+
+pub fn remove(e: &Env, id: u64) -> Result<PendingAction, Error> {
+    let mut queue = read_queue(e);
+    let pending = queue.get(id).ok_or(Error::ActionNotFound)?;
+    queue.remove(id);
+    write_queue(e, &queue);
+    Ok(pending)
+}
+
+/// Queued actions that haven't expired.
+pub fn pending_actions(e: &Env) -> Vec<PendingAction> {
+    let mut pending = Vec::new(e);
+    for queued in read_queue(e).values().iter() {
+        if !is_expired(e, &queued) {
+            pending.push_back(queued);
+        }
+    }
+    pending
+}
@@ -0,0 +1,76 @@
+use crate::pool::Error;
+use crate::storage_types::{AllowanceDataKey, AllowanceValue, PoolDataKey};
+use soroban_sdk::{Address, Env};
+
+pub fn read_allowance(e: &Env, from: Address, spender: Address) -> AllowanceValue {
+    let key = PoolDataKey::Allowance(AllowanceDataKey { from, spender });
+
+    if let Some(allowance) = e.storage().temporary().get::<_, AllowanceValue>(&key) {
+        if allowance.expiration_ledger < e.ledger().sequence() {
+            AllowanceValue {
+                amount: 0,
+                expiration_ledger: allowance.expiration_ledger,
+            }
+        } else {
+            allowance
+        }
+    } else {
+        AllowanceValue {
+            amount: 0,
+            expiration_ledger: 0,
+        }
+    }
+}
+
+pub fn write_allowance(
+    e: &Env,
+    from: Address,
+    spender: Address,
+    amount: i128,
+    expiration_ledger: u32,
+) -> Result<(), Error> {
+    if amount > 0 && expiration_ledger < e.ledger().sequence() {
+        return Err(Error::InvalidExpirationLedger);
+    }
+
+    let key = PoolDataKey::Allowance(AllowanceDataKey { from, spender });
+    let allowance = AllowanceValue {
+        amount,
+        expiration_ledger,
+    };
+    e.storage().temporary().set(&key, &allowance);
+
+    if amount > 0 {
+        // The allowance lives in temporary storage until it expires.
+        let live_for = expiration_ledger
+            .checked_sub(e.ledger().sequence())
+            .ok_or(Error::OverOrUnderFlow)?;
+        e.storage().temporary().extend_ttl(&key, live_for, live_for);
+    }
+    Ok(())
+}
+
+pub fn spend_allowance(
+    e: &Env,
+    from: Address,
+    spender: Address,
+    amount: i128,
+) -> Result<(), Error> {
+    let allowance = read_allowance(e, from.clone(), spender.clone());
+    if allowance.amount < amount {
+        return Err(Error::InsufficientAllowance);
+    }
+    if amount > 0 {
+        write_allowance(
+            e,
+            from,
+            spender,
+            allowance
+                .amount
+                .checked_sub(amount)
+                .ok_or(Error::OverOrUnderFlow)?,
+            allowance.expiration_ledger,
+        )?;
+    }
+    Ok(())
+}
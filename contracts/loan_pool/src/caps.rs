@@ -0,0 +1,61 @@
+use crate::dto::CapHeadroom;
+use crate::pool::{self, Error};
+use soroban_sdk::Env;
+
+/// Tokens supplied to the pool, both lendable funds and collateral.
+pub fn total_supply(e: &Env) -> Result<i128, Error> {
+    pool::read_total_balance(e)?
+        .checked_add(pool::read_total_collateral(e))
+        .ok_or(Error::OverOrUnderFlow)
+}
+
+/// Principal currently lent out of the pool.
+pub fn total_borrowed(e: &Env) -> Result<i128, Error> {
+    pool::read_total_balance(e)?
+        .checked_sub(pool::read_available_balance(e)?)
+        .ok_or(Error::OverOrUnderFlow)
+}
+
+pub fn require_within_supply_cap(e: &Env, amount: i128) -> Result<(), Error> {
+    if let Some(cap) = pool::read_supply_cap(e) {
+        let new_supply = total_supply(e)?
+            .checked_add(amount)
+            .ok_or(Error::OverOrUnderFlow)?;
+        if new_supply > cap {
+            return Err(Error::SupplyCapExceeded);
+        }
+    }
+    Ok(())
+}
+
+pub fn require_within_borrow_cap(e: &Env, amount: i128) -> Result<(), Error> {
+    if let Some(cap) = pool::read_borrow_cap(e) {
+        let new_borrowed = total_borrowed(e)?
+            .checked_add(amount)
+            .ok_or(Error::OverOrUnderFlow)?;
+        if new_borrowed > cap {
+            return Err(Error::BorrowCapExceeded);
+        }
+    }
+    Ok(())
+}
+
+pub fn validate_cap(cap: Option<i128>) -> Result<(), Error> {
+    match cap {
+        Some(cap) if cap < 0 => Err(Error::InvalidCap),
+        _ => Ok(()),
+    }
+}
+
+/// Headroom is zero rather than negative if a cap was lowered below the current amount.
+pub fn headroom(e: &Env) -> Result<CapHeadroom, Error> {
+    let supply = match pool::read_supply_cap(e) {
+        Some(cap) => Some(cap.saturating_sub(total_supply(e)?).max(0)),
+        None => None,
+    };
+    let borrow = match pool::read_borrow_cap(e) {
+        Some(cap) => Some(cap.saturating_sub(total_borrowed(e)?).max(0)),
+        None => None,
+    };
+    Ok(CapHeadroom { supply, borrow })
+}
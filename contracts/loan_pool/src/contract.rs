@@ -1,12 +1,22 @@
-use crate::dto::PoolState;
-use crate::interest::{self, get_interest};
+use crate::allowance;
+use crate::caps;
+use crate::dto::{CapHeadroom, LiquidationParams, PoolState};
+use crate::events::{self, PoolParam};
+use crate::flash_loan::FlashLoanReceiverClient;
+use crate::interest;
+use crate::liquidation;
+use crate::metadata;
 use crate::pool::{Currency, Error};
 use crate::positions;
-use crate::storage_types::PoolDataKey;
+use crate::shares;
+use crate::status;
+use crate::storage_types::{InterestRateModel, PoolConfig, PoolStatus};
 use crate::{pool, storage_types::Positions};
 
+use soroban_sdk::token::TokenInterface;
 use soroban_sdk::{
-    contract, contractimpl, contractmeta, symbol_short, token, Address, BytesN, Env,
+    contract, contractimpl, contractmeta, panic_with_error, symbol_short, token, Address, Bytes,
+    BytesN, Env, String,
 };
 
 // Metadata that is added on to the WASM custom section
@@ -15,6 +25,11 @@ contractmeta!(
     val = "Lending pool with variable interest rate."
 );
 
+// Reserve factor of 10_000_000 sends all paid interest to reserves.
+pub(crate) const RESERVE_FACTOR_DECIMAL: i128 = 10_000_000;
+// Flash loan fee of 10_000_000 is 100% of the borrowed amount.
+const FLASH_LOAN_FEE_DECIMAL: i128 = 10_000_000;
+
 #[contract]
 struct LoanPoolContract;
 
@@ -27,7 +42,12 @@ impl LoanPoolContract {
         loan_manager_addr: Address,
         currency: Currency,
         liquidation_threshold: i128,
-    ) {
+        interest_rate_model: InterestRateModel,
+        reserve_factor: i128,
+    ) -> Result<(), Error> {
+        interest::validate_model(&interest_rate_model)?;
+        Self::validate_reserve_factor(reserve_factor)?;
+
         pool::write_loan_manager_addr(&e, loan_manager_addr);
         pool::write_currency(&e, currency);
         pool::write_liquidation_threshold(&e, liquidation_threshold);
@@ -36,7 +56,17 @@ impl LoanPoolContract {
         pool::write_available_balance(&e, 0);
         pool::write_accrual(&e, 10_000_000); // Default initial accrual value.
         pool::write_accrual_last_updated(&e, e.ledger().timestamp());
-        pool::change_interest_rate_multiplier(&e, 1); // Temporary parameter
+        pool::write_interest_rate_model(&e, interest_rate_model);
+        pool::write_reserve_factor(&e, reserve_factor);
+        pool::write_total_reserves(&e, 0);
+        pool::write_scaled_debt(&e, 0);
+        pool::write_pool_config(
+            &e,
+            PoolConfig {
+                status: PoolStatus::Active,
+            },
+        );
+        Ok(())
     }
 
     pub fn upgrade(e: Env, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
@@ -47,21 +77,148 @@ impl LoanPoolContract {
         Ok(())
     }
 
-    pub fn change_interest_rate_multiplier(e: Env, multiplier: i128) -> Result<(), Error> {
+    /// Store the data of a pool deployed by an older version in the current layout. The loan
+    /// manager calls this after upgrading the pool.
+    pub fn migrate(e: Env) -> Result<(), Error> {
+        let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
+        loan_manager_addr.require_auth();
+
+        pool::migrate_currency(&e)
+    }
+
+    /// Replace the interest rate curve of the pool. Interest accrued so far is calculated with the old curve.
+    pub fn set_interest_model(e: Env, model: InterestRateModel) -> Result<(), Error> {
+        let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
+        loan_manager_addr.require_auth();
+
+        interest::validate_model(&model)?;
+        Self::add_interest_to_accrual(e.clone())?;
+
+        pool::write_interest_rate_model(&e, model.clone());
+        events::param_changed(&e, PoolParam::InterestRateModel(model));
+        Ok(())
+    }
+
+    /// Set the share of paid interest that goes to the protocol's reserves, 10_000_000 = 100%.
+    pub fn set_reserve_factor(e: Env, reserve_factor: i128) -> Result<(), Error> {
+        let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
+        loan_manager_addr.require_auth();
+
+        Self::validate_reserve_factor(reserve_factor)?;
+        pool::write_reserve_factor(&e, reserve_factor);
+        events::param_changed(&e, PoolParam::ReserveFactor(reserve_factor));
+        Ok(())
+    }
+
+    /// Move the pool to another state, e.g. to freeze borrowing during an incident.
+    pub fn set_status(e: Env, status: PoolStatus) -> Result<(), Error> {
+        let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
+        loan_manager_addr.require_auth();
+
+        let mut config = pool::read_pool_config(&e);
+        status::validate_transition(config.status, status)?;
+        config.status = status;
+        pool::write_pool_config(&e, config);
+
+        events::param_changed(&e, PoolParam::Status(status));
+        Ok(())
+    }
+
+    /// Limit the tokens supplied to and borrowed from the pool. `None` removes the cap.
+    pub fn set_caps(
+        e: Env,
+        supply_cap: Option<i128>,
+        borrow_cap: Option<i128>,
+    ) -> Result<(), Error> {
+        let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
+        loan_manager_addr.require_auth();
+
+        caps::validate_cap(supply_cap)?;
+        caps::validate_cap(borrow_cap)?;
+        pool::write_supply_cap(&e, supply_cap);
+        pool::write_borrow_cap(&e, borrow_cap);
+
+        events::param_changed(&e, PoolParam::SupplyCap(supply_cap));
+        events::param_changed(&e, PoolParam::BorrowCap(borrow_cap));
+        Ok(())
+    }
+
+    /// Set the fee charged on flash loans, 10_000_000 = 100% of the borrowed amount.
+    pub fn set_flash_loan_fee(e: Env, fee: i128) -> Result<(), Error> {
+        let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
+        loan_manager_addr.require_auth();
+
+        Self::validate_flash_loan_fee(fee)?;
+        pool::write_flash_loan_fee(&e, fee);
+        events::param_changed(&e, PoolParam::FlashLoanFee(fee));
+        Ok(())
+    }
+
+    /// Check a parameter without setting it. The loan manager uses this to reject a governance
+    /// action when it's queued instead of after the timelock.
+    pub fn validate_param(e: Env, param: PoolParam) -> Result<(), Error> {
+        match param {
+            PoolParam::InterestRateModel(model) => interest::validate_model(&model),
+            PoolParam::ReserveFactor(reserve_factor) => {
+                Self::validate_reserve_factor(reserve_factor)
+            }
+            PoolParam::Status(status) => {
+                status::validate_transition(pool::read_pool_config(&e).status, status)
+            }
+            PoolParam::SupplyCap(cap) | PoolParam::BorrowCap(cap) => caps::validate_cap(cap),
+            PoolParam::FlashLoanFee(fee) => Self::validate_flash_loan_fee(fee),
+            PoolParam::LiquidationParams(params) => liquidation::validate(&params),
+        }
+    }
+
+    /// Set how loans backed by the pool's token are liquidated.
+    pub fn set_liquidation_params(e: Env, params: LiquidationParams) -> Result<(), Error> {
         let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
         loan_manager_addr.require_auth();
 
-        pool::change_interest_rate_multiplier(&e, multiplier);
+        liquidation::validate(&params)?;
+        liquidation::write(&e, &params);
+        events::param_changed(&e, PoolParam::LiquidationParams(params));
         Ok(())
     }
 
-    /// Deposits token. Also, mints pool shares for the "user" Identifier.
+    /// Transfer tokens from the protocol's reserves out of the pool.
+    pub fn claim_reserves(e: Env, to: Address, amount: i128) -> Result<i128, Error> {
+        let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
+        loan_manager_addr.require_auth();
+
+        if amount <= 0 {
+            return Err(Error::NegativeAmount);
+        }
+        if amount > pool::read_total_reserves(&e) {
+            return Err(Error::InsufficientReserves);
+        }
+        let total_reserves =
+            pool::change_total_reserves(&e, amount.checked_neg().ok_or(Error::OverOrUnderFlow)?)?;
+
+        let client = token::Client::new(&e, &pool::read_currency(&e)?.token_address);
+        client.transfer(&e.current_contract_address(), &to, &amount);
+
+        events::reserves_claimed(&e, to, amount, total_reserves);
+        Ok(total_reserves)
+    }
+
+    /// Deposits token. Also, mints pool shares for the "user" Identifier. Returns amount of shares minted.
     pub fn deposit(e: Env, user: Address, amount: i128) -> Result<i128, Error> {
         user.require_auth();
+        status::require_deposits_allowed(&e)?;
         if amount <= 0 {
             Err(Error::NegativeDeposit)
         } else {
             Self::add_interest_to_accrual(e.clone())?;
+            caps::require_within_supply_cap(&e, amount)?;
+            shares::sweep_unowned_tokens(&e)?;
+
+            // Shares have to be calculated before the balance changes.
+            let shares = shares::tokens_to_shares_down(&e, amount)?;
+            if shares <= 0 {
+                return Err(Error::AmountTooSmall);
+            }
 
             let token_address = pool::read_currency(&e)?.token_address;
 
@@ -69,7 +226,7 @@ impl LoanPoolContract {
             client.transfer(&user, &e.current_contract_address(), &amount);
 
             pool::change_available_balance(&e, amount)?;
-            pool::change_total_shares(&e, amount)?;
+            pool::change_total_shares(&e, shares)?;
             pool::change_total_balance(&e, amount)?;
 
             // Increase users position in pool as they deposit
@@ -77,71 +234,62 @@ impl LoanPoolContract {
             // liabilities & collateral stays intact
             let liabilities: i128 = 0; // temp test param
             let collateral: i128 = 0; // temp test param
-            positions::increase_positions(&e, user.clone(), amount, liabilities, collateral)?;
-
-            Ok(amount)
+            positions::increase_positions(&e, user.clone(), shares, liabilities, collateral)?;
+            e.events().publish(
+                (
+                    symbol_short!("mint"),
+                    e.current_contract_address(),
+                    user.clone(),
+                ),
+                shares,
+            );
+            events::deposit(&e, user, amount, shares);
+
+            Ok(shares)
         }
     }
 
-    /// Transfers share tokens back, burns them and gives corresponding amount of tokens back to user. Returns amount of tokens withdrawn
+    /// Burns the shares worth `amount` tokens and transfers the tokens to the user.
     pub fn withdraw(e: Env, user: Address, amount: i128) -> Result<PoolState, Error> {
         user.require_auth();
+        if amount <= 0 {
+            return Err(Error::WithdrawIsNegative);
+        }
 
         Self::add_interest_to_accrual(e.clone())?;
 
-        // Get users receivables
-        let Positions {
-            receivable_shares, ..
-        } = positions::read_positions(&e, &user);
+        let shares = shares::tokens_to_shares_up(&e, amount)?;
+        Self::withdraw_tokens(&e, &user, amount, shares)
+    }
 
-        // Check that user is not trying to move more than receivables (TODO: also include collateral?)
-        if amount > receivable_shares {
+    /// Burns the given amount of shares and transfers the tokens they are worth to the user.
+    pub fn withdraw_shares(e: Env, user: Address, shares: i128) -> Result<PoolState, Error> {
+        user.require_auth();
+        if shares <= 0 {
             return Err(Error::WithdrawIsNegative);
         }
 
-        let available_balance_tokens = Self::get_available_balance(e.clone())?;
-        if amount > available_balance_tokens {
-            return Err(Error::WithdrawOverBalance);
-        }
-        let total_balance_shares = Self::get_total_balance_shares(e.clone())?;
-        let total_balance_tokens = Self::get_contract_balance(e.clone())?;
-        let shares_to_decrease = amount
-            .checked_mul(total_balance_shares)
-            .ok_or(Error::OverOrUnderFlow)?
-            .checked_div(total_balance_tokens)
-            .ok_or(Error::OverOrUnderFlow)?;
+        Self::add_interest_to_accrual(e.clone())?;
 
-        let new_available_balance_tokens = pool::change_available_balance(
-            &e,
-            amount.checked_neg().ok_or(Error::OverOrUnderFlow)?,
-        )?;
-        let new_total_balance_tokens =
-            pool::change_total_balance(&e, amount.checked_neg().ok_or(Error::OverOrUnderFlow)?)?;
-        let new_total_balance_shares = pool::change_total_shares(&e, shares_to_decrease)?;
-        let liabilities: i128 = 0;
-        let collateral: i128 = 0;
-        positions::decrease_positions(
-            &e,
-            user.clone(),
-            shares_to_decrease,
-            liabilities,
-            collateral,
-        )?;
+        let amount = shares::shares_to_tokens(&e, shares)?;
+        if amount <= 0 {
+            return Err(Error::AmountTooSmall);
+        }
+        Self::withdraw_tokens(&e, &user, amount, shares)
+    }
 
-        // Transfer tokens from pool to user
-        let token_address = &pool::read_currency(&e)?.token_address;
-        let client = token::Client::new(&e, token_address);
-        client.transfer(&e.current_contract_address(), &user, &amount);
+    /// Burns all of the user's shares and transfers the tokens they are worth to the user.
+    pub fn withdraw_all(e: Env, user: Address) -> Result<PoolState, Error> {
+        user.require_auth();
 
-        let new_annual_interest_rate = Self::get_interest(e.clone())?;
+        Self::add_interest_to_accrual(e.clone())?;
 
-        let pool_state = PoolState {
-            total_balance_tokens: new_total_balance_tokens,
-            available_balance_tokens: new_available_balance_tokens,
-            total_balance_shares: new_total_balance_shares,
-            annual_interest_rate: new_annual_interest_rate,
-        };
-        Ok(pool_state)
+        let shares = positions::read_positions(&e, &user).receivable_shares;
+        let amount = shares::shares_to_tokens(&e, shares)?;
+        if amount <= 0 {
+            return Err(Error::AmountTooSmall);
+        }
+        Self::withdraw_tokens(&e, &user, amount, shares)
     }
 
     /// Borrow tokens from the pool
@@ -154,8 +302,10 @@ impl LoanPoolContract {
         let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
         loan_manager_addr.require_auth();
         user.require_auth();
+        status::require_borrows_allowed(&e)?;
 
         Self::add_interest_to_accrual(e.clone())?;
+        caps::require_within_borrow_cap(&e, amount)?;
 
         let balance = pool::read_available_balance(&e)?;
         assert!(
@@ -171,24 +321,92 @@ impl LoanPoolContract {
         let collateral: i128 = 0; // temp test param
         let receivables: i128 = 0; // temp test param
         positions::increase_positions(&e, user.clone(), receivables, amount, collateral)?;
+        interest::change_total_debt(&e, amount)?;
 
         let token_address = &pool::read_currency(&e)?.token_address;
         let client = token::Client::new(&e, token_address);
         client.transfer(&e.current_contract_address(), &user, &amount);
 
+        events::borrow(&e, user, amount);
         Ok(amount)
     }
 
+    /// Lend tokens to `receiver` for the duration of one call to its `exec_op`.
+    /// The receiver has to pay back the amount and the fee before `exec_op` returns.
+    /// `initiator` is passed on to the receiver so it can refuse loans it did not ask for.
+    /// Returns the fee paid.
+    pub fn flash_loan(
+        e: Env,
+        initiator: Address,
+        receiver: Address,
+        amount: i128,
+        params: Bytes,
+    ) -> Result<i128, Error> {
+        initiator.require_auth();
+        status::require_borrows_allowed(&e)?;
+        if amount <= 0 {
+            return Err(Error::NegativeAmount);
+        }
+        if amount > pool::read_available_balance(&e)? {
+            return Err(Error::FlashLoanOverBalance);
+        }
+
+        Self::add_interest_to_accrual(e.clone())?;
+
+        // Round the fee up so that small loans are not free.
+        let fee_rate = pool::read_flash_loan_fee(&e);
+        let fee = amount
+            .checked_mul(fee_rate)
+            .ok_or(Error::OverOrUnderFlow)?
+            .checked_add(FLASH_LOAN_FEE_DECIMAL - 1)
+            .ok_or(Error::OverOrUnderFlow)?
+            .checked_div(FLASH_LOAN_FEE_DECIMAL)
+            .ok_or(Error::OverOrUnderFlow)?;
+
+        let token_address = pool::read_currency(&e)?.token_address;
+        let client = token::Client::new(&e, &token_address);
+        let balance_before = client.balance(&e.current_contract_address());
+
+        client.transfer(&e.current_contract_address(), &receiver, &amount);
+        FlashLoanReceiverClient::new(&e, &receiver).exec_op(
+            &initiator,
+            &token_address,
+            &amount,
+            &fee,
+            &params,
+        );
+
+        // The receiver cannot call back into the pool, so the balance can only grow by repayment.
+        let balance_after = client.balance(&e.current_contract_address());
+        let expected_balance = balance_before
+            .checked_add(fee)
+            .ok_or(Error::OverOrUnderFlow)?;
+        if balance_after < expected_balance {
+            return Err(Error::FlashLoanNotRepaid);
+        }
+
+        // The fee is shared between reserves and suppliers like paid interest.
+        let amount_to_reserves = Self::add_to_reserves(&e, fee)?;
+        pool::change_available_balance(&e, fee - amount_to_reserves)?;
+        pool::change_total_balance(&e, fee - amount_to_reserves)?;
+
+        events::flash_loan(&e, receiver, amount, fee);
+        Ok(fee)
+    }
+
     /// Deposit tokens to the pool to be used as collateral
     pub fn deposit_collateral(e: Env, user: Address, amount: i128) -> Result<i128, Error> {
         user.require_auth();
+        status::require_deposits_allowed(&e)?;
         assert!(amount > 0, "Amount must be positive!");
 
         Self::add_interest_to_accrual(e.clone())?;
+        caps::require_within_supply_cap(&e, amount)?;
 
         let token_address = &pool::read_currency(&e)?.token_address;
         let client = token::Client::new(&e, token_address);
         client.transfer(&user, &e.current_contract_address(), &amount);
+        pool::change_total_collateral(&e, amount)?;
 
         // Increase users position in pool as they deposit
         // as this is collateral amount is added to collateral and
@@ -197,11 +415,13 @@ impl LoanPoolContract {
         let receivables: i128 = 0; // temp test param
         positions::increase_positions(&e, user.clone(), receivables, liabilities, amount)?;
 
+        events::collateral_deposited(&e, user, amount);
         Ok(amount)
     }
 
     pub fn withdraw_collateral(e: Env, user: Address, amount: i128) -> Result<i128, Error> {
         user.require_auth();
+        status::require_not_paused(&e)?;
         Self::add_interest_to_accrual(e.clone())?;
 
         let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
@@ -211,6 +431,7 @@ impl LoanPoolContract {
         let token_address = &pool::read_currency(&e)?.token_address;
         let client = token::Client::new(&e, token_address);
         client.transfer(&e.current_contract_address(), &user, &amount);
+        pool::change_total_collateral(&e, amount.checked_neg().ok_or(Error::OverOrUnderFlow)?)?;
 
         // Increase users position in pool as they deposit
         // as this is collateral amount is added to collateral and
@@ -219,51 +440,17 @@ impl LoanPoolContract {
         let receivables: i128 = 0; // temp test param
         positions::decrease_positions(&e, user.clone(), receivables, liabilities, amount)?;
 
+        events::collateral_withdrawn(&e, user, amount);
         Ok(amount)
     }
 
     pub fn add_interest_to_accrual(e: Env) -> Result<(), Error> {
-        const DECIMAL: i128 = 10000000;
-        const SECONDS_IN_YEAR: u64 = 31_556_926;
-
         let current_timestamp = e.ledger().timestamp();
-        let accrual = pool::read_accrual(&e)?;
-        let accrual_last_update = pool::read_accrual_last_updated(&e)?;
-        let ledgers_since_update = current_timestamp
-            .checked_sub(accrual_last_update)
-            .ok_or(Error::OverOrUnderFlow)?;
-        let ledger_ratio: i128 = (i128::from(ledgers_since_update))
-            .checked_mul(DECIMAL)
-            .ok_or(Error::OverOrUnderFlow)?
-            .checked_div(i128::from(SECONDS_IN_YEAR))
-            .ok_or(Error::OverOrUnderFlow)?;
-
-        let interest_rate: i128 = get_interest(e.clone())?;
-        let interest_amount_in_year: i128 = accrual
-            .checked_mul(interest_rate)
-            .ok_or(Error::OverOrUnderFlow)?
-            .checked_div(DECIMAL)
-            .ok_or(Error::OverOrUnderFlow)?;
-        let interest_since_update: i128 = interest_amount_in_year
-            .checked_mul(ledger_ratio)
-            .ok_or(Error::OverOrUnderFlow)?
-            .checked_div(DECIMAL)
-            .ok_or(Error::OverOrUnderFlow)?;
-        let new_accrual: i128 = accrual
-            .checked_add(interest_since_update)
-            .ok_or(Error::OverOrUnderFlow)?;
+        let new_accrual = interest::projected_accrual(&e)?;
 
         pool::write_accrual_last_updated(&e, current_timestamp);
-        e.events().publish(
-            (PoolDataKey::AccrualLastUpdate, symbol_short!("updated")),
-            current_timestamp,
-        );
-
         pool::write_accrual(&e, new_accrual);
-        e.events().publish(
-            (PoolDataKey::Accrual, symbol_short!("updated")),
-            new_accrual,
-        );
+        events::accrual_updated(&e, new_accrual, current_timestamp);
         Ok(())
     }
 
@@ -271,6 +458,11 @@ impl LoanPoolContract {
         pool::read_accrual(e)
     }
 
+    /// Accrual with the interest up to now added, without storing it.
+    pub fn get_projected_accrual(e: &Env) -> Result<i128, Error> {
+        interest::projected_accrual(e)
+    }
+
     pub fn get_collateral_factor(e: &Env) -> Result<i128, Error> {
         pool::read_collateral_factor(e)
     }
@@ -289,6 +481,11 @@ impl LoanPoolContract {
         pool::read_total_shares(&e)
     }
 
+    /// Value of one pool share in tokens, 1.0 = 10_000_000.
+    pub fn get_exchange_rate(e: Env) -> Result<i128, Error> {
+        shares::exchange_rate(&e)
+    }
+
     pub fn get_available_balance(e: Env) -> Result<i128, Error> {
         pool::read_available_balance(&e)
     }
@@ -301,6 +498,35 @@ impl LoanPoolContract {
         interest::get_interest(e)
     }
 
+    pub fn get_status(e: Env) -> PoolStatus {
+        pool::read_pool_config(&e).status
+    }
+
+    pub fn get_reserve_factor(e: Env) -> i128 {
+        pool::read_reserve_factor(&e)
+    }
+
+    pub fn get_total_reserves(e: Env) -> i128 {
+        pool::read_total_reserves(&e)
+    }
+
+    pub fn get_liquidation_params(e: Env) -> Result<LiquidationParams, Error> {
+        liquidation::read(&e)
+    }
+
+    pub fn get_flash_loan_fee(e: Env) -> i128 {
+        pool::read_flash_loan_fee(&e)
+    }
+
+    pub fn get_interest_model(e: Env) -> InterestRateModel {
+        pool::read_interest_rate_model(&e)
+    }
+
+    /// Remaining room under the supply and borrow caps.
+    pub fn get_cap_headroom(e: Env) -> Result<CapHeadroom, Error> {
+        caps::headroom(&e)
+    }
+
     pub fn get_pool_state(e: Env) -> Result<PoolState, Error> {
         Ok(PoolState {
             total_balance_tokens: pool::read_total_balance(&e)?,
@@ -310,174 +536,1507 @@ impl LoanPoolContract {
         })
     }
 
-    pub fn increase_liabilities(e: Env, user: Address, amount: i128) -> Result<(), Error> {
+    pub fn repay(e: Env, user: Address, amount: i128, unpaid_interest: i128) -> Result<(), Error> {
         let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
         loan_manager_addr.require_auth();
+        status::require_not_paused(&e)?;
 
-        positions::increase_positions(&e, user.clone(), 0, amount, 0)?;
-        e.events().publish(
-            (
-                PoolDataKey::Positions(user),
-                symbol_short!("liab"),
-                symbol_short!("incr"),
-            ),
-            amount,
-        );
+        Self::add_interest_to_accrual(e.clone())?;
+
+        // Interest is paid before the principal.
+        let interest_paid = amount.min(unpaid_interest);
+        let amount_to_reserves = Self::add_to_reserves(&e, interest_paid)?;
+
+        let client = token::Client::new(&e, &pool::read_currency(&e)?.token_address);
+        client.transfer(&user, &e.current_contract_address(), &amount);
+
+        // Liabilities only track the principal, like in borrow.
+        positions::decrease_positions(&e, user.clone(), 0, amount - interest_paid, 0)?;
+        pool::change_available_balance(&e, amount - amount_to_reserves)?;
+        pool::change_total_balance(&e, interest_paid - amount_to_reserves)?;
+        interest::change_total_debt(&e, amount.checked_neg().ok_or(Error::OverOrUnderFlow)?)?;
+
+        events::repay(&e, user, amount, interest_paid, amount_to_reserves);
         Ok(())
     }
 
-    pub fn repay(e: Env, user: Address, amount: i128, unpaid_interest: i128) -> Result<(), Error> {
+    pub fn repay_and_close(
+        e: Env,
+        user: Address,
+        borrowed_amount: i128,
+        max_allowed_amount: i128,
+        unpaid_interest: i128,
+    ) -> Result<(), Error> {
         let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
         loan_manager_addr.require_auth();
+        status::require_not_paused(&e)?;
 
         Self::add_interest_to_accrual(e.clone())?;
 
-        let amount_to_admin = if amount < unpaid_interest {
-            amount / 10
-        } else {
-            unpaid_interest / 10
-        };
+        let interest_paid = borrowed_amount.min(unpaid_interest);
+        let amount_to_reserves = Self::add_to_reserves(&e, interest_paid)?;
 
-        let amount_to_pool = amount
-            .checked_sub(amount_to_admin)
+        let amount_to_user = max_allowed_amount
+            .checked_sub(borrowed_amount)
             .ok_or(Error::OverOrUnderFlow)?;
 
         let client = token::Client::new(&e, &pool::read_currency(&e)?.token_address);
-        client.transfer(&user, &e.current_contract_address(), &amount_to_pool);
-        client.transfer(&user, &loan_manager_addr, &amount_to_admin);
+        client.transfer(&user, &e.current_contract_address(), &max_allowed_amount);
+        client.transfer(&e.current_contract_address(), &user, &amount_to_user);
+
+        // The user can have other loans from this pool, so only the principal of this one is cleared.
+        let principal = borrowed_amount
+            .checked_sub(interest_paid)
+            .ok_or(Error::OverOrUnderFlow)?;
+        positions::decrease_positions(&e, user.clone(), 0, principal, 0)?;
+        pool::change_available_balance(&e, borrowed_amount - amount_to_reserves)?;
+        pool::change_total_balance(&e, interest_paid - amount_to_reserves)?;
+        interest::change_total_debt(
+            &e,
+            borrowed_amount
+                .checked_neg()
+                .ok_or(Error::OverOrUnderFlow)?,
+        )?;
 
-        positions::decrease_positions(&e, user, 0, amount, 0)?;
-        pool::change_available_balance(&e, amount - amount_to_admin)?;
-        pool::change_total_balance(&e, unpaid_interest - amount_to_admin)?;
+        events::repay(&e, user, borrowed_amount, interest_paid, amount_to_reserves);
         Ok(())
     }
 
-    pub fn repay_and_close(
+    pub fn liquidate(
         e: Env,
         user: Address,
-        borrowed_amount: i128,
-        max_allowed_amount: i128,
+        amount: i128,
         unpaid_interest: i128,
+        loan_owner: Address,
     ) -> Result<(), Error> {
         let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
         loan_manager_addr.require_auth();
+        status::require_not_paused(&e)?;
 
         Self::add_interest_to_accrual(e.clone())?;
 
-        let amount_to_admin = if borrowed_amount < unpaid_interest {
-            borrowed_amount / 10
-        } else {
-            unpaid_interest / 10
-        };
+        let interest_paid = amount.min(unpaid_interest);
+        let amount_to_reserves = Self::add_to_reserves(&e, interest_paid)?;
 
-        let amount_to_user = max_allowed_amount
-            .checked_sub(borrowed_amount)
+        let client = token::Client::new(&e, &pool::read_currency(&e)?.token_address);
+        client.transfer(&user, &e.current_contract_address(), &amount);
+
+        positions::decrease_positions(&e, loan_owner.clone(), 0, amount - interest_paid, 0)?;
+        pool::change_available_balance(&e, amount - amount_to_reserves)?;
+        pool::change_total_balance(&e, interest_paid - amount_to_reserves)?;
+        interest::change_total_debt(&e, amount.checked_neg().ok_or(Error::OverOrUnderFlow)?)?;
+
+        events::liquidate(
+            &e,
+            user,
+            loan_owner,
+            amount,
+            interest_paid,
+            amount_to_reserves,
+        );
+        Ok(())
+    }
+
+    /// Remove the debt of an insolvent loan from the pool. `amount` is the debt including `unpaid_interest`.
+    /// The lost principal is covered from reserves first and the rest lowers the exchange rate of the shares.
+    /// A loss that would leave the shares without any balance is refused, as the pool could not price
+    /// deposits anymore. Returns the loss to the suppliers.
+    pub fn write_off_debt(
+        e: Env,
+        user: Address,
+        amount: i128,
+        unpaid_interest: i128,
+    ) -> Result<i128, Error> {
+        let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
+        loan_manager_addr.require_auth();
+        if amount < 0 || unpaid_interest < 0 {
+            return Err(Error::NegativeAmount);
+        }
+
+        Self::add_interest_to_accrual(e.clone())?;
+
+        // Unpaid interest was never added to the pool's balance, so only the principal is lost.
+        let principal = amount
+            .checked_sub(unpaid_interest.min(amount))
+            .ok_or(Error::OverOrUnderFlow)?;
+        let principal_lost = principal.min(caps::total_borrowed(&e)?);
+
+        let covered_by_reserves = principal_lost.min(pool::read_total_reserves(&e));
+        let socialized = principal_lost
+            .checked_sub(covered_by_reserves)
             .ok_or(Error::OverOrUnderFlow)?;
+        if socialized >= pool::read_total_balance(&e)? && pool::read_total_shares(&e)? > 0 {
+            return Err(Error::WriteOffExceedsBalance);
+        }
+
+        // Reserve tokens take the place of the lost principal in the available balance.
+        pool::change_total_reserves(
+            &e,
+            covered_by_reserves
+                .checked_neg()
+                .ok_or(Error::OverOrUnderFlow)?,
+        )?;
+        pool::change_available_balance(&e, covered_by_reserves)?;
+        pool::change_total_balance(&e, socialized.checked_neg().ok_or(Error::OverOrUnderFlow)?)?;
+        interest::change_total_debt(&e, amount.checked_neg().ok_or(Error::OverOrUnderFlow)?)?;
+
+        positions::decrease_positions(&e, user.clone(), 0, principal, 0)?;
+
+        events::bad_debt(&e, user, principal_lost, covered_by_reserves, socialized);
+        Ok(socialized)
+    }
+
+    pub fn liquidate_transfer_collateral(
+        e: Env,
+        user: Address,
+        amount_collateral: i128,
+        loan_owner: Address,
+    ) -> Result<(), Error> {
+        let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
+        loan_manager_addr.require_auth();
+        status::require_not_paused(&e)?;
 
         let client = token::Client::new(&e, &pool::read_currency(&e)?.token_address);
-        client.transfer(&user, &e.current_contract_address(), &max_allowed_amount);
-        client.transfer(
-            &e.current_contract_address(),
-            &loan_manager_addr,
-            &amount_to_admin,
+        client.transfer(&e.current_contract_address(), &user, &amount_collateral);
+        pool::change_total_collateral(
+            &e,
+            amount_collateral
+                .checked_neg()
+                .ok_or(Error::OverOrUnderFlow)?,
+        )?;
+
+        positions::decrease_positions(&e, loan_owner.clone(), 0, 0, amount_collateral)?;
+        events::collateral_seized(&e, user, loan_owner, amount_collateral);
+        Ok(())
+    }
+}
+
+impl LoanPoolContract {
+    fn validate_reserve_factor(reserve_factor: i128) -> Result<(), Error> {
+        if !(0..=RESERVE_FACTOR_DECIMAL).contains(&reserve_factor) {
+            return Err(Error::InvalidReserveFactor);
+        }
+        Ok(())
+    }
+
+    fn validate_flash_loan_fee(fee: i128) -> Result<(), Error> {
+        if !(0..=FLASH_LOAN_FEE_DECIMAL).contains(&fee) {
+            return Err(Error::InvalidFlashLoanFee);
+        }
+        Ok(())
+    }
+
+    /// Move the protocol's cut of the paid interest to reserves. Returns the amount added to reserves.
+    fn add_to_reserves(e: &Env, interest_paid: i128) -> Result<i128, Error> {
+        let reserve_factor = pool::read_reserve_factor(e);
+        let amount = interest_paid
+            .checked_mul(reserve_factor)
+            .ok_or(Error::OverOrUnderFlow)?
+            .checked_div(RESERVE_FACTOR_DECIMAL)
+            .ok_or(Error::OverOrUnderFlow)?;
+
+        if amount > 0 {
+            let total_reserves = pool::change_total_reserves(e, amount)?;
+            events::reserves_accrued(e, amount, total_reserves);
+        }
+        Ok(amount)
+    }
+
+    fn withdraw_tokens(
+        e: &Env,
+        user: &Address,
+        amount: i128,
+        shares: i128,
+    ) -> Result<PoolState, Error> {
+        status::require_not_paused(e)?;
+
+        // Get users receivables
+        let Positions {
+            receivable_shares, ..
+        } = positions::read_positions(e, user);
+
+        // Check that user is not trying to burn more shares than they own.
+        if shares > receivable_shares {
+            return Err(Error::WithdrawIsNegative);
+        }
+
+        let available_balance_tokens = pool::read_available_balance(e)?;
+        if amount > available_balance_tokens {
+            return Err(Error::WithdrawOverBalance);
+        }
+
+        let new_available_balance_tokens =
+            pool::change_available_balance(e, amount.checked_neg().ok_or(Error::OverOrUnderFlow)?)?;
+        let new_total_balance_tokens =
+            pool::change_total_balance(e, amount.checked_neg().ok_or(Error::OverOrUnderFlow)?)?;
+        let new_total_balance_shares =
+            pool::change_total_shares(e, shares.checked_neg().ok_or(Error::OverOrUnderFlow)?)?;
+        let liabilities: i128 = 0;
+        let collateral: i128 = 0;
+        positions::decrease_positions(e, user.clone(), shares, liabilities, collateral)?;
+        e.events()
+            .publish((symbol_short!("burn"), user.clone()), shares);
+        events::withdraw(e, user.clone(), amount, shares);
+
+        // Transfer tokens from pool to user
+        let token_address = &pool::read_currency(e)?.token_address;
+        let client = token::Client::new(e, token_address);
+        client.transfer(&e.current_contract_address(), user, &amount);
+
+        let new_annual_interest_rate = interest::get_interest(e.clone())?;
+
+        let pool_state = PoolState {
+            total_balance_tokens: new_total_balance_tokens,
+            available_balance_tokens: new_available_balance_tokens,
+            total_balance_shares: new_total_balance_shares,
+            annual_interest_rate: new_annual_interest_rate,
+        };
+        Ok(pool_state)
+    }
+
+    fn transfer_shares(e: &Env, from: Address, to: Address, amount: i128) -> Result<(), Error> {
+        if amount < 0 {
+            return Err(Error::NegativeAmount);
+        }
+        status::require_not_paused(e)?;
+        // Only receivable shares move, collateral and liabilities stay with the owner.
+        positions::decrease_positions(e, from.clone(), amount, 0, 0)?;
+        positions::increase_positions(e, to.clone(), amount, 0, 0)?;
+        e.events()
+            .publish((symbol_short!("transfer"), from, to), amount);
+        Ok(())
+    }
+}
+
+/// Pool shares as a SEP-41 token. Balances are the users' receivable shares.
+#[contractimpl]
+impl TokenInterface for LoanPoolContract {
+    fn allowance(e: Env, from: Address, spender: Address) -> i128 {
+        allowance::read_allowance(&e, from, spender).amount
+    }
+
+    fn approve(e: Env, from: Address, spender: Address, amount: i128, expiration_ledger: u32) {
+        from.require_auth();
+        if amount < 0 {
+            panic_with_error!(&e, Error::NegativeAmount);
+        }
+
+        allowance::write_allowance(&e, from.clone(), spender.clone(), amount, expiration_ledger)
+            .unwrap_or_else(|err| panic_with_error!(&e, err));
+        e.events().publish(
+            (symbol_short!("approve"), from, spender),
+            (amount, expiration_ledger),
+        );
+    }
+
+    fn balance(e: Env, id: Address) -> i128 {
+        positions::read_positions(&e, &id).receivable_shares
+    }
+
+    fn transfer(e: Env, from: Address, to: Address, amount: i128) {
+        from.require_auth();
+
+        Self::transfer_shares(&e, from, to, amount)
+            .unwrap_or_else(|err| panic_with_error!(&e, err));
+    }
+
+    fn transfer_from(e: Env, spender: Address, from: Address, to: Address, amount: i128) {
+        spender.require_auth();
+        if amount < 0 {
+            panic_with_error!(&e, Error::NegativeAmount);
+        }
+
+        allowance::spend_allowance(&e, from.clone(), spender, amount)
+            .and_then(|_| Self::transfer_shares(&e, from, to, amount))
+            .unwrap_or_else(|err| panic_with_error!(&e, err));
+    }
+
+    /// Shares only leave through the withdraw functions. Burning them would leave their tokens to
+    /// the other shareholders, so a single share could be pushed to a price that blocks deposits.
+    fn burn(e: Env, _from: Address, _amount: i128) {
+        panic_with_error!(&e, Error::BurnNotSupported);
+    }
+
+    fn burn_from(e: Env, _spender: Address, _from: Address, _amount: i128) {
+        panic_with_error!(&e, Error::BurnNotSupported);
+    }
+
+    /// Shares use the decimals of the pool's token.
+    fn decimals(e: Env) -> u32 {
+        pool::read_currency(&e)
+            .unwrap_or_else(|err| panic_with_error!(&e, err))
+            .decimals
+    }
+
+    fn name(e: Env) -> String {
+        metadata::read_name(&e).unwrap_or_else(|err| panic_with_error!(&e, err))
+    }
+
+    fn symbol(e: Env) -> String {
+        metadata::read_symbol(&e).unwrap_or_else(|err| panic_with_error!(&e, err))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*; // This imports LoanPoolContract and everything else from the parent module
+    use crate::pool::CurrencyV1;
+    use crate::storage_types::PoolDataKey;
+    use soroban_sdk::{
+        testutils::{Address as _, Events, Ledger},
+        token::{Client as TokenClient, StellarAssetClient},
+        Bytes, Env, IntoVal, Map, String, Symbol, Val,
+    };
+
+    mod flash_loan_receiver {
+        soroban_sdk::contractimport!(
+            file = "../../target/wasm32-unknown-unknown/release/flash_loan_receiver_mock.wasm"
+        );
+    }
+
+    const TEST_LIQUIDATION_THRESHOLD: i128 = 8_000_000;
+    const TEST_RESERVE_FACTOR: i128 = 1_000_000;
+
+    fn test_interest_rate_model() -> InterestRateModel {
+        InterestRateModel {
+            base_rate: 200_000,
+            kink_utilization: 9_000_000,
+            rate_at_kink: 1_000_000,
+            max_rate: 3_000_000,
+        }
+    }
+
+    #[test]
+    fn initialize() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+    }
+
+    #[test]
+    fn read_currency_without_decimals() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        // Currency of a pool deployed before it had decimals.
+        e.as_contract(&contract_id, || {
+            e.storage().persistent().set(
+                &PoolDataKey::Currency,
+                &CurrencyV1 {
+                    token_address: token.address(),
+                    ticker: Symbol::new(&e, "XLM"),
+                },
+            );
+        });
+
+        let read = contract_client.get_currency();
+        assert_eq!(read.token_address, token.address());
+        assert_eq!(read.ticker, Symbol::new(&e, "XLM"));
+        assert_eq!(read.decimals, 7);
+        assert_eq!(contract_client.deposit(&user, &1000), 1000);
+
+        let has_decimals = || {
+            let stored: Map<Symbol, Val> = e.as_contract(&contract_id, || {
+                e.storage()
+                    .persistent()
+                    .get(&PoolDataKey::Currency)
+                    .unwrap()
+            });
+            stored.contains_key(Symbol::new(&e, "decimals"))
+        };
+        // Reading the currency doesn't store it, the migration does.
+        assert!(!has_decimals());
+        contract_client.migrate();
+        assert!(has_decimals());
+        assert_eq!(contract_client.get_currency().decimals, 7);
+    }
+
+    #[test]
+    fn old_pool_without_status_is_active() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.set_status(&PoolStatus::Paused);
+
+        // Pools deployed before the statuses have no config stored.
+        e.as_contract(&contract_id, || {
+            e.storage().persistent().remove(&PoolDataKey::PoolConfig);
+        });
+        assert_eq!(contract_client.get_status(), PoolStatus::Active);
+        assert_eq!(contract_client.deposit(&user, &500), 500);
+
+        // A config without the status field is active as well.
+        e.as_contract(&contract_id, || {
+            let mut config: Map<Symbol, Val> = Map::new(&e);
+            config.set(Symbol::new(&e, "oracle"), admin.to_val());
+            e.storage()
+                .persistent()
+                .set(&PoolDataKey::PoolConfig, &config);
+        });
+        assert_eq!(contract_client.get_status(), PoolStatus::Active);
+        assert_eq!(contract_client.deposit(&user, &500), 500);
+
+        contract_client.set_status(&PoolStatus::DepositFrozen);
+        assert_eq!(contract_client.get_status(), PoolStatus::DepositFrozen);
+    }
+
+    #[test]
+    fn repay_in_pool_without_reserves() {
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &0,
+        );
+
+        // Pools deployed before the reserves have neither a reserve factor nor reserves stored.
+        e.as_contract(&contract_id, || {
+            e.storage().persistent().remove(&PoolDataKey::ReserveFactor);
+            e.storage().persistent().remove(&PoolDataKey::TotalReserves);
+        });
+        assert_eq!(contract_client.get_reserve_factor(), 1_000_000);
+        assert_eq!(contract_client.get_total_reserves(), 0);
+
+        contract_client.deposit(&user, &1000);
+        contract_client.borrow(&user, &500);
+        contract_client.repay(&user, &200, &100);
+        assert_eq!(contract_client.get_total_reserves(), 10);
+    }
+
+    #[test]
+    fn read_interest_model_of_old_pool() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &InterestRateModel {
+                base_rate: 500_000,
+                kink_utilization: 8_000_000,
+                rate_at_kink: 2_000_000,
+                max_rate: 5_000_000,
+            },
+            &TEST_RESERVE_FACTOR,
+        );
+
+        // Pools deployed before the model was configurable don't have one stored.
+        e.as_contract(&contract_id, || {
+            e.storage()
+                .persistent()
+                .remove(&PoolDataKey::InterestRateModel);
+        });
+
+        assert_eq!(
+            contract_client.get_interest_model(),
+            test_interest_rate_model()
+        );
+        // Reading the model doesn't store it.
+        assert!(!e.as_contract(&contract_id, || {
+            e.storage()
+                .persistent()
+                .has(&PoolDataKey::InterestRateModel)
+        }));
+
+        contract_client.deposit(&user, &1000);
+        contract_client.borrow(&user, &500);
+        assert_eq!(contract_client.get_interest(), 644_440);
+    }
+
+    #[test]
+    fn deposit() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+        let amount: i128 = 100;
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        let result: i128 = contract_client.deposit(&user, &amount);
+
+        assert_eq!(result, amount);
+        let (address, topics, data) = e.events().all().last().unwrap();
+        assert_eq!(address, contract_id);
+        assert_eq!(
+            topics,
+            (symbol_short!("deposit"), user.clone()).into_val(&e)
+        );
+        let event: events::DepositEvent = data.into_val(&e);
+        assert_eq!(
+            event,
+            events::DepositEvent {
+                version: events::EVENT_VERSION,
+                user,
+                amount,
+                shares: amount,
+            }
+        );
+    }
+
+    #[test]
+    fn borrow() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let asset = StellarAssetClient::new(&e, &token.address());
+
+        let token_client = TokenClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        // Deposit funds for the borrower to loan.
+        let depositer = Address::generate(&e);
+        asset.mint(&depositer, &100);
+        contract_client.deposit(&depositer, &100);
+
+        // Borrow some of those funds
+        let borrower = Address::generate(&e);
+        contract_client.borrow(&borrower, &50);
+
+        // Did the funds move?
+        assert_eq!(token_client.balance(&depositer), 0);
+        assert_eq!(token_client.balance(&borrower), 50);
+    }
+
+    #[test]
+    fn withdraw() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+        let amount: i128 = 100;
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        let result: i128 = contract_client.deposit(&user, &amount);
+
+        assert_eq!(result, amount);
+
+        contract_client.withdraw(&user, &amount);
+    }
+
+    #[test]
+    fn withdraw_burns_shares() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        contract_client.deposit(&user, &1000);
+        let pool_state = contract_client.withdraw_shares(&user, &400);
+
+        assert_eq!(pool_state.total_balance_shares, 600);
+        assert_eq!(pool_state.total_balance_tokens, 600);
+        assert_eq!(
+            contract_client.get_user_positions(&user).receivable_shares,
+            600
+        );
+
+        let pool_state = contract_client.withdraw_all(&user);
+
+        assert_eq!(pool_state.total_balance_shares, 0);
+        assert_eq!(pool_state.total_balance_tokens, 0);
+        assert_eq!(TokenClient::new(&e, &token.address()).balance(&user), 1000);
+    }
+
+    #[test]
+    fn leftover_tokens_without_shares_go_to_reserves() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+        let user2 = Address::generate(&e);
+        stellar_asset.mint(&user2, &100);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.deposit(&user, &1000);
+        contract_client.withdraw_all(&user);
+
+        // 7 tokens are left in the pool after its last share was withdrawn.
+        stellar_asset.mint(&contract_id, &7);
+        e.as_contract(&contract_id, || {
+            pool::change_total_balance(&e, 7).unwrap();
+            pool::change_available_balance(&e, 7).unwrap();
+        });
+        assert_eq!(
+            contract_client.get_exchange_rate(),
+            shares::EXCHANGE_RATE_DECIMAL
+        );
+
+        // The next depositor doesn't get them.
+        assert_eq!(contract_client.deposit(&user2, &100), 100);
+        assert_eq!(contract_client.get_total_reserves(), 7);
+        contract_client.withdraw_all(&user2);
+        assert_eq!(TokenClient::new(&e, &token.address()).balance(&user2), 100);
+
+        // Tokens that are lent out can't be swept, so deposits wait until they are repaid.
+        e.as_contract(&contract_id, || {
+            pool::change_total_balance(&e, 5).unwrap();
+        });
+        assert_eq!(
+            contract_client.try_deposit(&user2, &100),
+            Err(Ok(Error::UnownedAssets))
+        );
+    }
+
+    #[test]
+    fn shares_are_priced_by_exchange_rate() {
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let token_client = TokenClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let early_depositor = Address::generate(&e);
+        stellar_asset.mint(&early_depositor, &1000);
+        let late_depositor = Address::generate(&e);
+        stellar_asset.mint(&late_depositor, &109);
+        let borrower = Address::generate(&e);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        assert_eq!(contract_client.deposit(&early_depositor, &1000), 1000);
+
+        // Borrower pays back 100 tokens, all of which is interest. 90 of it goes to the suppliers.
+        contract_client.borrow(&borrower, &100);
+        contract_client.repay(&borrower, &100, &100);
+        assert_eq!(contract_client.get_contract_balance(), 1090);
+        assert_eq!(contract_client.get_exchange_rate(), 10_900_000);
+
+        // Late depositor gets shares at the current exchange rate.
+        assert_eq!(contract_client.deposit(&late_depositor, &109), 100);
+
+        contract_client.withdraw_all(&early_depositor);
+        assert_eq!(token_client.balance(&early_depositor), 1090);
+        assert_eq!(contract_client.get_total_balance_shares(), 100);
+        assert_eq!(contract_client.get_contract_balance(), 109);
+    }
+
+    #[test]
+    fn withdraw_rounds_shares_up() {
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+        let borrower = Address::generate(&e);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        contract_client.deposit(&user, &1000);
+        contract_client.borrow(&borrower, &100);
+        contract_client.repay(&borrower, &100, &100);
+
+        // 10 tokens are worth 9.17 shares, so 10 shares are burned.
+        contract_client.withdraw(&user, &10);
+        assert_eq!(
+            contract_client.get_user_positions(&user).receivable_shares,
+            990
+        );
+    }
+
+    #[test]
+    fn transfer_shares() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+        let user2 = Address::generate(&e);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+        let share_client = TokenClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.deposit(&user, &1000);
+
+        share_client.transfer(&user, &user2, &300);
+
+        assert_eq!(share_client.balance(&user), 700);
+        assert_eq!(share_client.balance(&user2), 300);
+
+        // The receiver can redeem the shares.
+        contract_client.withdraw_all(&user2);
+        assert_eq!(TokenClient::new(&e, &token.address()).balance(&user2), 300);
+    }
+
+    #[test]
+    fn transfer_shares_from() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+        let spender = Address::generate(&e);
+        let receiver = Address::generate(&e);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+        let share_client = TokenClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.deposit(&user, &1000);
+
+        share_client.approve(&user, &spender, &500, &(e.ledger().sequence() + 100));
+        assert_eq!(share_client.allowance(&user, &spender), 500);
+
+        share_client.transfer_from(&spender, &user, &receiver, &200);
+
+        assert_eq!(share_client.allowance(&user, &spender), 300);
+        assert_eq!(share_client.balance(&user), 800);
+        assert_eq!(share_client.balance(&receiver), 200);
+        assert!(share_client
+            .try_transfer_from(&spender, &user, &receiver, &301)
+            .is_err());
+    }
+
+    #[test]
+    fn burning_shares_is_rejected() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let attacker = Address::generate(&e);
+        stellar_asset.mint(&attacker, &1000);
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &10);
+        let spender = Address::generate(&e);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+        let share_client = TokenClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.deposit(&attacker, &1000);
+
+        // Burning down to a single share would make it worth all 1000 tokens.
+        let burn_error = Err(Ok(soroban_sdk::Error::from_contract_error(
+            Error::BurnNotSupported as u32,
+        )));
+        assert_eq!(share_client.try_burn(&attacker, &999), burn_error);
+        share_client.approve(&attacker, &spender, &999, &(e.ledger().sequence() + 100));
+        assert_eq!(
+            share_client.try_burn_from(&spender, &attacker, &999),
+            burn_error
+        );
+
+        assert_eq!(share_client.balance(&attacker), 1000);
+        assert_eq!(contract_client.get_total_balance_shares(), 1000);
+        assert_eq!(
+            contract_client.get_exchange_rate(),
+            shares::EXCHANGE_RATE_DECIMAL
+        );
+        // Small deposits still get their shares.
+        assert_eq!(contract_client.deposit(&user, &10), 10);
+    }
+
+    #[test]
+    fn share_token_metadata() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+        let share_client = TokenClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        assert_eq!(share_client.decimals(), 7);
+        assert_eq!(
+            share_client.name(),
+            String::from_str(&e, "Laina XLM Pool Share")
+        );
+        assert_eq!(share_client.symbol(), String::from_str(&e, "lXLM"));
+    }
+
+    #[test]
+    #[should_panic(expected = "insufficient receivables")]
+    fn collateral_is_not_transferable() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+        let share_client = TokenClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.deposit_collateral(&user, &1000);
+
+        assert_eq!(share_client.balance(&user), 0);
+        share_client.transfer(&user, &Address::generate(&e), &1000);
+    }
+
+    #[test]
+    fn repay_adds_to_reserves() {
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let token_client = TokenClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+        let borrower = Address::generate(&e);
+        stellar_asset.mint(&borrower, &100);
+        let treasury = Address::generate(&e);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.set_reserve_factor(&2_000_000);
+
+        contract_client.deposit(&user, &1000);
+        contract_client.borrow(&borrower, &500);
+        // 50 of the repaid amount is interest, 20% of which goes to reserves.
+        contract_client.repay(&borrower, &200, &50);
+
+        assert_eq!(contract_client.get_total_reserves(), 10);
+        assert_eq!(contract_client.get_contract_balance(), 1040);
+        assert_eq!(contract_client.get_available_balance(), 690);
+        assert_eq!(token_client.balance(&contract_id), 700);
+
+        assert_eq!(contract_client.claim_reserves(&treasury, &6), 4);
+        assert_eq!(token_client.balance(&treasury), 6);
+        assert!(contract_client.try_claim_reserves(&treasury, &5).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #20)")]
+    fn set_reserve_factor_over_100_percent() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.set_reserve_factor(&10_000_001);
+    }
+
+    #[test]
+    fn borrow_frozen_pool_allows_repay_and_deposit() {
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+        let borrower = Address::generate(&e);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.deposit(&user, &500);
+        contract_client.borrow(&borrower, &100);
+
+        contract_client.set_status(&PoolStatus::BorrowFrozen);
+        assert_eq!(contract_client.get_status(), PoolStatus::BorrowFrozen);
+
+        assert_eq!(
+            contract_client.try_borrow(&borrower, &100),
+            Err(Ok(Error::BorrowsFrozen))
+        );
+        contract_client.repay(&borrower, &100, &0);
+        contract_client.deposit(&user, &500);
+        contract_client.withdraw(&user, &100);
+    }
+
+    #[test]
+    fn paused_pool_blocks_user_actions() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+        let share_client = TokenClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.deposit(&user, &500);
+
+        contract_client.set_status(&PoolStatus::Paused);
+
+        assert_eq!(
+            contract_client.try_deposit(&user, &100),
+            Err(Ok(Error::PoolPaused))
+        );
+        assert_eq!(
+            contract_client.try_withdraw(&user, &100),
+            Err(Ok(Error::PoolPaused))
+        );
+        assert!(share_client
+            .try_transfer(&user, &Address::generate(&e), &100)
+            .is_err());
+
+        contract_client.set_status(&PoolStatus::Active);
+        contract_client.withdraw(&user, &100);
+    }
+
+    #[test]
+    fn deprecated_pool_only_allows_exits() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.deposit(&user, &500);
+
+        contract_client.set_status(&PoolStatus::Deprecated);
+
+        assert_eq!(
+            contract_client.try_deposit(&user, &100),
+            Err(Ok(Error::DepositsFrozen))
+        );
+        assert_eq!(
+            contract_client.try_borrow(&user, &100),
+            Err(Ok(Error::BorrowsFrozen))
+        );
+        assert_eq!(
+            contract_client.try_set_status(&PoolStatus::Active),
+            Err(Ok(Error::InvalidStatusTransition))
+        );
+        contract_client.withdraw_all(&user);
+    }
+
+    #[test]
+    fn supply_cap_limits_deposits_and_collateral() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "EURC"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.set_caps(&Some(500), &None);
+
+        contract_client.deposit(&user, &300);
+        contract_client.deposit_collateral(&user, &100);
+        assert_eq!(
+            contract_client.get_cap_headroom(),
+            CapHeadroom {
+                supply: Some(100),
+                borrow: None,
+            }
+        );
+
+        assert_eq!(
+            contract_client.try_deposit(&user, &101),
+            Err(Ok(Error::SupplyCapExceeded))
         );
-        client.transfer(&e.current_contract_address(), &user, &amount_to_user);
+        assert_eq!(
+            contract_client.try_deposit_collateral(&user, &101),
+            Err(Ok(Error::SupplyCapExceeded))
+        );
+        contract_client.deposit(&user, &100);
 
-        let user_liabilities = positions::read_positions(&e, &user).liabilities;
-        positions::decrease_positions(&e, user, 0, user_liabilities, 0)?;
-        pool::change_available_balance(&e, borrowed_amount - amount_to_admin)?;
-        pool::change_total_balance(&e, unpaid_interest - amount_to_admin)?;
-        Ok(())
+        contract_client.set_caps(&None, &None);
+        contract_client.deposit(&user, &100);
+        assert_eq!(contract_client.get_contract_balance(), 500);
     }
 
-    pub fn liquidate(
-        e: Env,
-        user: Address,
-        amount: i128,
-        unpaid_interest: i128,
-        loan_owner: Address,
-    ) -> Result<(), Error> {
-        let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
-        loan_manager_addr.require_auth();
-
-        Self::add_interest_to_accrual(e.clone())?;
+    #[test]
+    fn borrow_cap_limits_borrows() {
+        let e = Env::default();
+        e.mock_all_auths();
 
-        let amount_to_admin = if amount < unpaid_interest {
-            amount / 10
-        } else {
-            unpaid_interest / 10
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "EURC"),
+            decimals: 7,
         };
 
-        let amount_to_pool = amount
-            .checked_sub(amount_to_admin)
-            .ok_or(Error::OverOrUnderFlow)?;
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
+        let borrower = Address::generate(&e);
 
-        let client = token::Client::new(&e, &pool::read_currency(&e)?.token_address);
-        client.transfer(&user, &e.current_contract_address(), &amount_to_pool);
-        client.transfer(&user, &loan_manager_addr, &amount_to_admin);
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
 
-        positions::decrease_positions(&e, loan_owner, 0, amount, 0)?;
-        pool::change_available_balance(&e, amount)?;
-        pool::change_total_balance(&e, amount)?;
-        Ok(())
-    }
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.deposit(&user, &1000);
+        contract_client.set_caps(&None, &Some(200));
 
-    pub fn liquidate_transfer_collateral(
-        e: Env,
-        user: Address,
-        amount_collateral: i128,
-        loan_owner: Address,
-    ) -> Result<(), Error> {
-        let loan_manager_addr = pool::read_loan_manager_addr(&e)?;
-        loan_manager_addr.require_auth();
+        contract_client.borrow(&borrower, &150);
+        assert_eq!(
+            contract_client.try_borrow(&borrower, &51),
+            Err(Ok(Error::BorrowCapExceeded))
+        );
+        contract_client.borrow(&borrower, &50);
 
-        let client = token::Client::new(&e, &pool::read_currency(&e)?.token_address);
-        client.transfer(&e.current_contract_address(), &user, &amount_collateral);
+        // Lowering the cap below the outstanding borrows leaves no headroom.
+        contract_client.set_caps(&None, &Some(100));
+        assert_eq!(contract_client.get_cap_headroom().borrow, Some(0));
 
-        positions::decrease_positions(&e, loan_owner, 0, 0, amount_collateral)?;
-        Ok(())
+        assert_eq!(
+            contract_client.try_set_caps(&Some(-1), &None),
+            Err(Ok(Error::InvalidCap))
+        );
     }
-}
-
-#[cfg(test)]
-mod test {
-    use super::*; // This imports LoanPoolContract and everything else from the parent module
-    use soroban_sdk::{
-        testutils::{Address as _, Ledger},
-        token::{Client as TokenClient, StellarAssetClient},
-        Env, Symbol,
-    };
-
-    const TEST_LIQUIDATION_THRESHOLD: i128 = 8_000_000;
 
     #[test]
-    fn initialize() {
+    fn flash_loan_fee_goes_to_suppliers_and_reserves() {
         let e = Env::default();
         e.mock_all_auths();
 
         let admin = Address::generate(&e);
         let token = e.register_stellar_asset_contract_v2(admin.clone());
         let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let token_client = TokenClient::new(&e, &token.address());
         let currency = Currency {
             token_address: token.address(),
             ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
         };
 
         let user = Address::generate(&e);
-        stellar_asset.mint(&user, &1000);
+        stellar_asset.mint(&user, &10_000);
 
         let contract_id = e.register(LoanPoolContract, ());
         let contract_client = LoanPoolContractClient::new(&e, &contract_id);
 
+        let receiver_id = e.register(flash_loan_receiver::WASM, ());
+        let receiver_client = flash_loan_receiver::Client::new(&e, &receiver_id);
+        receiver_client.initialize(&contract_id, &user, &true);
+        // The receiver pays the fee from its own funds.
+        stellar_asset.mint(&receiver_id, &100);
+
         contract_client.initialize(
             &Address::generate(&e),
             &currency,
             &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
         );
+        contract_client.deposit(&user, &10_000);
+        contract_client.set_flash_loan_fee(&100_000); // 1%
+
+        let fee = contract_client.flash_loan(&user, &receiver_id, &10_000, &Bytes::new(&e));
+
+        assert_eq!(fee, 100);
+        assert_eq!(token_client.balance(&contract_id), 10_100);
+        assert_eq!(token_client.balance(&receiver_id), 0);
+        assert_eq!(contract_client.get_total_reserves(), 10);
+        assert_eq!(contract_client.get_contract_balance(), 10_090);
+        assert_eq!(contract_client.get_available_balance(), 10_090);
     }
 
     #[test]
-    fn deposit() {
+    fn flash_loan_not_repaid() {
         let e = Env::default();
         e.mock_all_auths();
 
@@ -487,6 +2046,7 @@ mod test {
         let currency = Currency {
             token_address: token.address(),
             ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
         };
 
         let user = Address::generate(&e);
@@ -494,61 +2054,134 @@ mod test {
 
         let contract_id = e.register(LoanPoolContract, ());
         let contract_client = LoanPoolContractClient::new(&e, &contract_id);
-        let amount: i128 = 100;
+
+        // Receiver that only pays back the principal.
+        let receiver_id = e.register(flash_loan_receiver::WASM, ());
+        let receiver_client = flash_loan_receiver::Client::new(&e, &receiver_id);
+        receiver_client.initialize(&contract_id, &user, &false);
 
         contract_client.initialize(
             &Address::generate(&e),
             &currency,
             &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
         );
+        contract_client.deposit(&user, &1000);
+        contract_client.set_flash_loan_fee(&100_000);
 
-        let result: i128 = contract_client.deposit(&user, &amount);
-
-        assert_eq!(result, amount);
+        assert_eq!(
+            contract_client.try_flash_loan(&user, &receiver_id, &500, &Bytes::new(&e)),
+            Err(Ok(Error::FlashLoanNotRepaid))
+        );
+        assert_eq!(
+            contract_client.try_flash_loan(&user, &receiver_id, &1001, &Bytes::new(&e)),
+            Err(Ok(Error::FlashLoanOverBalance))
+        );
+        assert_eq!(contract_client.get_available_balance(), 1000);
     }
 
     #[test]
-    fn borrow() {
+    fn flash_loan_requires_initiator() {
         let e = Env::default();
         e.mock_all_auths();
 
         let admin = Address::generate(&e);
-
         let token = e.register_stellar_asset_contract_v2(admin.clone());
-        let asset = StellarAssetClient::new(&e, &token.address());
-
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
         let token_client = TokenClient::new(&e, &token.address());
         let currency = Currency {
             token_address: token.address(),
             ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
         };
 
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &10_000);
+        let owner = Address::generate(&e);
+        let attacker = Address::generate(&e);
+
         let contract_id = e.register(LoanPoolContract, ());
         let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        let receiver_id = e.register(flash_loan_receiver::WASM, ());
+        let receiver_client = flash_loan_receiver::Client::new(&e, &receiver_id);
+        receiver_client.initialize(&contract_id, &owner, &true);
+        stellar_asset.mint(&receiver_id, &100);
+
         contract_client.initialize(
             &Address::generate(&e),
             &currency,
             &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
         );
+        contract_client.deposit(&user, &10_000);
+        contract_client.set_flash_loan_fee(&100_000);
+
+        // The receiver refuses loans started by anyone else than its owner.
+        assert!(contract_client
+            .try_flash_loan(&attacker, &receiver_id, &10_000, &Bytes::new(&e))
+            .is_err());
+
+        // Without the initiator's signature the loan can't be started at all.
+        e.set_auths(&[]);
+        assert!(contract_client
+            .try_flash_loan(&owner, &receiver_id, &10_000, &Bytes::new(&e))
+            .is_err());
+        assert_eq!(token_client.balance(&receiver_id), 100);
+        assert_eq!(token_client.balance(&contract_id), 10_000);
+    }
 
-        // Deposit funds for the borrower to loan.
-        let depositer = Address::generate(&e);
-        asset.mint(&depositer, &100);
-        contract_client.deposit(&depositer, &100);
+    #[test]
+    fn write_off_debt_uses_reserves_first() {
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
 
-        // Borrow some of those funds
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let user = Address::generate(&e);
+        stellar_asset.mint(&user, &1000);
         let borrower = Address::generate(&e);
-        contract_client.borrow(&borrower, &50);
 
-        // Did the funds move?
-        assert_eq!(token_client.balance(&depositer), 0);
-        assert_eq!(token_client.balance(&borrower), 50);
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.deposit(&user, &1000);
+        contract_client.borrow(&borrower, &500);
+        // Interest payment of 100, of which 10 goes to reserves.
+        contract_client.repay(&borrower, &100, &100);
+        assert_eq!(contract_client.get_total_reserves(), 10);
+        assert_eq!(contract_client.get_contract_balance(), 1090);
+
+        let socialized = contract_client.write_off_debt(&borrower, &520, &20);
+
+        assert_eq!(socialized, 490);
+        assert_eq!(contract_client.get_total_reserves(), 0);
+        assert_eq!(contract_client.get_contract_balance(), 600);
+        assert_eq!(contract_client.get_available_balance(), 600);
+        assert_eq!(contract_client.get_exchange_rate(), 6_000_000);
+        assert_eq!(contract_client.get_user_positions(&borrower).liabilities, 0);
     }
 
     #[test]
-    fn withdraw() {
+    fn write_off_debt_cannot_empty_pool() {
         let e = Env::default();
-        e.mock_all_auths();
+        e.mock_all_auths_allowing_non_root_auth();
 
         let admin = Address::generate(&e);
         let token = e.register_stellar_asset_contract_v2(admin.clone());
@@ -556,26 +2189,38 @@ mod test {
         let currency = Currency {
             token_address: token.address(),
             ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
         };
 
         let user = Address::generate(&e);
         stellar_asset.mint(&user, &1000);
+        let borrower = Address::generate(&e);
 
         let contract_id = e.register(LoanPoolContract, ());
         let contract_client = LoanPoolContractClient::new(&e, &contract_id);
-        let amount: i128 = 100;
 
         contract_client.initialize(
             &Address::generate(&e),
             &currency,
             &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.deposit(&user, &1000);
+        contract_client.borrow(&borrower, &500);
+        // Everything that is not lent out is withdrawn.
+        contract_client.withdraw(&user, &500);
+
+        assert_eq!(
+            contract_client.try_write_off_debt(&borrower, &500, &0),
+            Err(Ok(Error::WriteOffExceedsBalance))
         );
 
-        let result: i128 = contract_client.deposit(&user, &amount);
-
-        assert_eq!(result, amount);
-
-        contract_client.withdraw(&user, &amount);
+        // With something left for the suppliers deposits can still be priced.
+        contract_client.repay(&borrower, &1, &0);
+        assert_eq!(contract_client.write_off_debt(&borrower, &499, &0), 499);
+        assert_eq!(contract_client.get_exchange_rate(), 20_000);
+        assert_eq!(contract_client.deposit(&user, &1), 500);
     }
 
     #[test]
@@ -589,10 +2234,13 @@ mod test {
         let currency = Currency {
             token_address: token.address(),
             ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
         };
 
         let user = Address::generate(&e);
         stellar_asset.mint(&user, &2000);
+        let depositor = Address::generate(&e);
+        stellar_asset.mint(&depositor, &2000);
 
         let contract_id = e.register(LoanPoolContract, ());
         let contract_client = LoanPoolContractClient::new(&e, &contract_id);
@@ -601,7 +2249,11 @@ mod test {
             &Address::generate(&e),
             &currency,
             &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
         );
+        contract_client.deposit(&depositor, &2000);
+        contract_client.borrow(&user, &980);
 
         let borrowed_amount = 1000_i128;
         let max_allowed_amount = 1050_i128;
@@ -612,6 +2264,123 @@ mod test {
             &max_allowed_amount,
             &unpaid_interest,
         );
+        assert_eq!(contract_client.get_user_positions(&user).liabilities, 0);
+    }
+
+    #[test]
+    fn deposit_before_repay_gets_no_earlier_interest() {
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let token_client = TokenClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let depositor = Address::generate(&e);
+        let late_depositor = Address::generate(&e);
+        let borrower = Address::generate(&e);
+        stellar_asset.mint(&depositor, &1000);
+        stellar_asset.mint(&late_depositor, &1000);
+        stellar_asset.mint(&borrower, &100);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.deposit(&depositor, &1000);
+        contract_client.borrow(&borrower, &500);
+
+        // A year at 6.4444% makes the debt 532, of which 29 is for the suppliers and 3 for reserves.
+        e.ledger().with_mut(|li| li.timestamp = 31_556_926);
+        assert_eq!(contract_client.get_exchange_rate(), 10_290_000);
+
+        // The late deposit pays for the interest accrued so far, and the repayment doesn't move
+        // the rate in its favor.
+        assert_eq!(contract_client.deposit(&late_depositor, &1000), 971);
+        contract_client.repay(&borrower, &532, &32);
+        assert_eq!(contract_client.get_total_reserves(), 3);
+        assert_eq!(contract_client.get_contract_balance(), 2029);
+
+        contract_client.withdraw_all(&late_depositor);
+        assert_eq!(token_client.balance(&late_depositor), 999);
+        contract_client.withdraw_all(&depositor);
+        assert_eq!(token_client.balance(&depositor), 1030);
+    }
+
+    #[test]
+    fn liabilities_of_two_loans() {
+        let e = Env::default();
+        e.mock_all_auths_allowing_non_root_auth();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let stellar_asset = StellarAssetClient::new(&e, &token.address());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let depositor = Address::generate(&e);
+        stellar_asset.mint(&depositor, &1000);
+        let borrower = Address::generate(&e);
+        stellar_asset.mint(&borrower, &1000);
+        let liquidator = Address::generate(&e);
+        stellar_asset.mint(&liquidator, &1000);
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        contract_client.deposit(&depositor, &1000);
+
+        // Two loans of the same borrower, with their interest tracked by the loan manager.
+        contract_client.borrow(&borrower, &500);
+        contract_client.borrow(&borrower, &300);
+        assert_eq!(
+            contract_client.get_user_positions(&borrower).liabilities,
+            800
+        );
+
+        // The interest is paid first and only the rest lowers the liabilities.
+        contract_client.repay(&borrower, &120, &20);
+        assert_eq!(
+            contract_client.get_user_positions(&borrower).liabilities,
+            700
+        );
+        contract_client.liquidate(&liquidator, &50, &10, &borrower);
+        assert_eq!(
+            contract_client.get_user_positions(&borrower).liabilities,
+            660
+        );
+
+        // Closing the first loan leaves the principal of the second one.
+        contract_client.repay_and_close(&borrower, &400, &450, &0);
+        assert_eq!(
+            contract_client.get_user_positions(&borrower).liabilities,
+            260
+        );
+        contract_client.repay_and_close(&borrower, &265, &300, &5);
+        assert_eq!(contract_client.get_user_positions(&borrower).liabilities, 0);
+        assert_eq!(contract_client.get_available_balance(), 1032);
     }
 
     #[test]
@@ -626,6 +2395,7 @@ mod test {
         let currency = Currency {
             token_address: token.address(),
             ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
         };
 
         let user = Address::generate(&e);
@@ -639,6 +2409,8 @@ mod test {
             &Address::generate(&e),
             &currency,
             &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
         );
 
         contract_client.deposit(&user, &amount);
@@ -656,6 +2428,7 @@ mod test {
         let currency = Currency {
             token_address: token.address(),
             ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
         };
 
         let user = Address::generate(&e);
@@ -669,6 +2442,8 @@ mod test {
             &Address::generate(&e),
             &currency,
             &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
         );
 
         let result: i128 = contract_client.deposit(&user, &amount);
@@ -689,6 +2464,7 @@ mod test {
         let currency = Currency {
             token_address: token.address(),
             ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
         };
 
         let user = Address::generate(&e);
@@ -705,6 +2481,8 @@ mod test {
             &Address::generate(&e),
             &currency,
             &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
         );
 
         let result: i128 = contract_client.deposit(&user, &amount);
@@ -735,6 +2513,7 @@ mod test {
         let currency = Currency {
             token_address: token.address(),
             ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
         };
 
         let user = Address::generate(&e);
@@ -751,6 +2530,8 @@ mod test {
             &Address::generate(&e),
             &currency,
             &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
         );
 
         let result: i128 = contract_client.deposit(&user, &amount);
@@ -789,6 +2570,7 @@ mod test {
         let currency = Currency {
             token_address: token.address(),
             ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
         };
 
         let user = Address::generate(&e);
@@ -805,6 +2587,8 @@ mod test {
             &Address::generate(&e),
             &currency,
             &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
         );
 
         let result: i128 = contract_client.deposit(&user, &amount);
@@ -820,4 +2604,139 @@ mod test {
         contract_client.add_interest_to_accrual();
         assert_eq!(10_644_440, contract_client.get_accrual());
     }
+
+    #[test]
+    fn set_interest_model() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        assert_eq!(contract_client.get_interest(), 200_000);
+
+        let model = InterestRateModel {
+            base_rate: 500_000,
+            kink_utilization: 8_000_000,
+            rate_at_kink: 1_500_000,
+            max_rate: 5_000_000,
+        };
+        contract_client.set_interest_model(&model);
+
+        assert_eq!(contract_client.get_interest_model(), model);
+        assert_eq!(contract_client.get_interest(), 500_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #14)")]
+    fn set_interest_model_with_decreasing_rates() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+
+        contract_client.set_interest_model(&InterestRateModel {
+            base_rate: 2_000_000,
+            kink_utilization: 9_000_000,
+            rate_at_kink: 1_000_000,
+            max_rate: 3_000_000,
+        });
+    }
+
+    #[test]
+    fn set_liquidation_params() {
+        let e = Env::default();
+        e.mock_all_auths();
+
+        let admin = Address::generate(&e);
+        let token = e.register_stellar_asset_contract_v2(admin.clone());
+        let currency = Currency {
+            token_address: token.address(),
+            ticker: Symbol::new(&e, "XLM"),
+            decimals: 7,
+        };
+
+        let contract_id = e.register(LoanPoolContract, ());
+        let contract_client = LoanPoolContractClient::new(&e, &contract_id);
+
+        contract_client.initialize(
+            &Address::generate(&e),
+            &currency,
+            &TEST_LIQUIDATION_THRESHOLD,
+            &test_interest_rate_model(),
+            &TEST_RESERVE_FACTOR,
+        );
+        assert_eq!(
+            contract_client.get_liquidation_params(),
+            LiquidationParams {
+                liquidation_threshold: TEST_LIQUIDATION_THRESHOLD,
+                close_factor: liquidation::DEFAULT_CLOSE_FACTOR,
+                liquidation_bonus: liquidation::DEFAULT_LIQUIDATION_BONUS,
+            }
+        );
+
+        let params = LiquidationParams {
+            liquidation_threshold: 7_500_000,
+            close_factor: 10_000_000,
+            liquidation_bonus: 1_000_000,
+        };
+        contract_client.set_liquidation_params(&params);
+        assert_eq!(contract_client.get_liquidation_params(), params);
+        assert_eq!(contract_client.get_collateral_factor(), 7_500_000);
+
+        // 95% threshold with a 10% bonus would seize more than the collateral is weighted at.
+        assert_eq!(
+            contract_client.try_set_liquidation_params(&LiquidationParams {
+                liquidation_threshold: 9_500_000,
+                ..params.clone()
+            }),
+            Err(Ok(Error::InvalidLiquidationParams))
+        );
+        assert_eq!(
+            contract_client.try_set_liquidation_params(&LiquidationParams {
+                close_factor: 0,
+                ..params.clone()
+            }),
+            Err(Ok(Error::InvalidLiquidationParams))
+        );
+        assert_eq!(
+            contract_client.try_set_liquidation_params(&LiquidationParams {
+                liquidation_bonus: liquidation::MAX_LIQUIDATION_BONUS + 1,
+                ..params
+            }),
+            Err(Ok(Error::InvalidLiquidationParams))
+        );
+    }
 }
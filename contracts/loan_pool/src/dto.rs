@@ -8,3 +8,24 @@ pub struct PoolState {
     pub total_balance_shares: i128,
     pub annual_interest_rate: i128,
 }
+
+/// How loans using the pool's token as collateral are liquidated, 10_000_000 = 100%.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LiquidationParams {
+    // Collateral value is weighted by this in the health factor. Loans under 1.0 can be liquidated.
+    pub liquidation_threshold: i128,
+    // Largest share of the loan that can be repaid in one liquidation
+    pub close_factor: i128,
+    // Extra collateral the liquidator gets on top of the repaid value
+    pub liquidation_bonus: i128,
+}
+
+/// Amount of tokens that can still be supplied or borrowed before hitting the pool's caps.
+/// `None` means that there is no cap.
+#[contracttype]
+#[derive(Debug, PartialEq)]
+pub struct CapHeadroom {
+    pub supply: Option<i128>,
+    pub borrow: Option<i128>,
+}
@@ -0,0 +1,291 @@
+use crate::dto::LiquidationParams;
+use crate::storage_types::{InterestRateModel, PoolStatus};
+use soroban_sdk::{contracttype, symbol_short, Address, Env, Symbol};
+
+/// Included in every pool event. Bumped when any payload below changes.
+pub const EVENT_VERSION: u32 = 1;
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DepositEvent {
+    pub version: u32,
+    pub user: Address,
+    pub amount: i128,
+    pub shares: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WithdrawEvent {
+    pub version: u32,
+    pub user: Address,
+    pub amount: i128,
+    pub shares: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CollateralEvent {
+    pub version: u32,
+    pub user: Address,
+    pub amount: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BorrowEvent {
+    pub version: u32,
+    pub user: Address,
+    pub amount: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RepayEvent {
+    pub version: u32,
+    pub user: Address,
+    // Tokens paid to the pool, including interest
+    pub amount: i128,
+    pub interest_paid: i128,
+    // Part of the interest that went to reserves
+    pub to_reserves: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LiquidateEvent {
+    pub version: u32,
+    pub liquidator: Address,
+    pub borrower: Address,
+    pub amount: i128,
+    pub interest_paid: i128,
+    pub to_reserves: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CollateralSeizedEvent {
+    pub version: u32,
+    pub liquidator: Address,
+    pub borrower: Address,
+    pub amount: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AccrualEvent {
+    pub version: u32,
+    pub accrual: i128,
+    pub timestamp: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReservesEvent {
+    pub version: u32,
+    pub amount: i128,
+    pub total_reserves: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FlashLoanEvent {
+    pub version: u32,
+    pub receiver: Address,
+    pub amount: i128,
+    pub fee: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BadDebtEvent {
+    pub version: u32,
+    pub user: Address,
+    pub principal_lost: i128,
+    pub covered_by_reserves: i128,
+    pub socialized: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PoolParam {
+    InterestRateModel(InterestRateModel),
+    ReserveFactor(i128),
+    Status(PoolStatus),
+    SupplyCap(Option<i128>),
+    BorrowCap(Option<i128>),
+    FlashLoanFee(i128),
+    LiquidationParams(LiquidationParams),
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParamChangedEvent {
+    pub version: u32,
+    pub param: PoolParam,
+}
+
+pub fn deposit(e: &Env, user: Address, amount: i128, shares: i128) {
+    let event = DepositEvent {
+        version: EVENT_VERSION,
+        user: user.clone(),
+        amount,
+        shares,
+    };
+    e.events().publish((symbol_short!("deposit"), user), event);
+}
+
+pub fn withdraw(e: &Env, user: Address, amount: i128, shares: i128) {
+    let event = WithdrawEvent {
+        version: EVENT_VERSION,
+        user: user.clone(),
+        amount,
+        shares,
+    };
+    e.events().publish((symbol_short!("withdraw"), user), event);
+}
+
+pub fn collateral_deposited(e: &Env, user: Address, amount: i128) {
+    let event = CollateralEvent {
+        version: EVENT_VERSION,
+        user: user.clone(),
+        amount,
+    };
+    e.events().publish(
+        (symbol_short!("collat"), symbol_short!("deposit"), user),
+        event,
+    );
+}
+
+pub fn collateral_withdrawn(e: &Env, user: Address, amount: i128) {
+    let event = CollateralEvent {
+        version: EVENT_VERSION,
+        user: user.clone(),
+        amount,
+    };
+    e.events().publish(
+        (symbol_short!("collat"), symbol_short!("withdraw"), user),
+        event,
+    );
+}
+
+pub fn collateral_seized(e: &Env, liquidator: Address, borrower: Address, amount: i128) {
+    let event = CollateralSeizedEvent {
+        version: EVENT_VERSION,
+        liquidator,
+        borrower: borrower.clone(),
+        amount,
+    };
+    e.events().publish(
+        (symbol_short!("collat"), symbol_short!("seized"), borrower),
+        event,
+    );
+}
+
+pub fn borrow(e: &Env, user: Address, amount: i128) {
+    let event = BorrowEvent {
+        version: EVENT_VERSION,
+        user: user.clone(),
+        amount,
+    };
+    e.events().publish((symbol_short!("borrow"), user), event);
+}
+
+pub fn repay(e: &Env, user: Address, amount: i128, interest_paid: i128, to_reserves: i128) {
+    let event = RepayEvent {
+        version: EVENT_VERSION,
+        user: user.clone(),
+        amount,
+        interest_paid,
+        to_reserves,
+    };
+    e.events().publish((symbol_short!("repay"), user), event);
+}
+
+pub fn liquidate(
+    e: &Env,
+    liquidator: Address,
+    borrower: Address,
+    amount: i128,
+    interest_paid: i128,
+    to_reserves: i128,
+) {
+    let event = LiquidateEvent {
+        version: EVENT_VERSION,
+        liquidator,
+        borrower: borrower.clone(),
+        amount,
+        interest_paid,
+        to_reserves,
+    };
+    e.events()
+        .publish((symbol_short!("liquidate"), borrower), event);
+}
+
+pub fn accrual_updated(e: &Env, accrual: i128, timestamp: u64) {
+    let event = AccrualEvent {
+        version: EVENT_VERSION,
+        accrual,
+        timestamp,
+    };
+    e.events().publish((symbol_short!("accrual"),), event);
+}
+
+pub fn reserves_accrued(e: &Env, amount: i128, total_reserves: i128) {
+    let event = ReservesEvent {
+        version: EVENT_VERSION,
+        amount,
+        total_reserves,
+    };
+    e.events()
+        .publish((symbol_short!("reserves"), symbol_short!("accrued")), event);
+}
+
+pub fn reserves_claimed(e: &Env, to: Address, amount: i128, total_reserves: i128) {
+    let event = ReservesEvent {
+        version: EVENT_VERSION,
+        amount,
+        total_reserves,
+    };
+    e.events().publish(
+        (symbol_short!("reserves"), symbol_short!("claimed"), to),
+        event,
+    );
+}
+
+pub fn flash_loan(e: &Env, receiver: Address, amount: i128, fee: i128) {
+    let event = FlashLoanEvent {
+        version: EVENT_VERSION,
+        receiver: receiver.clone(),
+        amount,
+        fee,
+    };
+    e.events()
+        .publish((symbol_short!("flash"), receiver), event);
+}
+
+pub fn bad_debt(
+    e: &Env,
+    user: Address,
+    principal_lost: i128,
+    covered_by_reserves: i128,
+    socialized: i128,
+) {
+    let event = BadDebtEvent {
+        version: EVENT_VERSION,
+        user: user.clone(),
+        principal_lost,
+        covered_by_reserves,
+        socialized,
+    };
+    e.events().publish((symbol_short!("bad_debt"), user), event);
+}
+
+pub fn param_changed(e: &Env, param: PoolParam) {
+    let event = ParamChangedEvent {
+        version: EVENT_VERSION,
+        param,
+    };
+    e.events()
+        .publish((Symbol::new(e, "param_changed"),), event);
+}
@@ -0,0 +1,13 @@
+use soroban_sdk::{contractclient, Address, Bytes, Env};
+
+/// Interface of contracts that receive flash loans from the pool.
+///
+/// `exec_op` is called after `amount` tokens have been sent to the receiver. The receiver has to
+/// transfer `amount + fee` back to the pool before returning. The pool has checked the `initiator`'s
+/// authorization, but any account can start a flash loan to any receiver, so the receiver should
+/// only act for initiators it trusts.
+#[allow(dead_code)] // Only the generated client is used by the pool.
+#[contractclient(name = "FlashLoanReceiverClient")]
+pub trait FlashLoanReceiver {
+    fn exec_op(e: Env, initiator: Address, token: Address, amount: i128, fee: i128, params: Bytes);
+}
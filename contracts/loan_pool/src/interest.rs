@@ -1,73 +1,187 @@
+use crate::caps;
 use crate::pool;
 use crate::pool::Error;
+use crate::storage_types::InterestRateModel;
 use soroban_sdk::Env;
 
-#[allow(dead_code)]
-// These are mockup numbers that should be set to each pool based on the token.
-pub const BASE_INTEREST_RATE: i128 = 200_000; // 2%
-pub const INTEREST_RATE_AT_PANIC: i128 = 1_000_000; // 10%
-pub const MAX_INTEREST_RATE: i128 = 3_000_000; // 30%
-pub const PANIC_BASE_RATE: i128 = -17_000_000;
-
-#[allow(dead_code, unused_variables)]
+const DECIMAL: i128 = 10_000_000;
+// Upper bound for any rate in the model, 1000% annually.
+pub const MAX_INTEREST_RATE: i128 = 100 * DECIMAL;
+// Utilization ratio has one more digit of precision than the rates.
+const UTILIZATION_DECIMAL: i128 = 10 * DECIMAL;
 
 pub fn get_interest(e: Env) -> Result<i128, Error> {
-    let interest_rate_multiplier = pool::read_interest_rate_multiplier(&e)?;
-    const PANIC_RATES_THRESHOLD: i128 = 90_000_000;
+    let model = pool::read_interest_rate_model(&e);
     let available = pool::read_available_balance(&e)?;
     let total = pool::read_total_balance(&e)?;
 
     if total > 0 {
-        let slope_before_panic = (INTEREST_RATE_AT_PANIC
-            .checked_sub(BASE_INTEREST_RATE)
-            .ok_or(Error::OverOrUnderFlow)?)
-        .checked_mul(10_000_000)
+        let utilization = (total.checked_sub(available).ok_or(Error::OverOrUnderFlow)?)
+            .checked_mul(UTILIZATION_DECIMAL)
+            .ok_or(Error::OverOrUnderFlow)?
+            .checked_div(total)
+            .ok_or(Error::OverOrUnderFlow)?;
+
+        get_interest_at(&model, utilization)
+    } else {
+        Ok(model.base_rate)
+    }
+}
+
+/// Accrual index with the interest since its last update added.
+pub fn projected_accrual(e: &Env) -> Result<i128, Error> {
+    const SECONDS_IN_YEAR: u64 = 31_556_926;
+
+    let current_timestamp = e.ledger().timestamp();
+    let accrual = pool::read_accrual(e)?;
+    let accrual_last_update = pool::read_accrual_last_updated(e)?;
+    let ledgers_since_update = current_timestamp
+        .checked_sub(accrual_last_update)
+        .ok_or(Error::OverOrUnderFlow)?;
+    let ledger_ratio: i128 = (i128::from(ledgers_since_update))
+        .checked_mul(DECIMAL)
         .ok_or(Error::OverOrUnderFlow)?
-        .checked_div(PANIC_RATES_THRESHOLD)
+        .checked_div(i128::from(SECONDS_IN_YEAR))
         .ok_or(Error::OverOrUnderFlow)?;
 
-        let slope_after_panic = (MAX_INTEREST_RATE
-            .checked_sub(INTEREST_RATE_AT_PANIC)
-            .ok_or(Error::OverOrUnderFlow)?)
-        .checked_mul(10_000_000)
+    let interest_rate: i128 = get_interest(e.clone())?;
+    let interest_amount_in_year: i128 = accrual
+        .checked_mul(interest_rate)
         .ok_or(Error::OverOrUnderFlow)?
-        .checked_div(
-            100_000_000_i128
-                .checked_sub(PANIC_RATES_THRESHOLD)
-                .ok_or(Error::OverOrUnderFlow)?,
-        )
+        .checked_div(DECIMAL)
+        .ok_or(Error::OverOrUnderFlow)?;
+    let interest_since_update: i128 = interest_amount_in_year
+        .checked_mul(ledger_ratio)
+        .ok_or(Error::OverOrUnderFlow)?
+        .checked_div(DECIMAL)
+        .ok_or(Error::OverOrUnderFlow)?;
+    accrual
+        .checked_add(interest_since_update)
+        .ok_or(Error::OverOrUnderFlow)
+}
+
+/// Debt of all loans with the interest up to now. It grows with the accrual index like the debt of
+/// each loan does.
+pub fn total_debt(e: &Env) -> Result<i128, Error> {
+    read_scaled_debt(e)?
+        .checked_mul(projected_accrual(e)?)
+        .ok_or(Error::OverOrUnderFlow)?
+        .checked_div(DECIMAL)
+        .ok_or(Error::OverOrUnderFlow)
+}
+
+/// Add borrowed tokens to the debt of all loans, or remove repaid and written off ones with a
+/// negative `amount`. Has to be called after the accrual and the balances are updated.
+pub fn change_total_debt(e: &Env, amount: i128) -> Result<(), Error> {
+    let scaled_amount = amount
+        .checked_mul(DECIMAL)
+        .ok_or(Error::OverOrUnderFlow)?
+        .checked_div(pool::read_accrual(e)?)
+        .ok_or(Error::OverOrUnderFlow)?;
+    let scaled_debt = read_scaled_debt(e)?
+        .checked_add(scaled_amount)
         .ok_or(Error::OverOrUnderFlow)?;
 
-        let ratio_of_balances = ((total.checked_sub(available).ok_or(Error::OverOrUnderFlow)?)
-            .checked_mul(100_000_000)
+    // Interest is repaid before the principal, so there is no debt left without principal. This
+    // also clears the rounding dust of the repaid loans.
+    if scaled_debt <= 0 || caps::total_borrowed(e)? <= 0 {
+        pool::write_scaled_debt(e, 0);
+    } else {
+        pool::write_scaled_debt(e, scaled_debt);
+    }
+    Ok(())
+}
+
+/// Pools deployed before the debt was tracked only know their principal, its interest is counted
+/// once it's repaid.
+fn read_scaled_debt(e: &Env) -> Result<i128, Error> {
+    if let Some(scaled_debt) = pool::read_scaled_debt(e) {
+        return Ok(scaled_debt);
+    }
+    caps::total_borrowed(e)?
+        .checked_mul(DECIMAL)
+        .ok_or(Error::OverOrUnderFlow)?
+        .checked_div(pool::read_accrual(e)?)
+        .ok_or(Error::OverOrUnderFlow)
+}
+
+/// Annual interest rate of the model at the given utilization ratio, scaled by
+/// `UTILIZATION_DECIMAL`. The slopes are rounded before they are applied.
+pub fn get_interest_at(model: &InterestRateModel, utilization: i128) -> Result<i128, Error> {
+    let kink = model
+        .kink_utilization
+        .checked_mul(UTILIZATION_DECIMAL / DECIMAL)
+        .ok_or(Error::OverOrUnderFlow)?;
+
+    if utilization < kink {
+        let slope = (model
+            .rate_at_kink
+            .checked_sub(model.base_rate)
             .ok_or(Error::OverOrUnderFlow)?)
-        .checked_div(total)
+        .checked_mul(DECIMAL)
+        .ok_or(Error::OverOrUnderFlow)?
+        .checked_div(kink)
         .ok_or(Error::OverOrUnderFlow)?;
 
-        if ratio_of_balances < PANIC_RATES_THRESHOLD {
-            Ok((slope_before_panic
-                .checked_mul(ratio_of_balances)
-                .ok_or(Error::OverOrUnderFlow)?)
-            .checked_div(10_000_000)
+        slope
+            .checked_mul(utilization)
             .ok_or(Error::OverOrUnderFlow)?
-            .checked_add(BASE_INTEREST_RATE)
+            .checked_div(DECIMAL)
             .ok_or(Error::OverOrUnderFlow)?
-            .checked_mul(interest_rate_multiplier)
+            .checked_add(model.base_rate)
+            .ok_or(Error::OverOrUnderFlow)
+    } else {
+        let slope = (model
+            .max_rate
+            .checked_sub(model.rate_at_kink)
             .ok_or(Error::OverOrUnderFlow)?)
-        } else {
-            Ok((slope_after_panic
-                .checked_mul(ratio_of_balances)
-                .ok_or(Error::OverOrUnderFlow)?)
-            .checked_div(10_000_000)
+        .checked_mul(DECIMAL)
+        .ok_or(Error::OverOrUnderFlow)?
+        .checked_div(
+            UTILIZATION_DECIMAL
+                .checked_sub(kink)
+                .ok_or(Error::OverOrUnderFlow)?,
+        )
+        .ok_or(Error::OverOrUnderFlow)?;
+        // Rate where the steep part of the curve would cross 0% utilization.
+        let base_rate_after_kink = model
+            .rate_at_kink
+            .checked_sub(
+                slope
+                    .checked_mul(kink)
+                    .ok_or(Error::OverOrUnderFlow)?
+                    .checked_div(DECIMAL)
+                    .ok_or(Error::OverOrUnderFlow)?,
+            )
+            .ok_or(Error::OverOrUnderFlow)?;
+
+        slope
+            .checked_mul(utilization)
             .ok_or(Error::OverOrUnderFlow)?
-            .checked_add(PANIC_BASE_RATE)
+            .checked_div(DECIMAL)
             .ok_or(Error::OverOrUnderFlow)?
-            .checked_mul(interest_rate_multiplier)
-            .ok_or(Error::OverOrUnderFlow)?)
-        }
-    } else {
-        Ok(BASE_INTEREST_RATE
-            .checked_mul(interest_rate_multiplier)
-            .ok_or(Error::OverOrUnderFlow)?)
+            .checked_add(base_rate_after_kink)
+            .ok_or(Error::OverOrUnderFlow)
+    }
+}
+
+/// Check that the rates are non-decreasing and bounded, and that the kink is strictly between 0% and 100%.
+pub fn validate_model(model: &InterestRateModel) -> Result<(), Error> {
+    let &InterestRateModel {
+        base_rate,
+        kink_utilization,
+        rate_at_kink,
+        max_rate,
+    } = model;
+
+    if kink_utilization <= 0
+        || kink_utilization >= DECIMAL
+        || base_rate < 0
+        || base_rate > rate_at_kink
+        || rate_at_kink > max_rate
+        || max_rate > MAX_INTEREST_RATE
+    {
+        return Err(Error::InvalidInterestRateModel);
     }
+    Ok(())
 }
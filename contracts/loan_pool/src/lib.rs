@@ -1,9 +1,17 @@
 #![no_std]
 #![allow(clippy::unused_unit)]
 
+mod allowance;
+mod caps;
 mod contract;
 mod dto;
+mod events;
+mod flash_loan;
 mod interest;
+mod liquidation;
+mod metadata;
 mod pool;
 mod positions;
+mod shares;
+mod status;
 mod storage_types;
@@ -0,0 +1,49 @@
+use crate::dto::LiquidationParams;
+use crate::pool::{self, Error};
+use soroban_sdk::Env;
+
+const DECIMAL: i128 = 10_000_000;
+// Half of a loan can be liquidated at once unless the pool has its own close factor.
+pub const DEFAULT_CLOSE_FACTOR: i128 = 5_000_000;
+// 500_000 = 5% extra collateral for the liquidator.
+pub const DEFAULT_LIQUIDATION_BONUS: i128 = 500_000;
+pub const MAX_LIQUIDATION_BONUS: i128 = 2_500_000;
+
+/// The threshold with the bonus added can't go over 100%, otherwise seizing the collateral of an
+/// unhealthy loan would leave it even less healthy.
+pub fn validate(params: &LiquidationParams) -> Result<(), Error> {
+    let LiquidationParams {
+        liquidation_threshold,
+        close_factor,
+        liquidation_bonus,
+    } = *params;
+    if liquidation_threshold <= 0
+        || close_factor <= 0
+        || close_factor > DECIMAL
+        || !(0..=MAX_LIQUIDATION_BONUS).contains(&liquidation_bonus)
+    {
+        return Err(Error::InvalidLiquidationParams);
+    }
+    let threshold_with_bonus = liquidation_threshold
+        .checked_mul(DECIMAL + liquidation_bonus)
+        .ok_or(Error::OverOrUnderFlow)?
+        / DECIMAL;
+    if threshold_with_bonus > DECIMAL {
+        return Err(Error::InvalidLiquidationParams);
+    }
+    Ok(())
+}
+
+pub fn read(e: &Env) -> Result<LiquidationParams, Error> {
+    Ok(LiquidationParams {
+        liquidation_threshold: pool::read_collateral_factor(e)?,
+        close_factor: pool::read_close_factor(e).unwrap_or(DEFAULT_CLOSE_FACTOR),
+        liquidation_bonus: pool::read_liquidation_bonus(e).unwrap_or(DEFAULT_LIQUIDATION_BONUS),
+    })
+}
+
+pub fn write(e: &Env, params: &LiquidationParams) {
+    pool::write_liquidation_threshold(e, params.liquidation_threshold);
+    pool::write_close_factor(e, params.close_factor);
+    pool::write_liquidation_bonus(e, params.liquidation_bonus);
+}
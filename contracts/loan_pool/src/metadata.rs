@@ -0,0 +1,34 @@
+use crate::pool::{self, Error};
+use soroban_sdk::{Env, String, SymbolStr, TryFromVal};
+
+const NAME_PREFIX: &[u8] = b"Laina ";
+const NAME_SUFFIX: &[u8] = b" Pool Share";
+const SYMBOL_PREFIX: &[u8] = b"l";
+
+// Longest possible name: prefix, 32 character ticker and suffix.
+const MAX_LEN: usize = 64;
+
+/// Name of the pool share token, e.g. "Laina XLM Pool Share".
+pub fn read_name(e: &Env) -> Result<String, Error> {
+    with_ticker(e, NAME_PREFIX, NAME_SUFFIX)
+}
+
+/// Symbol of the pool share token, e.g. "lXLM".
+pub fn read_symbol(e: &Env) -> Result<String, Error> {
+    with_ticker(e, SYMBOL_PREFIX, &[])
+}
+
+fn with_ticker(e: &Env, prefix: &[u8], suffix: &[u8]) -> Result<String, Error> {
+    let ticker = pool::read_currency(e)?.ticker;
+    let ticker =
+        SymbolStr::try_from_val(e, &ticker.to_symbol_val()).map_err(|_| Error::Currency)?;
+    let ticker: &[u8] = ticker.as_ref();
+
+    let mut buffer = [0u8; MAX_LEN];
+    let mut len = 0;
+    for part in [prefix, ticker, suffix] {
+        buffer[len..len + part.len()].copy_from_slice(part);
+        len += part.len();
+    }
+    Ok(String::from_bytes(e, &buffer[..len]))
+}
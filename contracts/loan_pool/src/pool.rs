@@ -1,10 +1,21 @@
-use crate::storage_types::{extend_persistent, PoolDataKey};
-use soroban_sdk::{contracterror, contracttype, Address, Env, Symbol};
+use crate::storage_types::{
+    extend_persistent, InterestRateModel, PoolConfig, PoolDataKey, PoolStatus,
+};
+use soroban_sdk::{contracterror, contracttype, token, Address, Env, Map, Symbol, TryFromVal, Val};
 
+#[derive(Clone)]
 #[contracttype]
 pub struct Currency {
     pub token_address: Address,
     pub ticker: Symbol,
+    pub decimals: u32,
+}
+
+// Currency as stored by pools deployed before it had decimals
+#[contracttype]
+pub(crate) struct CurrencyV1 {
+    pub token_address: Address,
+    pub ticker: Symbol,
 }
 
 #[contracterror]
@@ -23,7 +34,27 @@ pub enum Error {
     NegativeDeposit = 10,
     WithdrawOverBalance = 11,
     WithdrawIsNegative = 12,
-    InterestRateMultiplier = 13,
+    InvalidInterestRateModel = 14,
+    AmountTooSmall = 15,
+    NegativeAmount = 16,
+    InvalidExpirationLedger = 17,
+    InsufficientAllowance = 18,
+    InvalidReserveFactor = 20,
+    InsufficientReserves = 22,
+    PoolPaused = 24,
+    BorrowsFrozen = 25,
+    DepositsFrozen = 26,
+    InvalidStatusTransition = 27,
+    SupplyCapExceeded = 28,
+    BorrowCapExceeded = 29,
+    InvalidCap = 30,
+    InvalidFlashLoanFee = 31,
+    FlashLoanOverBalance = 32,
+    FlashLoanNotRepaid = 33,
+    InvalidLiquidationParams = 34,
+    WriteOffExceedsBalance = 35,
+    BurnNotSupported = 36,
+    UnownedAssets = 37,
 }
 
 pub fn write_loan_manager_addr(e: &Env, loan_manager_addr: Address) {
@@ -53,11 +84,47 @@ pub fn write_currency(e: &Env, currency: Currency) {
 pub fn read_currency(e: &Env) -> Result<Currency, Error> {
     let key = PoolDataKey::Currency;
 
-    if let Some(currency) = e.storage().persistent().get(&key) {
-        currency
-    } else {
-        Err(Error::Currency)
+    let fields: Map<Symbol, Val> = e.storage().persistent().get(&key).ok_or(Error::Currency)?;
+    if fields.contains_key(Symbol::new(e, "decimals")) {
+        return e.storage().persistent().get(&key).ok_or(Error::Currency);
     }
+
+    // The old layout, the decimals come from the token.
+    let CurrencyV1 {
+        token_address,
+        ticker,
+    } = e.storage().persistent().get(&key).ok_or(Error::Currency)?;
+    Ok(Currency {
+        decimals: token::Client::new(e, &token_address).decimals(),
+        token_address,
+        ticker,
+    })
+}
+
+/// Store the currency of a pool deployed before it had decimals in the current layout.
+pub fn migrate_currency(e: &Env) -> Result<(), Error> {
+    let currency = read_currency(e)?;
+    write_currency(e, currency);
+    Ok(())
+}
+
+pub fn write_pool_config(e: &Env, config: PoolConfig) {
+    let key = PoolDataKey::PoolConfig;
+
+    e.storage().persistent().set(&key, &config);
+    extend_persistent(e.clone(), &key);
+}
+
+/// Pools deployed before the statuses, or with an older config, are active.
+pub fn read_pool_config(e: &Env) -> PoolConfig {
+    let key = PoolDataKey::PoolConfig;
+
+    let fields: Option<Map<Symbol, Val>> = e.storage().persistent().get(&key);
+    let status = fields
+        .and_then(|fields| fields.get(Symbol::new(e, "status")))
+        .and_then(|status| PoolStatus::try_from_val(e, &status).ok())
+        .unwrap_or(PoolStatus::Active);
+    PoolConfig { status }
 }
 
 pub fn write_liquidation_threshold(e: &Env, threshold: i128) {
@@ -184,17 +251,158 @@ pub fn read_accrual_last_updated(e: &Env) -> Result<u64, Error> {
     }
 }
 
-pub fn change_interest_rate_multiplier(e: &Env, multiplier: i128) {
+pub fn write_interest_rate_model(e: &Env, model: InterestRateModel) {
+    let key = PoolDataKey::InterestRateModel;
+
+    e.storage().persistent().set(&key, &model);
+    extend_persistent(e.clone(), &key);
+}
+
+// Curve of the pools deployed before it was configurable: 2% at 0%, 10% at 90% and 30% at 100%
+// utilization.
+const LEGACY_INTEREST_RATE_MODEL: InterestRateModel = InterestRateModel {
+    base_rate: 200_000,
+    kink_utilization: 9_000_000,
+    rate_at_kink: 1_000_000,
+    max_rate: 3_000_000,
+};
+
+pub fn read_interest_rate_model(e: &Env) -> InterestRateModel {
+    e.storage()
+        .persistent()
+        .get(&PoolDataKey::InterestRateModel)
+        .unwrap_or(LEGACY_INTEREST_RATE_MODEL)
+}
+
+pub fn write_reserve_factor(e: &Env, reserve_factor: i128) {
+    let key = PoolDataKey::ReserveFactor;
+
+    e.storage().persistent().set(&key, &reserve_factor);
+    extend_persistent(e.clone(), &key);
+}
+
+// Pools deployed before the reserve factor was configurable kept 10% of the paid interest.
+const LEGACY_RESERVE_FACTOR: i128 = 1_000_000;
+
+pub fn read_reserve_factor(e: &Env) -> i128 {
+    e.storage()
+        .persistent()
+        .get(&PoolDataKey::ReserveFactor)
+        .unwrap_or(LEGACY_RESERVE_FACTOR)
+}
+
+pub fn write_scaled_debt(e: &Env, scaled_debt: i128) {
+    let key = PoolDataKey::ScaledDebt;
+
+    e.storage().persistent().set(&key, &scaled_debt);
+    extend_persistent(e.clone(), &key);
+}
+
+pub fn read_scaled_debt(e: &Env) -> Option<i128> {
+    e.storage().persistent().get(&PoolDataKey::ScaledDebt)
+}
+
+pub fn write_total_reserves(e: &Env, amount: i128) {
+    let key = PoolDataKey::TotalReserves;
+
+    e.storage().persistent().set(&key, &amount);
+    extend_persistent(e.clone(), &key);
+}
+
+pub fn read_total_reserves(e: &Env) -> i128 {
     e.storage()
         .persistent()
-        .set(&PoolDataKey::InterestRateMultiplier, &multiplier);
+        .get(&PoolDataKey::TotalReserves)
+        .unwrap_or(0)
 }
 
-pub fn read_interest_rate_multiplier(e: &Env) -> Result<i128, Error> {
+pub fn change_total_reserves(e: &Env, amount: i128) -> Result<i128, Error> {
+    let current_reserves = read_total_reserves(e);
+
+    let new_amount = amount
+        .checked_add(current_reserves)
+        .ok_or(Error::OverOrUnderFlow)?;
+    write_total_reserves(e, new_amount);
+    Ok(new_amount)
+}
+
+pub fn read_total_collateral(e: &Env) -> i128 {
     e.storage()
         .persistent()
-        .get(&PoolDataKey::InterestRateMultiplier)
-        .ok_or(Error::InterestRateMultiplier)
+        .get(&PoolDataKey::TotalCollateral)
+        .unwrap_or(0)
+}
+
+pub fn change_total_collateral(e: &Env, amount: i128) -> Result<i128, Error> {
+    let key = PoolDataKey::TotalCollateral;
+    let new_amount = amount
+        .checked_add(read_total_collateral(e))
+        .ok_or(Error::OverOrUnderFlow)?;
+
+    e.storage().persistent().set(&key, &new_amount);
+    extend_persistent(e.clone(), &key);
+    Ok(new_amount)
+}
+
+pub fn write_supply_cap(e: &Env, cap: Option<i128>) {
+    write_cap(e, PoolDataKey::SupplyCap, cap);
+}
+
+pub fn read_supply_cap(e: &Env) -> Option<i128> {
+    e.storage().persistent().get(&PoolDataKey::SupplyCap)
+}
+
+pub fn write_borrow_cap(e: &Env, cap: Option<i128>) {
+    write_cap(e, PoolDataKey::BorrowCap, cap);
+}
+
+pub fn read_borrow_cap(e: &Env) -> Option<i128> {
+    e.storage().persistent().get(&PoolDataKey::BorrowCap)
+}
+
+fn write_cap(e: &Env, key: PoolDataKey, cap: Option<i128>) {
+    if let Some(cap) = cap {
+        e.storage().persistent().set(&key, &cap);
+        extend_persistent(e.clone(), &key);
+    } else {
+        e.storage().persistent().remove(&key);
+    }
+}
+
+pub fn write_flash_loan_fee(e: &Env, fee: i128) {
+    let key = PoolDataKey::FlashLoanFee;
+
+    e.storage().persistent().set(&key, &fee);
+    extend_persistent(e.clone(), &key);
+}
+
+pub fn read_flash_loan_fee(e: &Env) -> i128 {
+    e.storage()
+        .persistent()
+        .get(&PoolDataKey::FlashLoanFee)
+        .unwrap_or(0)
+}
+
+pub fn write_close_factor(e: &Env, close_factor: i128) {
+    let key = PoolDataKey::CloseFactor;
+
+    e.storage().persistent().set(&key, &close_factor);
+    extend_persistent(e.clone(), &key);
+}
+
+pub fn read_close_factor(e: &Env) -> Option<i128> {
+    e.storage().persistent().get(&PoolDataKey::CloseFactor)
+}
+
+pub fn write_liquidation_bonus(e: &Env, bonus: i128) {
+    let key = PoolDataKey::LiquidationBonus;
+
+    e.storage().persistent().set(&key, &bonus);
+    extend_persistent(e.clone(), &key);
+}
+
+pub fn read_liquidation_bonus(e: &Env) -> Option<i128> {
+    e.storage().persistent().get(&PoolDataKey::LiquidationBonus)
 }
 
 pub fn read_collateral_factor(e: &Env) -> Result<i128, Error> {
@@ -0,0 +1,125 @@
+use crate::caps;
+use crate::contract::RESERVE_FACTOR_DECIMAL;
+use crate::events;
+use crate::interest;
+use crate::pool;
+use crate::pool::Error;
+use soroban_sdk::Env;
+
+pub(crate) const EXCHANGE_RATE_DECIMAL: i128 = 10_000_000;
+
+/// Tokens the shares are worth together: the balance and the suppliers' part of the interest that
+/// has accrued but isn't repaid yet. Counting the interest as it accrues keeps a deposit made
+/// right before a repayment from taking interest that was earned before it.
+pub fn total_assets(e: &Env) -> Result<i128, Error> {
+    let accrued_interest = interest::total_debt(e)?
+        .checked_sub(caps::total_borrowed(e)?)
+        .ok_or(Error::OverOrUnderFlow)?
+        .max(0);
+    // Rounded like the reserves' cut of a repayment, so repaying doesn't move the exchange rate.
+    let reserves_interest = accrued_interest
+        .checked_mul(pool::read_reserve_factor(e))
+        .ok_or(Error::OverOrUnderFlow)?
+        .checked_div(RESERVE_FACTOR_DECIMAL)
+        .ok_or(Error::OverOrUnderFlow)?;
+    let suppliers_interest = accrued_interest
+        .checked_sub(reserves_interest)
+        .ok_or(Error::OverOrUnderFlow)?;
+
+    pool::read_total_balance(e)?
+        .checked_add(suppliers_interest)
+        .ok_or(Error::OverOrUnderFlow)
+}
+
+/// Total shares and the tokens they are worth. Without shares tokens and shares are converted 1:1,
+/// which is only fair if no tokens are left over either.
+fn totals(e: &Env) -> Result<(i128, i128), Error> {
+    let total_shares = pool::read_total_shares(e)?;
+    let total_assets = total_assets(e)?;
+
+    if total_shares == 0 && total_assets != 0 {
+        return Err(Error::UnownedAssets);
+    }
+    Ok((total_shares, total_assets))
+}
+
+/// Move tokens left in a pool without shares, e.g. rounding remainders of the last withdrawals, to
+/// reserves. Nobody owns them, so they would otherwise go to the next depositor. Returns the amount
+/// moved.
+pub fn sweep_unowned_tokens(e: &Env) -> Result<i128, Error> {
+    if pool::read_total_shares(e)? != 0 {
+        return Ok(0);
+    }
+    let unowned = pool::read_total_balance(e)?;
+    if unowned <= 0 {
+        return Ok(0);
+    }
+    // Tokens that are lent out can't be moved until they are repaid.
+    if unowned > pool::read_available_balance(e)? {
+        return Err(Error::UnownedAssets);
+    }
+
+    let negated = unowned.checked_neg().ok_or(Error::OverOrUnderFlow)?;
+    pool::change_available_balance(e, negated)?;
+    pool::change_total_balance(e, negated)?;
+    let total_reserves = pool::change_total_reserves(e, unowned)?;
+    events::reserves_accrued(e, unowned, total_reserves);
+    Ok(unowned)
+}
+
+/// Amount of shares minted for `amount` tokens. Rounds down so that depositing never dilutes existing shareholders.
+pub fn tokens_to_shares_down(e: &Env, amount: i128) -> Result<i128, Error> {
+    let (total_shares, total_assets) = totals(e)?;
+
+    if total_shares == 0 {
+        return Ok(amount);
+    }
+    amount
+        .checked_mul(total_shares)
+        .ok_or(Error::OverOrUnderFlow)?
+        .checked_div(total_assets)
+        .ok_or(Error::OverOrUnderFlow)
+}
+
+/// Amount of shares burned for `amount` tokens. Rounds up so that withdrawing never dilutes remaining shareholders.
+pub fn tokens_to_shares_up(e: &Env, amount: i128) -> Result<i128, Error> {
+    let (total_shares, total_assets) = totals(e)?;
+
+    if total_shares == 0 {
+        return Ok(amount);
+    }
+    let numerator = amount
+        .checked_mul(total_shares)
+        .ok_or(Error::OverOrUnderFlow)?;
+    let shares = numerator
+        .checked_div(total_assets)
+        .ok_or(Error::OverOrUnderFlow)?;
+    if numerator % total_assets == 0 {
+        Ok(shares)
+    } else {
+        shares.checked_add(1).ok_or(Error::OverOrUnderFlow)
+    }
+}
+
+/// Amount of tokens the given shares are worth. Rounds down.
+pub fn shares_to_tokens(e: &Env, shares: i128) -> Result<i128, Error> {
+    let (total_shares, total_assets) = totals(e)?;
+
+    if total_shares == 0 {
+        return Ok(shares);
+    }
+    shares
+        .checked_mul(total_assets)
+        .ok_or(Error::OverOrUnderFlow)?
+        .checked_div(total_shares)
+        .ok_or(Error::OverOrUnderFlow)
+}
+
+/// Value of one share in tokens, 1.0 = 10_000_000. A pool without shares mints them 1:1 once any
+/// leftover tokens are swept.
+pub fn exchange_rate(e: &Env) -> Result<i128, Error> {
+    if pool::read_total_shares(e)? == 0 {
+        return Ok(EXCHANGE_RATE_DECIMAL);
+    }
+    shares_to_tokens(e, EXCHANGE_RATE_DECIMAL)
+}
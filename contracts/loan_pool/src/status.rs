@@ -0,0 +1,35 @@
+use crate::pool::{self, Error};
+use crate::storage_types::PoolStatus;
+use soroban_sdk::Env;
+
+/// Deposits of both lendable funds and collateral.
+pub fn require_deposits_allowed(e: &Env) -> Result<(), Error> {
+    match pool::read_pool_config(e).status {
+        PoolStatus::Active | PoolStatus::BorrowFrozen => Ok(()),
+        PoolStatus::DepositFrozen | PoolStatus::Deprecated => Err(Error::DepositsFrozen),
+        PoolStatus::Paused => Err(Error::PoolPaused),
+    }
+}
+
+pub fn require_borrows_allowed(e: &Env) -> Result<(), Error> {
+    match pool::read_pool_config(e).status {
+        PoolStatus::Active | PoolStatus::DepositFrozen => Ok(()),
+        PoolStatus::BorrowFrozen | PoolStatus::Deprecated => Err(Error::BorrowsFrozen),
+        PoolStatus::Paused => Err(Error::PoolPaused),
+    }
+}
+
+/// Withdrawals, repayments, liquidations and share transfers.
+pub fn require_not_paused(e: &Env) -> Result<(), Error> {
+    match pool::read_pool_config(e).status {
+        PoolStatus::Paused => Err(Error::PoolPaused),
+        _ => Ok(()),
+    }
+}
+
+pub fn validate_transition(from: PoolStatus, to: PoolStatus) -> Result<(), Error> {
+    if from == PoolStatus::Deprecated && to != PoolStatus::Deprecated {
+        return Err(Error::InvalidStatusTransition);
+    }
+    Ok(())
+}
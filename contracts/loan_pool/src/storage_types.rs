@@ -9,12 +9,28 @@ pub(crate) const POSITIONS_LIFETIME_THRESHOLD: u32 = POSITIONS_BUMP_AMOUNT - DAY
 
 /* Storage Types */
 
+// Operating state of the pool
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+#[repr(u32)]
+pub enum PoolStatus {
+    // Everything is allowed
+    Active = 0,
+    // No new borrows, everything else is allowed
+    BorrowFrozen = 1,
+    // No new deposits or collateral, everything else is allowed
+    DepositFrozen = 2,
+    // Only views and the loan manager's admin actions are allowed
+    Paused = 3,
+    // Pool is being wound down, only exits are allowed. There is no way back to the other states.
+    Deprecated = 4,
+}
+
 // Config for pool
 #[derive(Clone)]
 #[contracttype]
 pub struct PoolConfig {
-    pub oracle: Address, // The contract address for the price oracle
-    pub status: u32,     // Status of the pool
+    pub status: PoolStatus, // Status of the pool
 }
 
 #[derive(Clone)]
@@ -26,6 +42,35 @@ pub struct Positions {
     pub collateral: i128,
 }
 
+#[derive(Clone)]
+#[contracttype]
+pub struct AllowanceDataKey {
+    pub from: Address,
+    pub spender: Address,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct AllowanceValue {
+    pub amount: i128,
+    pub expiration_ledger: u32,
+}
+
+// Parameters of the pool's kinked interest rate curve.
+// All rates and the utilization ratio use 7 decimals, i.e. 10_000_000 is 100%.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct InterestRateModel {
+    // Annual interest rate when nothing is borrowed
+    pub base_rate: i128,
+    // Utilization ratio after which the rate follows the steeper slope
+    pub kink_utilization: i128,
+    // Annual interest rate at the kink
+    pub rate_at_kink: i128,
+    // Annual interest rate when the pool is fully utilized
+    pub max_rate: i128,
+}
+
 #[derive(Clone)]
 #[contracttype]
 pub enum PoolDataKey {
@@ -33,6 +78,8 @@ pub enum PoolDataKey {
     LoanManagerAddress,
     // Pool's token's address & ticker
     Currency,
+    // Pool's operating config
+    PoolConfig,
     // The threshold when a loan should liquidate, unit is one-millionth
     LiquidationThreshold,
     // Users positions in the pool
@@ -47,8 +94,28 @@ pub enum PoolDataKey {
     Accrual,
     // Last update ledger of accrual
     AccrualLastUpdate,
-    // Interest rate multiplier
-    InterestRateMultiplier,
+    // Parameters for calculating the interest rate
+    InterestRateModel,
+    // Share of paid interest that goes to the protocol
+    ReserveFactor,
+    // Protocol's tokens held in the pool, not part of the total balance
+    TotalReserves,
+    // Amount of pool shares a spender may transfer on behalf of the owner
+    Allowance(AllowanceDataKey),
+    // Total amount of tokens deposited as collateral
+    TotalCollateral,
+    // Maximum of deposits and collateral in the pool, no limit if not set
+    SupplyCap,
+    // Maximum of outstanding borrows from the pool, no limit if not set
+    BorrowCap,
+    // Fee charged on flash loans, zero if not set
+    FlashLoanFee,
+    // Share of a loan that can be liquidated at once
+    CloseFactor,
+    // Extra collateral given to liquidators
+    LiquidationBonus,
+    // Debt of all loans including interest, divided by the accrual index
+    ScaledDebt,
 }
 
 /* Persistent ttl bumper */
@@ -17,15 +17,65 @@ pub struct PriceData {
     pub timestamp: u64,
 }
 
+#[derive(Clone)]
+#[contracttype]
+enum DataKey {
+    Price(Asset),
+    Twap(Asset),
+    PriceAge,
+    Decimals,
+}
+
+// Price of 1.0 with the default decimals.
+const DEFAULT_PRICE: i128 = 100_000_000_000_000;
+
 #[contract]
 pub struct MockPriceOracleContract;
 
 #[contractimpl]
 impl MockPriceOracleContract {
-    pub fn lastprice(_e: Env, _asset: Asset) -> Option<PriceData> {
+    /// Decimals of the prices, 14 like in Reflector unless set otherwise.
+    pub fn decimals(e: Env) -> u32 {
+        e.storage().instance().get(&DataKey::Decimals).unwrap_or(14)
+    }
+
+    /// Price of the asset, 1.0 unless set otherwise. The timestamp is the current ledger time
+    /// minus the price age.
+    pub fn lastprice(e: Env, asset: Asset) -> Option<PriceData> {
+        let price = e
+            .storage()
+            .instance()
+            .get(&DataKey::Price(asset))
+            .unwrap_or(DEFAULT_PRICE);
+        let age: u64 = e.storage().instance().get(&DataKey::PriceAge).unwrap_or(0);
         Some(PriceData {
-            price: 1,
-            timestamp: 1,
+            price,
+            timestamp: e.ledger().timestamp().saturating_sub(age),
         })
     }
+
+    /// Time-weighted average price of the asset, the last price unless set otherwise.
+    pub fn twap(e: Env, asset: Asset, _records: u32) -> Option<i128> {
+        if let Some(twap) = e.storage().instance().get(&DataKey::Twap(asset.clone())) {
+            return Some(twap);
+        }
+        Self::lastprice(e, asset).map(|price_data| price_data.price)
+    }
+
+    pub fn set_price(e: Env, asset: Asset, price: i128) {
+        e.storage().instance().set(&DataKey::Price(asset), &price);
+    }
+
+    /// Make the returned prices look this many seconds old.
+    pub fn set_price_age(e: Env, age: u64) {
+        e.storage().instance().set(&DataKey::PriceAge, &age);
+    }
+
+    pub fn set_twap(e: Env, asset: Asset, price: i128) {
+        e.storage().instance().set(&DataKey::Twap(asset), &price);
+    }
+
+    pub fn set_decimals(e: Env, decimals: u32) {
+        e.storage().instance().set(&DataKey::Decimals, &decimals);
+    }
 }
@@ -24,6 +24,13 @@ const deploy = (wasm: string) => {
   );
 };
 
+/** Seconds before queued admin actions can be executed. Testnet uses the shortest delay the loan
+ * manager accepts, one day. */
+const TIMELOCK_DELAY = 86400;
+
+/** Address of the Reflector price oracle in Testnet. */
+const ORACLE_ADDRESS = 'CCYOZJCOPG34LLQQ7N24YXBM7LL62R7ONMZ3G6WZAAYPB5OYKOMJRN63';
+
 /** Deploy loan_manager contract as there will only be one for all the pools.
  * Loan_manager is used as a factory for the loan_pools.
  */
@@ -38,9 +45,22 @@ const deployLoanManager = () => {
 --source-account ${account} \
 --network testnet \
 -- initialize \
---admin ${account}`);
+--admin ${account} \
+--oracle ${ORACLE_ADDRESS} \
+--timelock_delay ${TIMELOCK_DELAY}`);
 };
 
+/** Interest rate curve for new pools. Rates and utilization use 7 decimals, 10000000 = 100%. */
+const INTEREST_RATE_MODEL = {
+  base_rate: 200000,
+  kink_utilization: 9000000,
+  rate_at_kink: 1000000,
+  max_rate: 3000000,
+};
+
+/** Share of paid interest kept as protocol reserves, 10000000 = 100%. */
+const RESERVE_FACTOR = 1000000;
+
 /** Deploy liquidity pools using the loan-manager as a factory contract */
 const deployLoanPools = () => {
   const wasmHash = readTextFile('./.stellar/contract-wasm-hash/loan_pool.txt');
@@ -58,6 +78,8 @@ const deployLoanPools = () => {
 --token_address ${tokenContractAddress} \
 --ticker ${ticker} \
 --liquidation_threshold 8000000 \
+--interest_rate_model '${JSON.stringify(INTEREST_RATE_MODEL)}' \
+--reserve_factor ${RESERVE_FACTOR} \
 | tr -d '"' > ./.stellar/contract-ids/${loanPoolName}.txt`,
     );
   });
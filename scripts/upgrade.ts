@@ -1,4 +1,5 @@
 import 'dotenv/config';
+import { execSync } from 'child_process';
 import {
   buildContracts,
   createContractBindings,
@@ -12,8 +13,33 @@ import {
 
 console.log('######################Updating contracts ########################');
 
-// Invoke the upgrade-action of loan manager. It will upgrade its pools as well.
-const upgradeContracts = () => {
+const actionIdFile = './.stellar/upgrade-action-id.txt';
+
+/** Seconds before queued admin actions can be executed, the same as in initialize.ts. */
+const TIMELOCK_DELAY = 86400;
+
+/** Address of the Reflector price oracle in Testnet, which the first deployment used. */
+const ORACLE_ADDRESS = 'CCYOZJCOPG34LLQQ7N24YXBM7LL62R7ONMZ3G6WZAAYPB5OYKOMJRN63';
+
+// Managers deployed before the timelock have no delay and can't queue actions. They only have the
+// old upgrade entrypoint, which upgrades the manager and its pools right away.
+const isMigrated = () => {
+  try {
+    exe(`stellar contract invoke \
+--id ${loanManagerAddress()} \
+--source-account ${process.env.SOROBAN_ACCOUNT} \
+--network testnet \
+-- \
+get_timelock_delay`);
+    return true;
+  } catch {
+    return false;
+  }
+};
+
+// Upgrade an unmigrated loan manager with its old upgrade entrypoint and write the settings the new
+// version needs. Later upgrades go through the timelock.
+const upgradeAndMigrate = () => {
   const managerWasmHash = readTextFile('./.stellar/contract-wasm-hash/loan_manager.txt');
   const poolWasmHash = readTextFile('./.stellar/contract-wasm-hash/loan_pool.txt');
 
@@ -25,13 +51,86 @@ const upgradeContracts = () => {
 upgrade \
 --new_manager_wasm_hash ${managerWasmHash} \
 --new_pool_wasm_hash ${poolWasmHash}`);
+
+  exe(`stellar contract invoke \
+--id ${loanManagerAddress()} \
+--source-account ${process.env.SOROBAN_ACCOUNT} \
+--network testnet \
+-- \
+migrate \
+--oracle ${ORACLE_ADDRESS} \
+--timelock_delay ${TIMELOCK_DELAY}`);
 };
 
-loadAccount();
-buildContracts();
-installContracts();
-upgradeContracts();
-createContractBindings();
-createContractImports();
+type PendingAction = { id: number; action: unknown };
+
+// Only one upgrade can be queued at a time. A newer build replaces the one that is still waiting.
+const cancelQueuedUpgrade = () => {
+  const output = execSync(`stellar contract invoke \
+--id ${loanManagerAddress()} \
+--source-account ${process.env.SOROBAN_ACCOUNT} \
+--network testnet \
+-- \
+get_pending_actions`).toString();
+  const pending: PendingAction[] = JSON.parse(output);
+
+  pending
+    .filter(({ action }) => typeof action === 'object' && action !== null && 'Upgrade' in action)
+    .forEach(({ id }) => {
+      exe(`stellar contract invoke \
+--id ${loanManagerAddress()} \
+--source-account ${process.env.SOROBAN_ACCOUNT} \
+--network testnet \
+-- \
+cancel_action \
+--id ${id}`);
+    });
+};
+
+// Queue the upgrade-action of loan manager. It will upgrade its pools as well once it is executed.
+const queueUpgrade = () => {
+  cancelQueuedUpgrade();
+
+  const managerWasmHash = readTextFile('./.stellar/contract-wasm-hash/loan_manager.txt');
+  const poolWasmHash = readTextFile('./.stellar/contract-wasm-hash/loan_pool.txt');
 
-console.log('\nUpgrade successful!');
+  exe(`stellar contract invoke \
+--id ${loanManagerAddress()} \
+--source-account ${process.env.SOROBAN_ACCOUNT} \
+--network testnet \
+-- \
+queue_action \
+--action '${JSON.stringify({ Upgrade: [managerWasmHash, poolWasmHash] })}' \
+| tr -d '"' > ${actionIdFile}`);
+};
+
+// Execute the queued upgrade. This only succeeds after the loan manager's timelock delay has passed.
+const executeUpgrade = () => {
+  exe(`stellar contract invoke \
+--id ${loanManagerAddress()} \
+--source-account ${process.env.SOROBAN_ACCOUNT} \
+--network testnet \
+-- \
+execute_action \
+--id ${readTextFile(actionIdFile)}`);
+};
+
+loadAccount();
+if (process.argv[2] === 'execute') {
+  executeUpgrade();
+  createContractBindings();
+  createContractImports();
+  console.log('\nUpgrade successful!');
+} else if (!isMigrated()) {
+  buildContracts();
+  installContracts();
+  upgradeAndMigrate();
+  createContractBindings();
+  createContractImports();
+  console.log('\nUpgrade and migration successful!');
+} else {
+  buildContracts();
+  installContracts();
+  queueUpgrade();
+  console.log(`\nUpgrade queued! Run "npm run upgrade -- execute" once the timelock delay has passed.`);
+}
@@ -33,7 +33,7 @@ const LoansView = ({ onClose, onRepay }: LoansViewProps) => {
           </thead>
           <tbody>
             {loans.map((loan) => (
-              <TableRow key={loan.borrowedTicker} loan={loan} onRepay={onRepay} />
+              <TableRow key={loan.id.toString()} loan={loan} onRepay={onRepay} />
             ))}
           </tbody>
         </table>
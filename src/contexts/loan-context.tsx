@@ -5,6 +5,7 @@ import { type PropsWithChildren, createContext, useCallback, useContext, useEffe
 import { CURRENCY_BINDINGS_BY_ADDRESS, type PoolAddress } from 'src/currency-bindings';
 
 export type Loan = {
+  id: bigint;
   borrower: string;
   borrowedAmount: bigint;
   borrowedTicker: SupportedCurrency;
@@ -28,25 +29,31 @@ export const LoansProvider = ({ children }: PropsWithChildren) => {
   const [loans, setLoans] = useState<Loan[] | null>(null);
   const { wallet } = useWallet();
 
-  // TODO: add support for having more than 1 loan
   const refetchLoans = useCallback(async () => {
     setLoans(null);
     if (!wallet) {
       return;
     }
     try {
-      const { result } = await loanManagerClient.get_loan({ addr: wallet.address });
-      setLoans([
-        {
-          borrower: result.borrower,
-          borrowedAmount: result.borrowed_amount,
-          borrowedTicker: CURRENCY_BINDINGS_BY_ADDRESS[result.borrowed_from as PoolAddress].ticker,
-          collateralAmount: result.collateral_amount,
-          collateralTicker: CURRENCY_BINDINGS_BY_ADDRESS[result.collateral_from as PoolAddress].ticker,
-          healthFactor: result.health_factor,
-          unpaidInterest: result.unpaid_interest,
-        },
-      ]);
+      const { result: loanIds } = await loanManagerClient.get_user_loans({ user: wallet.address });
+      const results = await Promise.all(loanIds.map((loan_id) => loanManagerClient.get_loan({ loan_id })));
+      setLoans(
+        results.map(({ result }) => {
+          const loan = result.unwrap();
+          // TODO: show loans backed by more than one collateral. The app only creates loans with one.
+          const [collateral] = loan.collateral;
+          return {
+            id: loan.id,
+            borrower: loan.borrower,
+            borrowedAmount: loan.borrowed_amount,
+            borrowedTicker: CURRENCY_BINDINGS_BY_ADDRESS[loan.borrowed_from as PoolAddress].ticker,
+            collateralAmount: collateral.amount,
+            collateralTicker: CURRENCY_BINDINGS_BY_ADDRESS[collateral.pool as PoolAddress].ticker,
+            healthFactor: loan.health_factor,
+            unpaidInterest: loan.unpaid_interest,
+          };
+        }),
+      );
     } catch (err) {
       setLoans([]);
     }
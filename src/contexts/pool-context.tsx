@@ -35,8 +35,8 @@ const fetchAllPrices = async (): Promise<PriceRecord> => {
   return { XLM, USDC, EURC };
 };
 
-const fetchPriceData = async (ticker: string): Promise<bigint> => {
-  const { result } = await loanManagerClient.get_price({ token: ticker });
+const fetchPriceData = async (ticker: SupportedCurrency): Promise<bigint> => {
+  const { result } = await loanManagerClient.get_price({ pool: CURRENCY_BINDINGS[ticker].contractId });
   if (result.isOk()) {
     const value = result.unwrap();
     return value;
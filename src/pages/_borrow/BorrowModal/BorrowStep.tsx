@@ -85,8 +85,7 @@ export const BorrowStep = ({ onClose, currency }: BorrowStepProps) => {
         user: wallet.address,
         borrowed: loanAmount,
         borrowed_from: loanCurrencyId,
-        collateral: collateralAmount,
-        collateral_from: collateralCurrencyId,
+        collateral: [{ pool: collateralCurrencyId, amount: collateralAmount }],
       });
       await tx.signAndSend({ signTransaction });
       setIsBorrowingSuccess(true);